@@ -1,7 +1,11 @@
 /// This file holds all the actions which can be performed
 /// by an oracle part of the oracle pool. These actions
 /// are implemented on the `OraclePool` struct.
-use crate::node_interface::sign_and_submit_transaction;
+use crate::datapoint_source::{DataPoint, SourceQuote};
+use crate::history::DatapointHistory;
+use crate::node_interface::{sign_transaction, submit_transaction};
+use crate::signer::SignerError;
+use crate::tx_tracker::{ActionKind, TX_TRACKER};
 use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
 
 use derive_more::From;
@@ -25,12 +29,18 @@ pub struct RefreshAction {
 #[derive(Debug)]
 pub struct PublishDataPointAction {
     pub tx: UnsignedTransaction,
+    /// Pool epoch the datapoint is published in
+    pub epoch_counter: u32,
+    pub datapoint: DataPoint,
+    pub source_quotes: Vec<SourceQuote>,
 }
 
 #[derive(Error, Debug, From)]
 pub enum ActionExecError {
     #[error("node error: {0}")]
     NodeError(NodeError),
+    #[error("signer error: {0}")]
+    Signer(SignerError),
 }
 
 pub fn execute_action(action: PoolAction) -> Result<(), ActionExecError> {
@@ -52,13 +62,35 @@ pub fn execute_action(action: PoolAction) -> Result<(), ActionExecError> {
 }
 
 fn execute_refresh_action(action: RefreshAction) -> Result<(), ActionExecError> {
-    let tx_id = sign_and_submit_transaction(&action.tx)?;
+    let tx_id = sign_submit_and_track(&action.tx, ActionKind::Refresh)?;
     log::info!("Refresh tx published successfully, tx id: {}", tx_id);
     Ok(())
 }
 
 fn execute_publish_datapoint_action(action: PublishDataPointAction) -> Result<(), ActionExecError> {
-    let tx_id = sign_and_submit_transaction(&action.tx)?;
+    let tx_id = sign_submit_and_track(&action.tx, ActionKind::PublishDatapoint)?;
     log::info!("Datapoint published successfully, tx id: {}", tx_id);
+    if let Err(e) = DatapointHistory::local().record_posted_datapoint(
+        action.epoch_counter,
+        action.datapoint,
+        action.source_quotes,
+        tx_id,
+    ) {
+        log::error!("Failed to record datapoint in the datapoint history: {}", e);
+    }
     Ok(())
 }
+
+/// Signs and submits the tx and hands it over to the tx tracker. Returns the tx id.
+fn sign_submit_and_track(
+    tx: &UnsignedTransaction,
+    kind: ActionKind,
+) -> Result<String, ActionExecError> {
+    let signed_tx = sign_transaction(tx, kind.into())?;
+    // node interface returns the tx id as a JSON string
+    let tx_id = submit_transaction(&signed_tx)?.replace('"', "");
+    if let Err(e) = TX_TRACKER.record(tx_id.clone(), kind, signed_tx) {
+        log::error!("Failed to track {:?} tx {}: {}", kind, tx_id, e);
+    }
+    Ok(tx_id)
+}
@@ -1,10 +1,16 @@
 use std::convert::From;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use crate::node_interface::current_block_height;
-use crate::oracle_config::{get_core_api_port, get_node_ip, get_node_port, ORACLE_CONFIG};
+use crate::node_pool::{LAST_NODE_HEALTH, NODE_POOL};
+use crate::oracle_config::{get_core_api_port, ORACLE_CONFIG};
 use crate::oracle_state::LocalDatapointState::{Collected, Posted};
 use crate::oracle_state::{OraclePool, StageError};
+use crate::pool_commands::deviation_guard::DeviationGuard;
+use crate::scheduler::Shutdown;
+use crate::state::{reward_warning_threshold, LAST_DECISION};
+use crate::tx_tracker::{TrackedTx, TX_TRACKER};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
@@ -28,11 +34,15 @@ async fn oracle_info() -> impl IntoResponse {
 }
 
 /// Status of the oracle
-async fn oracle_status() -> Result<Json<serde_json::Value>, ApiError> {
+async fn oracle_status(
+    deviation_guard: Arc<DeviationGuard>,
+) -> Result<Json<serde_json::Value>, ApiError> {
     let op = OraclePool::new().unwrap();
     let live_epoch = task::spawn_blocking(move || op.get_live_epoch_state())
         .await
         .unwrap()?;
+    // state, command and reason of the latest main loop iteration
+    let last_decision = LAST_DECISION.lock().unwrap().clone();
     if let Some(local_datapoint_box_state) = live_epoch.local_datapoint_box_state {
         let json = match local_datapoint_box_state {
             Collected { height } => json!( {
@@ -47,10 +57,14 @@ async fn oracle_status() -> Result<Json<serde_json::Value>, ApiError> {
         };
         Ok(Json(json!({
                 "local_datapoint_box_state": json,
+                "deviation_guard": deviation_guard.status(),
+                "last_decision": last_decision,
         })))
     } else {
         Ok(Json(json!({
                 "local_datapoint_box_state": "No local datapoint box",
+                "deviation_guard": deviation_guard.status(),
+                "last_decision": last_decision,
         })))
     }
 }
@@ -72,10 +86,19 @@ async fn pool_info() -> impl IntoResponse {
     }))
 }
 
-/// Basic information about node the oracle core is using
+/// Basic information about node the oracle core is using, and the health of each node
 async fn node_info() -> impl IntoResponse {
+    let nodes = NODE_POOL.status();
+    let node_url = nodes
+        .iter()
+        .find(|node| node.selected)
+        .map(|node| node.url.clone());
+    // sync status and peers of the node as of the latest main loop iteration
+    let health = LAST_NODE_HEALTH.lock().unwrap().clone();
     Json(json!({
-        "node_url": "http://".to_string() + &get_node_ip() + ":" + &get_node_port(),
+        "node_url": node_url,
+        "health": health,
+        "nodes": nodes,
     }))
 }
 
@@ -85,10 +108,20 @@ async fn pool_status() -> Result<Json<serde_json::Value>, ApiError> {
     let live_epoch = task::spawn_blocking(move || op.get_live_epoch_state())
         .await
         .unwrap()?;
+    let min_data_points = ORACLE_CONFIG
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters()
+        .min_data_points() as u32;
+    let reward_warning_threshold =
+        reward_warning_threshold(ORACLE_CONFIG.pool_reward_warning_threshold, min_data_points);
     Ok(Json(json!({
             "latest_pool_datapoint": live_epoch.latest_pool_datapoint,
             "latest_pool_box_height": live_epoch.latest_pool_box_height,
             "pool_box_epoch_id" : live_epoch.pool_box_epoch_id,
+            "pool_reward_tokens": live_epoch.pool_reward_tokens,
+            "reward_warning_threshold": reward_warning_threshold,
+            "pool_rewards_low": live_epoch.pool_reward_tokens < reward_warning_threshold,
     })))
 }
 
@@ -98,6 +131,19 @@ async fn block_height() -> Result<impl IntoResponse, ApiError> {
     Ok(format!("{}", current_height))
 }
 
+/// Transactions submitted by this oracle and their confirmation status
+async fn tracked_txs() -> impl IntoResponse {
+    let txs: Vec<TrackedTx> = TX_TRACKER
+        .list()
+        .into_iter()
+        .map(|tx| TrackedTx {
+            signed_tx: None,
+            ..tx
+        })
+        .collect();
+    Json(json!(txs))
+}
+
 /// Whether the Core requires the Connector to repost a new Datapoint
 async fn require_datapoint_repost(repost_receiver: Receiver<bool>) -> impl IntoResponse {
     let mut response_text = "false".to_string();
@@ -107,15 +153,20 @@ async fn require_datapoint_repost(repost_receiver: Receiver<bool>) -> impl IntoR
     response_text
 }
 
-pub async fn start_rest_server(repost_receiver: Receiver<bool>) {
+pub async fn start_rest_server(
+    repost_receiver: Receiver<bool>,
+    deviation_guard: Arc<DeviationGuard>,
+    shutdown: Shutdown,
+) {
     let app = Router::new()
         .route("/", get(root))
         .route("/oracleInfo", get(oracle_info))
-        .route("/oracleStatus", get(oracle_status))
+        .route("/oracleStatus", get(|| oracle_status(deviation_guard)))
         .route("/poolInfo", get(pool_info))
         .route("/nodeInfo", get(node_info))
         .route("/poolStatus", get(pool_status))
         .route("/blockHeight", get(block_height))
+        .route("/trackedTxs", get(tracked_txs))
         .route(
             "/requireDatapointRepost",
             get(|| require_datapoint_repost(repost_receiver)),
@@ -128,6 +179,7 @@ pub async fn start_rest_server(repost_receiver: Receiver<bool>) {
     let addr = SocketAddr::from(([0, 0, 0, 0], get_core_api_port().parse().unwrap()));
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(async move { shutdown.requested().await })
         .await
         .unwrap();
 }
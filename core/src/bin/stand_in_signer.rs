@@ -0,0 +1,140 @@
+//! Stand-in for an external signing service, to test the `remote` tx signer against. Signs the
+//! requests that carry the token from `STAND_IN_SIGNER_TOKEN` and pass the `SignPolicy` read from
+//! the JSON file at `STAND_IN_SIGNER_POLICY`, with the hex encoded DLog secret from
+//! `STAND_IN_SIGNER_SECRET`. Change may go back to the address of the secret. Not meant for real
+//! keys.
+//!
+//! Usage: stand_in_signer [listen address, default 127.0.0.1:9020] [mainnet|testnet]
+
+#[path = "../signer/remote_protocol.rs"]
+mod remote_protocol;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use ergo_lib::ergotree_ir::chain::address::{NetworkAddress, NetworkPrefix};
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::wallet::secret_key::SecretKey;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::Wallet;
+use subtle::ConstantTimeEq;
+
+use remote_protocol::{
+    SignErrorResponse, SignPolicy, SignRequest, SignResponse, PROTOCOL_VERSION, SIGN_PATH,
+};
+
+struct Signer {
+    secret: SecretKey,
+    /// ErgoTree of the secret's address
+    change_tree: ErgoTree,
+    token: String,
+    policy: SignPolicy,
+    network: NetworkPrefix,
+}
+
+type Refusal = (StatusCode, Json<SignErrorResponse>);
+
+fn refuse(status: StatusCode, error: impl ToString) -> Refusal {
+    (
+        status,
+        Json(SignErrorResponse {
+            error: error.to_string(),
+        }),
+    )
+}
+
+async fn sign(
+    State(signer): State<Arc<Signer>>,
+    headers: HeaderMap,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignResponse>, Refusal> {
+    let expected_auth = format!("Bearer {}", signer.token);
+    let auth = headers
+        .get(AUTHORIZATION)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+    // compared in constant time, so that the response time does not leak the token
+    if !bool::from(auth.ct_eq(expected_auth.as_bytes())) {
+        return Err(refuse(StatusCode::UNAUTHORIZED, "invalid auth token"));
+    }
+    if request.version != PROTOCOL_VERSION {
+        return Err(refuse(
+            StatusCode::BAD_REQUEST,
+            format!("unsupported protocol version {}", request.version),
+        ));
+    }
+    signer
+        .policy
+        .check(
+            request.action,
+            request.unsigned_tx.output_candidates.as_vec(),
+            &signer.change_tree,
+            signer.network,
+        )
+        .map_err(|e| refuse(StatusCode::FORBIDDEN, e))?;
+    let state_context = request
+        .state_context()
+        .map_err(|e| refuse(StatusCode::BAD_REQUEST, e))?;
+    let action = request.action;
+    let tx_context =
+        TransactionContext::new(request.unsigned_tx, request.inputs, request.data_inputs)
+            .map_err(|e| refuse(StatusCode::BAD_REQUEST, e))?;
+    let signed_tx = Wallet::from_secrets(vec![signer.secret.clone()])
+        .sign_transaction(tx_context, &state_context, None)
+        .map_err(|e| refuse(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    println!("Signed {:?} tx {:?}", action, signed_tx.id());
+    Ok(Json(SignResponse { signed_tx }))
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let addr: SocketAddr = args
+        .next()
+        .unwrap_or_else(|| "127.0.0.1:9020".to_string())
+        .parse()
+        .expect("invalid listen address");
+    let network = match args.next().as_deref() {
+        Some("testnet") => NetworkPrefix::Testnet,
+        Some(_) | None => NetworkPrefix::Mainnet,
+    };
+    let secret = std::env::var("STAND_IN_SIGNER_SECRET")
+        .ok()
+        .and_then(|hex| base16::decode(hex.trim()).ok())
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| SecretKey::dlog_from_bytes(&bytes))
+        .expect("STAND_IN_SIGNER_SECRET must hold a hex encoded DLog secret");
+    let token = std::env::var("STAND_IN_SIGNER_TOKEN").expect("STAND_IN_SIGNER_TOKEN must be set");
+    let policy: SignPolicy = std::env::var("STAND_IN_SIGNER_POLICY")
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .expect("STAND_IN_SIGNER_POLICY must be the path of a JSON sign policy");
+    let change_tree = secret
+        .get_address_from_public_image()
+        .script()
+        .expect("cannot build the ErgoTree of the secret's address");
+    println!(
+        "Signing for {} on {}",
+        NetworkAddress::new(network, &secret.get_address_from_public_image()).to_base58(),
+        addr
+    );
+    let app = Router::new()
+        .route(SIGN_PATH, post(sign))
+        .with_state(Arc::new(Signer {
+            secret,
+            change_tree,
+            token,
+            policy,
+            network,
+        }));
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
@@ -1,10 +1,13 @@
 use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
 
 pub mod bootstrap;
+pub mod encrypt_signer_key;
 pub mod extract_reward_tokens;
 pub mod prepare_update;
 pub mod print_conf;
+pub mod print_history;
 pub mod print_reward_tokens;
+pub mod simulate_refresh;
 pub mod transfer_oracle_token;
 pub mod update_pool;
 pub mod vote_update_pool;
@@ -42,16 +42,19 @@ use crate::{
             UpdateContract, UpdateContractError, UpdateContractInputs, UpdateContractParameters,
         },
     },
-    datapoint_source::PredefinedDataPointSource,
+    datapoint_source::{ExternalScriptSpec, PredefinedDataPointSource},
     node_interface::{assert_wallet_unlocked, SignTransaction, SubmitTransaction},
     oracle_config::{OracleConfig, TokenIds},
     oracle_config::{OracleConfigError, BASE_FEE},
     serde::BootstrapConfigSerde,
+    signer::{SignAction, SignerError, TxSigner, TxSignerConfig},
     spec_token::{
         BallotTokenId, OracleTokenId, PoolTokenId, RefreshTokenId, RewardTokenId, SpecToken,
         TokenIdKind, UpdateTokenId,
     },
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{
+        tracked_address, AddressWallet, WalletDataError, WalletDataSource, WalletSourceConfig,
+    },
 };
 
 /// Loads bootstrap configuration file and performs the chain-transactions for minting of tokens and
@@ -63,24 +66,49 @@ pub fn bootstrap(config_file_name: String) -> Result<(), BootstrapError> {
 
     // We can't call any functions from the `crate::node_interface` module because we don't have an
     // `oracle_config.yaml` file to work from here.
-    let node = NodeInterface::new(
-        &config.node_api_key,
-        &config.node_ip,
-        &config.node_port.to_string(),
-    );
-    assert_wallet_unlocked(&node);
-    let change_address_str = node
-        .wallet_status()?
-        .change_address
-        .ok_or(BootstrapError::NoChangeAddressSetInNode)?;
+    let new_node = || {
+        NodeInterface::new(
+            &config.node_api_key,
+            &config.node_ip,
+            &config.node_port.to_string(),
+        )
+    };
+    let node = new_node();
+    let tx_signer = TxSigner::from_config(
+        &config.tx_signer.clone().unwrap_or_default(),
+        new_node(),
+        &config.oracle_address,
+    )?;
+    if let TxSigner::Node(node) = &tx_signer {
+        assert_wallet_unlocked(node);
+    }
+    let tracked_address = tracked_address(
+        &config.wallet_source.clone().unwrap_or_default(),
+        &config.oracle_address,
+    )?;
+    let change_address_str = match &tracked_address {
+        Some(address) => address.to_base58(),
+        None => node
+            .wallet_status()?
+            .change_address
+            .ok_or(BootstrapError::NoChangeAddressSetInNode)?,
+    };
     debug!("Change address: {}", change_address_str);
+    let wallet: Box<dyn WalletDataSource> = match tracked_address {
+        Some(address) => Box::new(AddressWallet {
+            address,
+            source: new_node(),
+        }),
+        None => Box::new(new_node()),
+    };
 
     let change_address = AddressEncoder::unchecked_parse_address_from_str(&change_address_str)?;
     let erg_value_per_box = config.oracle_contract_parameters.min_storage_rent;
+    let action_signer = tx_signer.for_action(SignAction::Bootstrap);
     let input = BootstrapInput {
         config,
-        wallet: &node as &dyn WalletDataSource,
-        tx_signer: &node as &dyn SignTransaction,
+        wallet: wallet.as_ref(),
+        tx_signer: &action_signer,
         submit_tx: &node as &dyn SubmitTransaction,
         tx_fee: *BASE_FEE,
         erg_value_per_box,
@@ -548,9 +576,13 @@ pub struct BootstrapConfig {
     pub node_api_key: String,
     pub core_api_port: u16,
     pub data_point_source: Option<PredefinedDataPointSource>,
-    pub data_point_source_custom_script: Option<String>,
+    pub data_point_source_custom_script: Option<ExternalScriptSpec>,
     pub oracle_address: NetworkAddress,
     pub base_fee: u64,
+    /// Signs the bootstrap txs, and is carried over to the oracle config
+    pub tx_signer: Option<TxSignerConfig>,
+    /// Pays for the bootstrap txs, and is carried over to the oracle config
+    pub wallet_source: Option<WalletSourceConfig>,
 }
 
 impl Default for BootstrapConfig {
@@ -602,6 +634,8 @@ impl Default for BootstrapConfig {
             data_point_source: Some(PredefinedDataPointSource::NanoErgUsd),
             data_point_source_custom_script: None,
             base_fee: *tx_builder::SUGGESTED_TX_FEE().as_u64(),
+            tx_signer: None,
+            wallet_source: None,
         }
     }
 }
@@ -637,6 +671,8 @@ pub enum BootstrapError {
     ErgoBoxCandidateBuilder(ErgoBoxCandidateBuilderError),
     #[error("node error: {0}")]
     Node(NodeError),
+    #[error("signer error: {0}")]
+    Signer(SignerError),
     #[error("box selector error: {0}")]
     BoxSelector(BoxSelectorError),
     #[error("box value error: {0}")]
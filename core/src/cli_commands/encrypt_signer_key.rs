@@ -0,0 +1,24 @@
+use std::io::BufRead;
+
+use crate::signer::{write_key_file, SecretKind, SignerError, PASSWORD_ENV_VAR};
+
+/// Reads the secret from stdin (without echoing it on a terminal) and writes it encrypted with the
+/// password from the env var
+pub fn encrypt_signer_key(key_file: &str, kind: SecretKind) -> Result<(), SignerError> {
+    let password = std::env::var(PASSWORD_ENV_VAR).map_err(|_| SignerError::MissingPassword)?;
+    let prompt = match kind {
+        SecretKind::Mnemonic => "Enter the mnemonic: ",
+        SecretKind::Dlog => "Enter the hex encoded secret: ",
+    };
+    let secret = if atty::is(atty::Stream::Stdin) {
+        rpassword::prompt_password(prompt)?
+    } else {
+        // piped in
+        let mut secret = String::new();
+        std::io::stdin().lock().read_line(&mut secret)?;
+        secret
+    };
+    write_key_file(key_file, kind, secret.trim(), &password)?;
+    println!("Key file written to {}", key_file);
+    Ok(())
+}
@@ -27,11 +27,12 @@ use crate::{
         make_collected_oracle_box_candidate, make_oracle_box_candidate, OracleBox, OracleBoxWrapper,
     },
     cli_commands::ergo_explorer_transaction_link,
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    node_interface::{current_block_height, sign_and_submit_transaction},
     oracle_config::BASE_FEE,
     oracle_state::{LocalDatapointBoxSource, StageError},
+    signer::{SignAction, SignerError},
     spec_token::SpecToken,
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{change_address, WalletDataError, WalletDataSource},
 };
 
 #[derive(Debug, Error, From)]
@@ -46,6 +47,8 @@ pub enum ExtractRewardTokensActionError {
     StageError(StageError),
     #[error("node error: {0}")]
     Node(NodeError),
+    #[error("signer error: {0}")]
+    Signer(SignerError),
     #[error("box selector error: {0}")]
     BoxSelector(BoxSelectorError),
     #[error("Sigma parsing error: {0}")]
@@ -73,9 +76,8 @@ pub fn extract_reward_tokens(
         AddressEncoder::unchecked_parse_network_address_from_str(&rewards_destination_str)?;
     let network_prefix = rewards_destination.network();
 
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(ExtractRewardTokensActionError::NoChangeAddressSetInNode)?;
+    let change_address_str =
+        change_address()?.ok_or(ExtractRewardTokensActionError::NoChangeAddressSetInNode)?;
 
     let change_address =
         AddressEncoder::new(network_prefix).parse_address_from_str(&change_address_str)?;
@@ -94,7 +96,7 @@ pub fn extract_reward_tokens(
     let mut input = String::new();
     std::io::stdin().read_line(&mut input)?;
     if input.trim() == "YES" {
-        let tx_id_str = sign_and_submit_transaction(&unsigned_tx)?;
+        let tx_id_str = sign_and_submit_transaction(&unsigned_tx, SignAction::ExtractRewardTokens)?;
         println!(
             "Transaction made. Check status here: {}",
             ergo_explorer_transaction_link(tx_id_str, network_prefix)
@@ -51,14 +51,15 @@ use crate::{
             UpdateContractParameters,
         },
     },
-    node_interface::{new_node_interface, SignTransaction, SubmitTransaction},
+    node_interface::{new_node_interface, new_tx_signer, SignTransaction, SubmitTransaction},
     oracle_config::{OracleConfig, BASE_FEE, ORACLE_CONFIG},
     oracle_state::{OraclePool, StageDataSource},
     serde::{OracleConfigSerde, SerdeConversionError, UpdateBootstrapConfigSerde},
+    signer::{SignAction, SignerError},
     spec_token::{
         BallotTokenId, OracleTokenId, RefreshTokenId, RewardTokenId, TokenIdKind, UpdateTokenId,
     },
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{change_address, WalletData, WalletDataError, WalletDataSource},
 };
 
 use super::bootstrap::{NftMintDetails, TokenMintDetails};
@@ -86,15 +87,15 @@ pub fn prepare_update(config_file_name: String) -> Result<(), PrepareUpdateError
 
     let node_interface = new_node_interface();
     let change_address = AddressEncoder::unchecked_parse_address_from_str(
-        &node_interface
-            .wallet_status()?
-            .change_address
-            .ok_or(PrepareUpdateError::NoChangeAddressSetInNode)?,
+        &change_address()?.ok_or(PrepareUpdateError::NoChangeAddressSetInNode)?,
     )?;
     let config = UpdateBootstrapConfig::try_from(config_serde)?;
+    let tx_signer = new_tx_signer()?;
+    let action_signer = tx_signer.for_action(SignAction::PrepareUpdate);
+    let wallet = WalletData::new();
     let update_bootstrap_input = PrepareUpdateInput {
-        wallet: &node_interface,
-        tx_signer: &node_interface,
+        wallet: &wallet,
+        tx_signer: &action_signer,
         submit_tx: &node_interface,
         tx_fee: *BASE_FEE,
         erg_value_per_box: *BASE_FEE,
@@ -510,6 +511,8 @@ pub enum PrepareUpdateError {
     ErgoBoxCandidateBuilder(ErgoBoxCandidateBuilderError),
     #[error("node error: {0}")]
     Node(NodeError),
+    #[error("signer error: {0}")]
+    Signer(SignerError),
     #[error("box selector error: {0}")]
     BoxSelector(BoxSelectorError),
     #[error("box value error: {0}")]
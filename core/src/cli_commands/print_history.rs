@@ -0,0 +1,47 @@
+use crate::history::{DatapointHistory, EpochRecord, HistoryError, HistoryQuery};
+
+pub fn print_history(
+    history: &DatapointHistory,
+    query: &HistoryQuery,
+    json: bool,
+) -> Result<(), HistoryError> {
+    let records = history.query(query)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    if records.is_empty() {
+        println!("No datapoint history recorded");
+        return Ok(());
+    }
+    println!(
+        "{:>8} {:>20} {:>20} {:>8}  {:<64}  sources",
+        "epoch", "posted", "pool rate", "reward", "tx id"
+    );
+    for record in &records {
+        println!("{}", format_record(record));
+    }
+    Ok(())
+}
+
+fn format_record(record: &EpochRecord) -> String {
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    let sources = record
+        .fetched
+        .iter()
+        .map(|q| {
+            let rejected = if q.accepted { "" } else { " (rejected)" };
+            format!("{}={}{}", q.source, q.value, rejected)
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!(
+        "{:>8} {:>20} {:>20} {:>8}  {:<64}  {}",
+        record.epoch_id,
+        or_dash(record.posted_datapoint.map(|v| v.to_string())),
+        or_dash(record.pool_rate.map(|v| v.to_string())),
+        or_dash(record.reward_delta.map(|v| format!("{:+}", v))),
+        or_dash(record.posted_tx_id.clone()),
+        sources
+    )
+}
@@ -0,0 +1,450 @@
+//! Dry run of the refresh: which datapoints would be collected or excluded, the resulting pool
+//! rate and the reward distribution. Nothing is signed or submitted.
+
+use derive_more::From;
+use ergo_lib::ergotree_ir::chain::address::{Address, NetworkAddress, NetworkPrefix};
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBoxCandidate;
+use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::box_kind::{OracleBox, PoolBox};
+use crate::oracle_config::ORACLE_CONFIG;
+use crate::oracle_state::{
+    DatapointBoxesSource, OraclePool, PoolBoxSource, RefreshBoxSource, StageError,
+};
+use crate::pool_commands::aggregation::AggregationStrategyConfig;
+use crate::pool_commands::refresh::{
+    build_refresh_action, current_epoch_datapoints, plan_refresh, RefreshActionError,
+};
+use crate::wallet::WalletDataSource;
+
+#[derive(Debug, Error, From)]
+pub enum SimulateRefreshError {
+    #[error("stage error: {0}")]
+    StageError(StageError),
+    #[error("refresh error: {0}")]
+    RefreshAction(RefreshActionError),
+    #[error("JSON error: {0}")]
+    Json(serde_json::Error),
+    #[error("Wrong oracle address type")]
+    WrongOracleAddressType,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectedDatapoint {
+    pub address: String,
+    pub rate: u64,
+    pub reward_tokens: u64,
+    /// Reward tokens in the oracle box after the refresh, `None` if the refresh tx can not be
+    /// built
+    pub new_reward_tokens: Option<u64>,
+    /// The oracle building the refresh tx receives an extra reward
+    pub collector: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludedDatapointReport {
+    pub address: String,
+    pub rate: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshSimulation {
+    pub height: u32,
+    pub epoch_counter: u32,
+    pub strategy: AggregationStrategyConfig,
+    pub max_deviation_percent: u32,
+    pub min_data_points: u32,
+    pub current_pool_rate: i64,
+    /// `None` if the refresh tx can not be built (see `error`)
+    pub new_pool_rate: Option<u64>,
+    pub pool_reward_tokens: u64,
+    /// `None` if the refresh tx can not be built
+    pub new_pool_reward_tokens: Option<u64>,
+    /// Sorted by rate. If the outlier removal gave up, the datapoints left at that point.
+    pub collected: Vec<CollectedDatapoint>,
+    /// Outlier removal trace, in the order the datapoints were excluded
+    pub excluded: Vec<ExcludedDatapointReport>,
+    /// Why the refresh tx could not be built (e.g. not enough datapoints), if it failed
+    pub error: Option<String>,
+}
+
+pub fn simulate_refresh(
+    op: &OraclePool,
+    wallet: &dyn WalletDataSource,
+    height: u32,
+    change_address: NetworkAddress,
+    json: bool,
+) -> Result<(), SimulateRefreshError> {
+    let oracle_public_key =
+        if let Address::P2Pk(public_key) = ORACLE_CONFIG.oracle_address.address() {
+            public_key
+        } else {
+            return Err(SimulateRefreshError::WrongOracleAddressType);
+        };
+    let contract_parameters = ORACLE_CONFIG
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let simulation = run_simulation(
+        op.get_pool_box_source(),
+        op.get_refresh_box_source(),
+        op.get_datapoint_boxes_source(),
+        ORACLE_CONFIG
+            .refresh_aggregation_strategy
+            .unwrap_or_default(),
+        contract_parameters.max_deviation_percent() as u32,
+        contract_parameters.min_data_points() as u32,
+        wallet,
+        height,
+        change_address,
+        oracle_public_key,
+    )?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&simulation)?);
+    } else {
+        print_simulation(&simulation);
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_simulation(
+    pool_box_source: &dyn PoolBoxSource,
+    refresh_box_source: &dyn RefreshBoxSource,
+    datapoint_stage_src: &dyn DatapointBoxesSource,
+    strategy: AggregationStrategyConfig,
+    max_deviation_percent: u32,
+    min_data_points: u32,
+    wallet: &dyn WalletDataSource,
+    height: u32,
+    change_address: NetworkAddress,
+    oracle_public_key: ProveDlog,
+) -> Result<RefreshSimulation, SimulateRefreshError> {
+    let network_prefix = change_address.network();
+    let pool_box = pool_box_source.get_pool_box()?;
+    let refresh_box = refresh_box_source.get_refresh_box()?;
+    let plan = match plan_refresh(
+        &pool_box,
+        &refresh_box,
+        datapoint_stage_src,
+        max_deviation_percent,
+        min_data_points,
+        &*strategy.strategy(),
+        height,
+    ) {
+        Ok(plan) => plan,
+        // report the datapoints as they were when the outlier removal gave up (the error itself
+        // is reported by `build_refresh_action` below)
+        Err(RefreshActionError::NotEnoughDatapoints { partial }) => {
+            current_epoch_datapoints(&pool_box, &refresh_box, datapoint_stage_src, height)?
+                .with_selection(&partial)
+        }
+        Err(_) => current_epoch_datapoints(&pool_box, &refresh_box, datapoint_stage_src, height)?,
+    };
+
+    // runs the same checks as the main loop, the tx is dropped unsigned
+    let action = build_refresh_action(
+        pool_box_source,
+        refresh_box_source,
+        datapoint_stage_src,
+        max_deviation_percent,
+        min_data_points,
+        &*strategy.strategy(),
+        wallet,
+        height,
+        change_address.address(),
+        oracle_public_key.h.as_ref(),
+    );
+    // the values after the refresh are read off the tx outputs (pool box, refresh box, then the
+    // collected oracle boxes in the plan order), so they are only known if the tx can be built
+    let outputs: &[ErgoBoxCandidate] = match &action {
+        Ok(action) => action.tx.output_candidates.as_vec(),
+        Err(_) => &[],
+    };
+    let collected = plan
+        .collected
+        .iter()
+        .enumerate()
+        .map(|(i, b)| CollectedDatapoint {
+            address: address_of(network_prefix, b.public_key()),
+            rate: b.rate(),
+            reward_tokens: *b.reward_token().amount.as_u64(),
+            new_reward_tokens: outputs.get(i + 2).and_then(reward_tokens_of),
+            collector: b.public_key() == oracle_public_key,
+        })
+        .collect::<Vec<_>>();
+    let excluded = plan
+        .excluded
+        .iter()
+        .map(|e| ExcludedDatapointReport {
+            address: address_of(network_prefix, e.public_key.clone()),
+            rate: e.rate,
+            reason: e.reason.to_string(),
+        })
+        .collect();
+
+    Ok(RefreshSimulation {
+        height,
+        epoch_counter: pool_box.epoch_counter(),
+        strategy,
+        max_deviation_percent,
+        min_data_points,
+        current_pool_rate: pool_box.rate(),
+        new_pool_rate: action.as_ref().ok().and_then(|_| plan.pool_rate()),
+        pool_reward_tokens: *pool_box.reward_token().amount.as_u64(),
+        new_pool_reward_tokens: outputs.first().and_then(reward_tokens_of),
+        collected,
+        excluded,
+        error: action.err().map(|e| e.to_string()),
+    })
+}
+
+fn address_of(network_prefix: NetworkPrefix, public_key: ProveDlog) -> String {
+    NetworkAddress::new(network_prefix, &Address::P2Pk(public_key)).to_base58()
+}
+
+/// Reward token amount of a pool or oracle box candidate (reward tokens reside in `tokens(1)`)
+fn reward_tokens_of(candidate: &ErgoBoxCandidate) -> Option<u64> {
+    candidate
+        .tokens
+        .as_ref()
+        .and_then(|tokens| tokens.get(1))
+        .map(|token| *token.amount.as_u64())
+}
+
+fn print_simulation(simulation: &RefreshSimulation) {
+    let or_dash = |v: Option<u64>| v.map_or_else(|| "-".to_string(), |v| v.to_string());
+    println!(
+        "Refresh simulation at height {} (epoch {}, strategy {:?}, max deviation {}%, min datapoints {})",
+        simulation.height,
+        simulation.epoch_counter,
+        simulation.strategy,
+        simulation.max_deviation_percent,
+        simulation.min_data_points
+    );
+    println!(
+        "Pool rate: {} -> {}",
+        simulation.current_pool_rate,
+        or_dash(simulation.new_pool_rate)
+    );
+    println!(
+        "Pool reward tokens: {} -> {}",
+        simulation.pool_reward_tokens,
+        or_dash(simulation.new_pool_reward_tokens)
+    );
+    println!("Collected ({}):", simulation.collected.len());
+    for c in &simulation.collected {
+        println!(
+            "  {:>20}  {}  reward tokens {} -> {}{}",
+            c.rate,
+            c.address,
+            c.reward_tokens,
+            or_dash(c.new_reward_tokens),
+            if c.collector { " (collector)" } else { "" }
+        );
+    }
+    println!("Excluded ({}):", simulation.excluded.len());
+    for (step, e) in simulation.excluded.iter().enumerate() {
+        println!(
+            "  {:>3}. {:>20}  {}  {}",
+            step + 1,
+            e.rate,
+            e.address,
+            e.reason
+        );
+    }
+    match &simulation.error {
+        Some(e) => println!("The refresh would fail: {}", e),
+        None => println!("The refresh tx can be built"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+    use ergo_lib::ergo_chain_types::EcPoint;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::box_kind::RefreshBoxWrapperInputs;
+    use crate::contracts::oracle::OracleContractParameters;
+    use crate::contracts::pool::PoolContractParameters;
+    use crate::contracts::refresh::{RefreshContractInputs, RefreshContractParameters};
+    use crate::oracle_config::BASE_FEE;
+    use crate::pool_commands::test_utils::{
+        generate_token_ids, make_datapoint_boxes, make_pool_box, make_refresh_box,
+        make_wallet_unspent_box, DatapointStageMock, PoolBoxMock, RefreshBoxMock, WalletDataMock,
+    };
+
+    fn simulate(rates: Vec<i64>) -> RefreshSimulation {
+        let height = force_any_val::<ErgoStateContext>().pre_header.height;
+        let token_ids = generate_token_ids();
+        let refresh_box_inputs = RefreshBoxWrapperInputs {
+            refresh_nft_token_id: token_ids.refresh_nft_token_id.clone(),
+            contract_inputs: RefreshContractInputs::build_with(
+                RefreshContractParameters::default(),
+                token_ids.oracle_token_id.clone(),
+                token_ids.pool_nft_token_id.clone(),
+            )
+            .unwrap(),
+        };
+        let secret = force_any_val::<DlogProverInput>();
+        let oracle_public_key = secret.public_image();
+        let mut pub_keys = vec![*oracle_public_key.h.clone()];
+        pub_keys.extend((1..rates.len()).map(|_| force_any_val::<EcPoint>()));
+        let mut datapoints = make_datapoint_boxes(
+            pub_keys,
+            rates,
+            1,
+            BASE_FEE.checked_mul_u32(100).unwrap(),
+            height - 9,
+            &OracleContractParameters::default(),
+            &token_ids,
+        );
+        // posted in the next epoch
+        datapoints.extend(make_datapoint_boxes(
+            vec![force_any_val::<EcPoint>()],
+            vec![150],
+            2,
+            BASE_FEE.checked_mul_u32(100).unwrap(),
+            height - 9,
+            &OracleContractParameters::default(),
+            &token_ids,
+        ));
+        let change_address = NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &Address::P2Pk(oracle_public_key.clone()),
+        );
+        run_simulation(
+            &PoolBoxMock {
+                pool_box: make_pool_box(
+                    200,
+                    1,
+                    *BASE_FEE,
+                    height - 32,
+                    &PoolContractParameters::default(),
+                    &token_ids,
+                ),
+            },
+            &RefreshBoxMock {
+                refresh_box: make_refresh_box(*BASE_FEE, &refresh_box_inputs, height - 32),
+            },
+            &DatapointStageMock { datapoints },
+            AggregationStrategyConfig::Mean,
+            5,
+            4,
+            &WalletDataMock {
+                unspent_boxes: vec![make_wallet_unspent_box(
+                    oracle_public_key.clone(),
+                    BASE_FEE.checked_mul_u32(10000).unwrap(),
+                    None,
+                )],
+            },
+            height,
+            change_address,
+            oracle_public_key,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_simulation() {
+        let simulation = simulate(vec![199, 70, 196, 197, 198, 200]);
+        assert_eq!(simulation.error, None);
+        assert_eq!(
+            simulation
+                .collected
+                .iter()
+                .map(|c| c.rate)
+                .collect::<Vec<_>>(),
+            vec![196, 197, 198, 199, 200]
+        );
+        assert!(simulation
+            .collected
+            .iter()
+            .all(|c| c.new_reward_tokens.is_some()));
+        assert_eq!(
+            simulation
+                .excluded
+                .iter()
+                .map(|e| e.rate)
+                .collect::<Vec<_>>(),
+            vec![150, 70]
+        );
+        assert_eq!(simulation.new_pool_rate, Some(198));
+        assert_eq!(simulation.new_pool_reward_tokens, Some(90));
+    }
+
+    #[test]
+    fn test_simulation_not_enough_datapoints() {
+        let simulation = simulate(vec![70, 200]);
+        assert!(simulation.error.is_some());
+        // the datapoints of the epoch are still reported
+        assert_eq!(
+            simulation
+                .collected
+                .iter()
+                .map(|c| c.rate)
+                .collect::<Vec<_>>(),
+            vec![70, 200]
+        );
+        assert!(simulation
+            .collected
+            .iter()
+            .all(|c| c.new_reward_tokens.is_none()));
+        assert_eq!(simulation.excluded.len(), 1);
+        assert_eq!(simulation.excluded[0].rate, 150);
+        assert_eq!(simulation.new_pool_rate, None);
+        assert_eq!(simulation.new_pool_reward_tokens, None);
+
+        // the outliers dropped before the removal gave up are reported
+        let simulation = simulate(vec![70, 100, 200]);
+        assert!(simulation.error.is_some());
+        assert_eq!(
+            simulation
+                .collected
+                .iter()
+                .map(|c| c.rate)
+                .collect::<Vec<_>>(),
+            vec![70, 100]
+        );
+        assert_eq!(
+            simulation
+                .excluded
+                .iter()
+                .map(|e| e.rate)
+                .collect::<Vec<_>>(),
+            vec![150, 200]
+        );
+
+        // 3 datapoints within the deviation range, 4 required
+        let simulation = simulate(vec![70, 196, 197, 198]);
+        assert!(simulation.error.is_some());
+        assert_eq!(
+            simulation
+                .collected
+                .iter()
+                .map(|c| c.rate)
+                .collect::<Vec<_>>(),
+            vec![196, 197, 198]
+        );
+        assert!(simulation
+            .collected
+            .iter()
+            .all(|c| c.new_reward_tokens.is_none()));
+        assert_eq!(
+            simulation
+                .excluded
+                .iter()
+                .map(|e| e.rate)
+                .collect::<Vec<_>>(),
+            vec![150, 70]
+        );
+        assert_eq!(simulation.new_pool_rate, None);
+        assert_eq!(simulation.new_pool_reward_tokens, None);
+    }
+}
@@ -24,10 +24,11 @@ use crate::{
         make_collected_oracle_box_candidate, make_oracle_box_candidate, OracleBox, OracleBoxWrapper,
     },
     cli_commands::ergo_explorer_transaction_link,
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    node_interface::{current_block_height, sign_and_submit_transaction},
     oracle_config::BASE_FEE,
     oracle_state::{LocalDatapointBoxSource, StageError},
-    wallet::{WalletDataError, WalletDataSource},
+    signer::{SignAction, SignerError},
+    wallet::{change_address, WalletDataError, WalletDataSource},
 };
 
 #[derive(Debug, Error, From)]
@@ -42,6 +43,8 @@ pub enum TransferOracleTokenActionError {
     StageError(StageError),
     #[error("node error: {0}")]
     Node(NodeError),
+    #[error("signer error: {0}")]
+    Signer(SignerError),
     #[error("box selector error: {0}")]
     BoxSelector(BoxSelectorError),
     #[error("Sigma parsing error: {0}")]
@@ -68,9 +71,8 @@ pub fn transfer_oracle_token(
     let rewards_destination =
         AddressEncoder::unchecked_parse_network_address_from_str(&rewards_destination_str)?;
 
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(TransferOracleTokenActionError::NoChangeAddressSetInNode)?;
+    let change_address_str =
+        change_address()?.ok_or(TransferOracleTokenActionError::NoChangeAddressSetInNode)?;
 
     let (change_address, network_prefix) = {
         let a = AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
@@ -91,7 +93,7 @@ pub fn transfer_oracle_token(
     let mut input = String::new();
     std::io::stdin().read_line(&mut input)?;
     if input.trim() == "YES" {
-        let tx_id_str = sign_and_submit_transaction(&unsigned_tx)?;
+        let tx_id_str = sign_and_submit_transaction(&unsigned_tx, SignAction::TransferOracleToken)?;
         println!(
             "Transaction made. Check status here: {}",
             ergo_explorer_transaction_link(tx_id_str, network_prefix)
@@ -28,11 +28,12 @@ use crate::{
     },
     cli_commands::ergo_explorer_transaction_link,
     contracts::pool::PoolContract,
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    node_interface::{current_block_height, sign_and_submit_transaction},
     oracle_config::{CastBallotBoxVoteParameters, OracleConfig, BASE_FEE, ORACLE_CONFIG},
     oracle_state::{OraclePool, PoolBoxSource, StageError, UpdateBoxSource, VoteBallotBoxesSource},
+    signer::{SignAction, SignerError},
     spec_token::TokenIdKind,
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{change_address, WalletDataError, WalletDataSource},
 };
 use derive_more::From;
 use thiserror::Error;
@@ -55,6 +56,8 @@ pub enum UpdatePoolError {
     StageError(StageError),
     #[error("Update pool: node error {0}")]
     Node(NodeError),
+    #[error("Update pool: signer error {0}")]
+    Signer(SignerError),
     #[error("No change address in node")]
     NoChangeAddressSetInNode,
     #[error("Update pool: address encoder error {0}")]
@@ -80,9 +83,7 @@ pub fn update_pool(
     let s = std::fs::read_to_string("oracle_config_updated.yaml")?;
     let new_oracle_config: OracleConfig = serde_yaml::from_str(&s)?;
     let wallet = crate::wallet::WalletData {};
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(UpdatePoolError::NoChangeAddressSetInNode)?;
+    let change_address_str = change_address()?.ok_or(UpdatePoolError::NoChangeAddressSetInNode)?;
 
     let (change_address, network_prefix) = {
         let a = AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
@@ -124,7 +125,7 @@ pub fn update_pool(
         change_address,
     )?;
 
-    let tx_id_str = sign_and_submit_transaction(&tx.spending_tx)?;
+    let tx_id_str = sign_and_submit_transaction(&tx.spending_tx, SignAction::UpdatePool)?;
     println!(
         "Update pool box transaction submitted: view here, {}",
         ergo_explorer_transaction_link(tx_id_str, network_prefix)
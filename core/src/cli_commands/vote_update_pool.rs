@@ -24,11 +24,12 @@ use crate::{
     contracts::ballot::{
         BallotContract, BallotContractError, BallotContractInputs, BallotContractParameters,
     },
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    node_interface::{current_block_height, sign_and_submit_transaction},
     oracle_config::{TokenIds, BASE_FEE, ORACLE_CONFIG},
     oracle_state::{LocalBallotBoxSource, StageError},
+    signer::{SignAction, SignerError},
     spec_token::SpecToken,
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{change_address, WalletDataError, WalletDataSource},
 };
 use derive_more::From;
 use thiserror::Error;
@@ -41,6 +42,8 @@ pub enum VoteUpdatePoolError {
     ErgoBoxCandidateBuilder(ErgoBoxCandidateBuilderError),
     #[error("Vote update pool: node error {0}")]
     Node(NodeError),
+    #[error("Vote update pool: signer error {0}")]
+    Signer(SignerError),
     #[error("Vote update pool: box selector error {0}")]
     BoxSelector(BoxSelectorError),
     #[error("Vote update pool: tx builder error {0}")]
@@ -69,9 +72,8 @@ pub fn vote_update_pool(
     reward_token_amount: u32,
     update_box_creation_height: u32,
 ) -> Result<(), VoteUpdatePoolError> {
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(VoteUpdatePoolError::NoChangeAddressSetInNode)?;
+    let change_address_str =
+        change_address()?.ok_or(VoteUpdatePoolError::NoChangeAddressSetInNode)?;
 
     let change_network_address =
         AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
@@ -124,7 +126,7 @@ pub fn vote_update_pool(
     let mut input = String::new();
     std::io::stdin().read_line(&mut input)?;
     if input.trim_end() == "YES" {
-        let tx_id_str = sign_and_submit_transaction(&unsigned_tx)?;
+        let tx_id_str = sign_and_submit_transaction(&unsigned_tx, SignAction::VoteUpdatePool)?;
         println!(
             "Transaction made. Check status here: {}",
             ergo_explorer_transaction_link(tx_id_str, network_prefix)
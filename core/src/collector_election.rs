@@ -0,0 +1,109 @@
+//! Election of the oracle that collects the datapoints of an epoch. Instead of every oracle racing
+//! to refresh (and all but one failing with a double spend), the oracles that posted a datapoint
+//! in the epoch are ranked by `blake2b256(epoch id || public key)`. The first one refreshes as
+//! soon as the epoch ends, every following rank waits `fallback_delay_blocks` longer so that it
+//! only steps in if the ones before it fail.
+
+use ergo_lib::ergo_chain_types::blake2b256_hash;
+use ergo_lib::ergo_chain_types::EcPoint;
+use ergo_lib::ergotree_ir::mir::constant::Constant;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CollectorElectionConfig {
+    /// Blocks each rank waits after the previous one before refreshing
+    pub fallback_delay_blocks: u32,
+}
+
+impl CollectorElectionConfig {
+    /// Blocks the oracle with the given rank waits after the end of the epoch. Oracles with
+    /// unknown rank do not wait.
+    pub fn delay_blocks(&self, rank: Option<u32>) -> u32 {
+        rank.unwrap_or(0).saturating_mul(self.fallback_delay_blocks)
+    }
+}
+
+/// Rank of `public_key` among the `candidates` in the given epoch, 0 being the elected collector.
+/// `None` if `public_key` is not among the candidates.
+pub fn collector_rank(epoch_id: u32, public_key: &EcPoint, candidates: &[EcPoint]) -> Option<u32> {
+    if !candidates.contains(public_key) {
+        return None;
+    }
+    let own_hash = election_hash(epoch_id, public_key);
+    let mut ranked = candidates
+        .iter()
+        .map(|pk| election_hash(epoch_id, pk))
+        .collect::<Vec<_>>();
+    ranked.sort();
+    ranked.dedup();
+    ranked
+        .iter()
+        .position(|hash| *hash == own_hash)
+        .map(|rank| rank as u32)
+}
+
+fn election_hash(epoch_id: u32, public_key: &EcPoint) -> Vec<u8> {
+    let mut bytes = epoch_id.to_be_bytes().to_vec();
+    bytes.extend(
+        Constant::from(public_key.clone())
+            .sigma_serialize_bytes()
+            .unwrap(),
+    );
+    blake2b256_hash(&bytes).as_ref().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+
+    #[test]
+    fn test_collector_rank() {
+        let candidates = force_any_val::<[EcPoint; 5]>().to_vec();
+        for epoch_id in 0..10 {
+            let mut ranks = candidates
+                .iter()
+                .map(|pk| collector_rank(epoch_id, pk, &candidates).unwrap())
+                .collect::<Vec<_>>();
+            // the order of the candidates does not matter
+            let mut reversed = candidates.clone();
+            reversed.reverse();
+            for (pk, rank) in candidates.iter().zip(&ranks) {
+                assert_eq!(collector_rank(epoch_id, pk, &reversed), Some(*rank));
+            }
+            ranks.sort();
+            assert_eq!(ranks, vec![0, 1, 2, 3, 4]);
+        }
+        assert_eq!(
+            collector_rank(1, &force_any_val::<EcPoint>(), &candidates),
+            None
+        );
+    }
+
+    #[test]
+    fn test_collector_rotates_with_epoch() {
+        let candidates = force_any_val::<[EcPoint; 5]>().to_vec();
+        let collectors = (0..50)
+            .map(|epoch_id| {
+                candidates
+                    .iter()
+                    .position(|pk| collector_rank(epoch_id, pk, &candidates) == Some(0))
+                    .unwrap()
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert!(collectors.len() > 1);
+    }
+
+    #[test]
+    fn test_delay_blocks() {
+        let config = CollectorElectionConfig {
+            fallback_delay_blocks: 3,
+        };
+        assert_eq!(config.delay_blocks(Some(0)), 0);
+        assert_eq!(config.delay_blocks(Some(2)), 6);
+        assert_eq!(config.delay_blocks(None), 0);
+        assert_eq!(config.delay_blocks(Some(u32::MAX)), u32::MAX);
+    }
+}
@@ -1,28 +1,108 @@
 //! Datapoint sources for oracle-core
 mod ada_usd;
+mod aggregate;
+pub mod binance;
+pub mod coincap;
+pub mod coingecko;
 mod erg_usd;
 mod erg_xau;
+mod exchange;
+mod external_script;
+mod fetch;
+mod http;
+mod http_json;
+pub mod kraken;
+pub mod kucoin;
+pub mod price;
+#[cfg(test)]
+pub(crate) mod test_utils;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use derive_more::From;
 use thiserror::Error;
 
+pub use aggregate::AggregateDataPointSource;
+pub use aggregate::AggregateDataPointSourceConfig;
+pub use aggregate::RejectedSources;
+pub use exchange::supported_exchanges;
+pub use exchange::Exchange;
+pub use exchange::ExchangeSource;
+pub use external_script::ExternalScript;
+pub use external_script::ExternalScriptError;
+pub use external_script::ExternalScriptOutput;
+pub use external_script::ExternalScriptOutputFormat;
+pub use external_script::ExternalScriptSpec;
+pub use fetch::cancel_datapoint_fetches;
+pub use fetch::fetch_datapoint;
+pub use fetch::AsyncDataPointSource;
+pub use fetch::BlockingSource;
+pub use fetch::FetchPolicy;
+pub use fetch::RetryingSource;
+pub use fetch::RuntimeBoundSource;
+pub use fetch::ITERATION_CANCELLATION;
+pub use fetch::RUNTIME_HANDLE;
+pub use http_json::HttpJsonSource;
+pub use http_json::HttpJsonSourceConfig;
+pub use price::{Price, PriceError};
+
+/// Blocking datapoint source. Retries, timeouts and cancellation are handled by
+/// `RuntimeBoundSource` which drives the source on the tokio runtime (see `fetch`).
 pub trait DataPointSource: std::fmt::Debug {
-    fn get_datapoint(&self) -> Result<i64, DataPointSourceError>;
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError>;
+
+    /// Label of the source in logs and in `SourceQuote::source`, must not contain secrets
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// Quotes of the underlying sources taken by the last `get_datapoint` call (empty if the
+    /// source is not composed of several sources)
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        Vec::new()
+    }
+}
 
-    fn get_datapoint_retry(&self, retries: u8) -> Result<i64, DataPointSourceError> {
-        let mut last_error = None;
-        for _ in 0..retries {
-            match self.get_datapoint() {
-                Ok(datapoint) => return Ok(datapoint),
-                Err(err) => {
-                    log::warn!("Failed to get datapoint from source: {}, retrying ...", err);
-                    last_error = Some(err)
-                }
-            }
+/// Quote of a single source taken into account by a composed datapoint source
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SourceQuote {
+    pub source: String,
+    pub value: i64,
+    /// Unix timestamp (in seconds) of the quote
+    pub timestamp: i64,
+    /// Whether the quote was within the deviation range
+    pub accepted: bool,
+}
+
+/// Datapoint with the time it was quoted at
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DataPoint {
+    pub value: i64,
+    /// Unix timestamp (in seconds) of the quote
+    pub timestamp: i64,
+}
+
+impl DataPoint {
+    /// Datapoint quoted right now
+    pub fn now(value: i64) -> Self {
+        DataPoint {
+            value,
+            timestamp: unix_timestamp_now(),
         }
-        Err(last_error.unwrap())
+    }
+
+    /// Seconds since the quote, negative for quotes from the future (clock skew)
+    pub fn age_secs(&self) -> i64 {
+        unix_timestamp_now().saturating_sub(self.timestamp)
     }
 }
 
+pub fn unix_timestamp_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
 #[derive(Debug, From, Error)]
 pub enum DataPointSourceError {
     #[error("external script error: {0}")]
@@ -31,40 +111,38 @@ pub enum DataPointSourceError {
     Reqwest(reqwest::Error),
     #[error("JSON parse error: {0}")]
     JsonParse(json::Error),
+    #[error("JSON parse error: {0}")]
+    SerdeJsonParse(serde_json::Error),
     #[error("Missing JSON field")]
     JsonMissingField,
-}
-
-#[derive(Debug, From, Error)]
-pub enum ExternalScriptError {
-    #[error("external script child process error: {0}")]
-    ChildProcess(std::io::Error),
-    #[error("String from bytes error: {0}")]
-    StringFromBytes(std::string::FromUtf8Error),
-    #[error("Parse i64 from string error: {0}")]
-    ParseInt(std::num::ParseIntError),
-}
-
-#[derive(Debug, Clone)]
-pub struct ExternalScript(String);
-
-impl ExternalScript {
-    pub fn new(script_name: String) -> Self {
-        ExternalScript(script_name)
-    }
-}
-
-impl DataPointSource for ExternalScript {
-    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
-        let script_output = std::process::Command::new(&self.0)
-            .output()
-            .map_err(ExternalScriptError::from)?;
-        let datapoint_str =
-            String::from_utf8(script_output.stdout).map_err(ExternalScriptError::from)?;
-        datapoint_str
-            .parse()
-            .map_err(|e| DataPointSourceError::from(ExternalScriptError::from(e)))
-    }
+    #[error("Invalid datapoint: {0}")]
+    InvalidDatapoint(String),
+    #[error("Invalid price: {0}")]
+    Price(PriceError),
+    #[error("Datapoint source timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("Rate limited by {url}, retry after {retry_after:?}")]
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    #[error("Datapoint fetch cancelled")]
+    Cancelled,
+    #[error("Datapoint fetch task failed: {0}")]
+    Task(tokio::task::JoinError),
+    #[error("Failed to start tokio runtime: {0}")]
+    Runtime(std::io::Error),
+    #[error("{exchange:?} does not quote {pair:?}")]
+    UnsupportedExchangePair {
+        exchange: Exchange,
+        pair: PredefinedDataPointSource,
+    },
+    #[error("Not enough datapoint sources agree: required {required}, accepted {accepted}, rejected {rejected}")]
+    QuorumNotReached {
+        required: usize,
+        accepted: usize,
+        rejected: RejectedSources,
+    },
 }
 
 pub use ada_usd::NanoAdaUsd;
@@ -80,7 +158,7 @@ pub enum PredefinedDataPointSource {
 }
 
 impl DataPointSource for PredefinedDataPointSource {
-    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
         match self {
             PredefinedDataPointSource::NanoAdaUsd => NanoAdaUsd.get_datapoint(),
             PredefinedDataPointSource::NanoErgUsd => NanoErgUsd.get_datapoint(),
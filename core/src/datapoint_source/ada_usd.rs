@@ -1,12 +1,17 @@
 //! Obtains the lovelace per 1 USD rate.
 
-use super::{DataPointSource, DataPointSourceError};
+use std::collections::BTreeMap;
+
+use super::http::{get_json, DEFAULT_TIMEOUT};
+use super::http_json::json_to_price;
+use super::price::LOVELACE_DECIMALS;
+use super::{DataPoint, DataPointSource, DataPointSourceError};
 
 #[derive(Debug, Clone)]
 pub struct NanoAdaUsd;
 
 impl DataPointSource for NanoAdaUsd {
-    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
         get_nanoada_usd_price()
     }
 }
@@ -24,15 +29,13 @@ pub fn generate_current_price(datapoint: u64) -> f64 {
 
 /// Acquires the price of Ada in USD from CoinGecko, convert it
 /// into Lovelaces per 1 USD, and return it.
-fn get_nanoada_usd_price() -> Result<i64, DataPointSourceError> {
-    let resp = reqwest::blocking::Client::new().get(CG_RATE_URL).send()?;
-    let price_json = json::parse(&resp.text()?)?;
-    if let Some(p) = price_json["cardano"]["usd"].as_f64() {
-        let lovelace_price = (1.0 / p) * LOVELACE_CONVERSION;
-        Ok(lovelace_price as i64)
-    } else {
-        Err(DataPointSourceError::JsonMissingField)
-    }
+fn get_nanoada_usd_price() -> Result<DataPoint, DataPointSourceError> {
+    let price_json = get_json(CG_RATE_URL, &BTreeMap::new(), DEFAULT_TIMEOUT)?;
+    let price = json_to_price(&price_json.body["cardano"]["usd"])?;
+    Ok(DataPoint {
+        value: price.inverse_in_smallest_units(LOVELACE_DECIMALS)?,
+        timestamp: price_json.fetched_at,
+    })
 }
 
 #[cfg(test)]
@@ -42,6 +45,6 @@ mod tests {
     #[test]
     fn test_ada_usd_price() {
         let n = NanoAdaUsd {};
-        assert!(n.get_datapoint().unwrap() > 0);
+        assert!(n.get_datapoint().unwrap().value > 0);
     }
 }
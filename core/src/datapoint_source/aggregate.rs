@@ -0,0 +1,408 @@
+//! Combines several datapoint sources into a single one. Each source is queried, quotes that
+//! deviate too far from the median are dropped and the median of the remaining quotes is
+//! returned, provided that enough sources agree.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::task::JoinSet;
+
+use super::{
+    fetch_datapoint, AsyncDataPointSource, BlockingSource, DataPoint, DataPointSource,
+    DataPointSourceError, ExchangeSource, ExternalScript, ExternalScriptSpec, FetchPolicy,
+    HttpJsonSource, HttpJsonSourceConfig, PredefinedDataPointSource, SourceQuote,
+};
+
+/// Configuration of `AggregateDataPointSource` as given in the oracle config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateDataPointSourceConfig {
+    /// Predefined sources to query
+    #[serde(default)]
+    pub predefined: Vec<PredefinedDataPointSource>,
+    /// External scripts to query
+    #[serde(default)]
+    pub custom_scripts: Vec<ExternalScriptSpec>,
+    /// HTTP JSON sources to query
+    #[serde(default)]
+    pub http_json: Vec<HttpJsonSourceConfig>,
+    /// Predefined pairs quoted by a specific exchange
+    #[serde(default)]
+    pub exchanges: Vec<ExchangeSource>,
+    /// Quotes deviating from the median of all quotes by more than this percent are dropped
+    pub max_deviation_percent: u32,
+    /// Minimal number of sources whose quotes must be within the deviation range
+    pub min_sources: usize,
+}
+
+#[derive(Debug)]
+pub struct AggregateDataPointSource {
+    sources: Vec<Arc<dyn DataPointSource + Send + Sync>>,
+    max_deviation_percent: u32,
+    min_sources: usize,
+    /// Applied to each source when queried asynchronously
+    fetch_policy: FetchPolicy,
+    /// Quotes taken by the last aggregation
+    last_quotes: Mutex<Vec<SourceQuote>>,
+}
+
+/// Why a source did not take part in the aggregated datapoint
+#[derive(Debug, Error)]
+pub enum RejectionReason {
+    #[error("failed to get datapoint: {0}")]
+    Failed(Box<DataPointSourceError>),
+    #[error(
+        "datapoint {datapoint} deviates more than {max_deviation_percent}% from median {median}"
+    )]
+    Outlier {
+        datapoint: i64,
+        median: i64,
+        max_deviation_percent: u32,
+    },
+}
+
+#[derive(Debug)]
+pub struct RejectedSource {
+    pub source_name: String,
+    pub reason: RejectionReason,
+}
+
+/// Sources rejected during aggregation, reported as part of `DataPointSourceError`
+#[derive(Debug, Default)]
+pub struct RejectedSources(pub Vec<RejectedSource>);
+
+impl std::fmt::Display for RejectedSources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reports = self
+            .0
+            .iter()
+            .map(|r| format!("{} ({})", r.source_name, r.reason))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(f, "[{}]", reports)
+    }
+}
+
+impl AggregateDataPointSource {
+    pub fn new(
+        sources: Vec<Arc<dyn DataPointSource + Send + Sync>>,
+        max_deviation_percent: u32,
+        min_sources: usize,
+    ) -> Self {
+        AggregateDataPointSource {
+            sources,
+            max_deviation_percent,
+            min_sources,
+            fetch_policy: FetchPolicy::default(),
+            last_quotes: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_fetch_policy(self, fetch_policy: FetchPolicy) -> Self {
+        AggregateDataPointSource {
+            fetch_policy,
+            ..self
+        }
+    }
+
+    pub fn from_config(
+        config: &AggregateDataPointSourceConfig,
+    ) -> Result<Self, DataPointSourceError> {
+        let mut sources: Vec<Arc<dyn DataPointSource + Send + Sync>> = Vec::new();
+        for predefined in &config.predefined {
+            sources.push(Arc::new(*predefined));
+        }
+        for script in &config.custom_scripts {
+            sources.push(Arc::new(ExternalScript::new(script.clone())));
+        }
+        for http_json_config in &config.http_json {
+            sources.push(Arc::new(HttpJsonSource::new(http_json_config.clone())));
+        }
+        for exchange_source in &config.exchanges {
+            sources.push(Arc::new(exchange_source.clone().validated()?));
+        }
+        Ok(Self::new(
+            sources,
+            config.max_deviation_percent,
+            config.min_sources,
+        ))
+    }
+
+    /// Takes the results of all sources (in the order of `sources`) and computes the median of
+    /// the quotes that are within the deviation range. The result is as old as the oldest of these
+    /// quotes.
+    fn aggregate(
+        &self,
+        results: Vec<Result<DataPoint, DataPointSourceError>>,
+    ) -> Result<DataPoint, DataPointSourceError> {
+        let mut rejected = Vec::new();
+        let mut quotes = Vec::new();
+        for (source, result) in self.sources.iter().zip(results) {
+            let source_name = source.name();
+            match result {
+                Ok(datapoint) => quotes.push((source_name, datapoint)),
+                Err(e) => {
+                    log::warn!("Datapoint source {} failed: {}", source_name, e);
+                    rejected.push(RejectedSource {
+                        source_name,
+                        reason: RejectionReason::Failed(Box::new(e)),
+                    });
+                }
+            }
+        }
+        let accepted = if quotes.is_empty() {
+            self.last_quotes.lock().unwrap().clear();
+            Vec::new()
+        } else {
+            let median = median(quotes.iter().map(|(_, dp)| dp.value).collect());
+            let (accepted, outliers): (Vec<_>, Vec<_>) = quotes.into_iter().partition(|(_, dp)| {
+                within_deviation(dp.value, median, self.max_deviation_percent)
+            });
+            let quote = |(source_name, datapoint): &(String, DataPoint), is_accepted| SourceQuote {
+                source: source_name.clone(),
+                value: datapoint.value,
+                timestamp: datapoint.timestamp,
+                accepted: is_accepted,
+            };
+            *self.last_quotes.lock().unwrap() = accepted
+                .iter()
+                .map(|q| quote(q, true))
+                .chain(outliers.iter().map(|q| quote(q, false)))
+                .collect();
+            for (source_name, datapoint) in outliers {
+                log::warn!(
+                    "Dropping datapoint {} from source {}, median is {}",
+                    datapoint.value,
+                    source_name,
+                    median
+                );
+                rejected.push(RejectedSource {
+                    source_name,
+                    reason: RejectionReason::Outlier {
+                        datapoint: datapoint.value,
+                        median,
+                        max_deviation_percent: self.max_deviation_percent,
+                    },
+                });
+            }
+            accepted
+        };
+        if accepted.is_empty() || accepted.len() < self.min_sources {
+            return Err(DataPointSourceError::QuorumNotReached {
+                required: self.min_sources,
+                accepted: accepted.len(),
+                rejected: RejectedSources(rejected),
+            });
+        }
+        let oldest_timestamp = accepted.iter().map(|(_, dp)| dp.timestamp).min();
+        Ok(DataPoint {
+            value: median(accepted.iter().map(|(_, dp)| dp.value).collect()),
+            timestamp: oldest_timestamp.unwrap_or_default(),
+        })
+    }
+}
+
+impl DataPointSource for AggregateDataPointSource {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+        let results = self
+            .sources
+            .iter()
+            .map(|source| source.get_datapoint())
+            .collect();
+        self.aggregate(results)
+    }
+
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        self.last_quotes.lock().unwrap().clone()
+    }
+}
+
+/// Queries all sources concurrently, each according to the fetch policy
+#[async_trait]
+impl AsyncDataPointSource for AggregateDataPointSource {
+    async fn get_datapoint_async(&self) -> Result<DataPoint, DataPointSourceError> {
+        // pending fetches are aborted when the set is dropped (e.g. on cancellation)
+        let mut fetches = JoinSet::new();
+        for (idx, source) in self.sources.iter().enumerate() {
+            let source = BlockingSource(source.clone());
+            let policy = self.fetch_policy;
+            fetches.spawn(async move { (idx, fetch_datapoint(&source, &policy).await) });
+        }
+        let mut results: Vec<Result<DataPoint, DataPointSourceError>> = self
+            .sources
+            .iter()
+            .map(|_| Err(DataPointSourceError::Cancelled))
+            .collect();
+        while let Some(joined) = fetches.join_next().await {
+            let (idx, result) = joined?;
+            results[idx] = result;
+        }
+        self.aggregate(results)
+    }
+
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        self.last_quotes.lock().unwrap().clone()
+    }
+}
+
+fn median(mut datapoints: Vec<i64>) -> i64 {
+    datapoints.sort_unstable();
+    let mid = datapoints.len() / 2;
+    if datapoints.len() % 2 == 0 {
+        let (lower, upper) = (datapoints[mid - 1] as i128, datapoints[mid] as i128);
+        ((lower + upper) / 2) as i64
+    } else {
+        datapoints[mid]
+    }
+}
+
+fn within_deviation(datapoint: i64, median: i64, max_deviation_percent: u32) -> bool {
+    let delta = (datapoint as i128 - median as i128).abs();
+    delta * 100 <= (median as i128).abs() * max_deviation_percent as i128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct MockDatapointSource {
+        datapoint: Option<DataPoint>,
+    }
+
+    impl DataPointSource for MockDatapointSource {
+        fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+            self.datapoint.ok_or(DataPointSourceError::JsonMissingField)
+        }
+    }
+
+    fn make_timestamped_source(
+        datapoints: Vec<Option<DataPoint>>,
+        max_deviation_percent: u32,
+        min_sources: usize,
+    ) -> AggregateDataPointSource {
+        AggregateDataPointSource::new(
+            datapoints
+                .into_iter()
+                .map(|datapoint| {
+                    Arc::new(MockDatapointSource { datapoint })
+                        as Arc<dyn DataPointSource + Send + Sync>
+                })
+                .collect(),
+            max_deviation_percent,
+            min_sources,
+        )
+    }
+
+    fn make_source(
+        values: Vec<Option<i64>>,
+        max_deviation_percent: u32,
+        min_sources: usize,
+    ) -> AggregateDataPointSource {
+        make_timestamped_source(
+            values
+                .into_iter()
+                .map(|value| {
+                    value.map(|value| DataPoint {
+                        value,
+                        timestamp: 1000,
+                    })
+                })
+                .collect(),
+            max_deviation_percent,
+            min_sources,
+        )
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![3, 1, 2]), 2);
+        assert_eq!(median(vec![4, 1, 2, 3]), 2);
+        assert_eq!(median(vec![i64::MAX, i64::MAX]), i64::MAX);
+    }
+
+    #[test]
+    fn test_outlier_is_dropped() {
+        let source = make_source(vec![Some(100), Some(101), Some(200), Some(99)], 5, 3);
+        assert_eq!(source.get_datapoint().unwrap().value, 100);
+    }
+
+    #[test]
+    fn test_source_quotes() {
+        let source = make_source(vec![Some(100), None, Some(200), Some(101)], 5, 2);
+        assert_eq!(source.get_datapoint().unwrap().value, 100);
+        let quotes = DataPointSource::source_quotes(&source);
+        assert_eq!(
+            quotes
+                .iter()
+                .map(|q| (q.value, q.accepted))
+                .collect::<Vec<_>>(),
+            vec![(100, true), (101, true), (200, false)]
+        );
+    }
+
+    #[test]
+    fn test_failed_sources_are_ignored() {
+        let source = make_source(vec![Some(100), None, Some(102)], 5, 2);
+        assert_eq!(source.get_datapoint().unwrap().value, 101);
+    }
+
+    #[test]
+    fn test_oldest_accepted_timestamp() {
+        let quote = |value, timestamp| Some(DataPoint { value, timestamp });
+        let source = make_timestamped_source(
+            vec![quote(100, 1000), quote(101, 900), quote(300, 100), None],
+            5,
+            2,
+        );
+        assert_eq!(
+            source.get_datapoint().unwrap(),
+            DataPoint {
+                value: 100,
+                timestamp: 900
+            }
+        );
+    }
+
+    #[test]
+    fn test_quorum_not_reached() {
+        let source = make_source(vec![Some(100), None, Some(101), Some(200)], 5, 3);
+        match source.get_datapoint() {
+            Err(DataPointSourceError::QuorumNotReached {
+                required,
+                accepted,
+                rejected,
+            }) => {
+                assert_eq!(required, 3);
+                assert_eq!(accepted, 2);
+                assert_eq!(rejected.0.len(), 2);
+            }
+            res => panic!("expected QuorumNotReached, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_async_matches_blocking() {
+        let source = make_source(vec![Some(100), None, Some(102), Some(300)], 5, 2)
+            .with_fetch_policy(FetchPolicy {
+                attempts: 1,
+                ..FetchPolicy::default()
+            });
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let res = runtime.block_on(source.get_datapoint_async());
+        assert_eq!(res.unwrap().value, 101);
+    }
+
+    #[test]
+    fn test_all_sources_failed() {
+        let source = make_source(vec![None, None], 5, 1);
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::QuorumNotReached { accepted: 0, .. })
+        ));
+    }
+}
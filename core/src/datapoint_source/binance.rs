@@ -0,0 +1,38 @@
+//! Binance spot ticker API
+
+use std::collections::BTreeMap;
+
+use super::http::{get_json, DEFAULT_TIMEOUT};
+use super::http_json::json_to_price;
+use super::price::Quote;
+use super::DataPointSourceError;
+
+pub const BASE_URL: &str = "https://api.binance.com";
+
+/// Last traded price of the `symbol` pair (e.g. `ADAUSDT`)
+pub fn get_price(base_url: &str, symbol: &str) -> Result<Quote, DataPointSourceError> {
+    let url = format!("{}/api/v3/ticker/price?symbol={}", base_url, symbol);
+    let resp = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT)?;
+    Ok(Quote {
+        price: json_to_price(&resp.body["price"])?,
+        timestamp: resp.fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let server = StubServer::start(vec![(
+            "/api/v3/ticker/price?symbol=ADAUSDT",
+            include_str!("fixtures/binance_adausdt.json"),
+        )]);
+        assert_eq!(
+            get_price(&server.base_url(), "ADAUSDT").unwrap().price,
+            "0.3825".parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! CoinCap assets API
+
+use std::collections::BTreeMap;
+
+use super::http::{get_json, DEFAULT_TIMEOUT};
+use super::http_json::json_to_price;
+use super::price::Quote;
+use super::DataPointSourceError;
+
+pub const BASE_URL: &str = "https://api.coincap.io";
+
+/// USD price of the asset with CoinCap `id` (e.g. `ergo`)
+pub fn get_usd_price(base_url: &str, id: &str) -> Result<Quote, DataPointSourceError> {
+    let url = format!("{}/v2/assets/{}", base_url, id);
+    let resp = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT)?;
+    Ok(Quote {
+        price: json_to_price(&resp.body["data"]["priceUsd"])?,
+        timestamp: resp.fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    #[test]
+    fn test_get_usd_price() {
+        let server = StubServer::start(vec![(
+            "/v2/assets/ergo",
+            include_str!("fixtures/coincap_ergo.json"),
+        )]);
+        assert_eq!(
+            get_usd_price(&server.base_url(), "ergo").unwrap().price,
+            "1.5203481269836427".parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! CoinGecko simple price API
+
+use std::collections::BTreeMap;
+
+use super::http::{get_json, DEFAULT_TIMEOUT};
+use super::http_json::json_to_price;
+use super::price::Quote;
+use super::DataPointSourceError;
+
+pub const BASE_URL: &str = "https://api.coingecko.com";
+
+/// Price of the asset with CoinGecko `id` (e.g. `ergo`) in `vs_currency` (e.g. `usd`)
+pub fn get_price(
+    base_url: &str,
+    id: &str,
+    vs_currency: &str,
+) -> Result<Quote, DataPointSourceError> {
+    let url = format!(
+        "{}/api/v3/simple/price?ids={}&vs_currencies={}",
+        base_url, id, vs_currency
+    );
+    let resp = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT)?;
+    Ok(Quote {
+        price: json_to_price(&resp.body[id][vs_currency])?,
+        timestamp: resp.fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let server = StubServer::start(vec![(
+            "/api/v3/simple/price?ids=ergo&vs_currencies=usd",
+            include_str!("fixtures/coingecko_ergo_usd.json"),
+        )]);
+        assert_eq!(
+            get_price(&server.base_url(), "ergo", "usd").unwrap().price,
+            "1.52".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let server = StubServer::start(vec![("/api/v3/simple/price", "{}")]);
+        assert!(matches!(
+            get_price(&server.base_url(), "ergo", "usd"),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+//! Predefined pairs quoted by a single exchange. Several of these can be combined in
+//! `AggregateDataPointSource` to back a pair by independent venues.
+
+use serde::{Deserialize, Serialize};
+
+use super::price::{ratio_in_smallest_units, Quote, LOVELACE_DECIMALS, NANO_ERG_DECIMALS};
+use super::{
+    binance, coincap, coingecko, kraken, kucoin, DataPoint, DataPointSource, DataPointSourceError,
+    PredefinedDataPointSource,
+};
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Exchange {
+    CoinGecko,
+    Binance,
+    Kraken,
+    KuCoin,
+    CoinCap,
+}
+
+impl Exchange {
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Exchange::CoinGecko => coingecko::BASE_URL,
+            Exchange::Binance => binance::BASE_URL,
+            Exchange::Kraken => kraken::BASE_URL,
+            Exchange::KuCoin => kucoin::BASE_URL,
+            Exchange::CoinCap => coincap::BASE_URL,
+        }
+    }
+}
+
+/// Exchanges able to quote the given pair
+pub fn supported_exchanges(pair: PredefinedDataPointSource) -> &'static [Exchange] {
+    match pair {
+        PredefinedDataPointSource::NanoErgUsd | PredefinedDataPointSource::NanoErgXau => {
+            &[Exchange::CoinGecko, Exchange::KuCoin, Exchange::CoinCap]
+        }
+        PredefinedDataPointSource::NanoAdaUsd => &[
+            Exchange::CoinGecko,
+            Exchange::Binance,
+            Exchange::Kraken,
+            Exchange::KuCoin,
+            Exchange::CoinCap,
+        ],
+    }
+}
+
+/// Predefined pair quoted by a single exchange
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExchangeSource {
+    pub exchange: Exchange,
+    pub pair: PredefinedDataPointSource,
+    /// Overrides the exchange API URL (e.g. to go through a proxy)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+}
+
+impl ExchangeSource {
+    pub fn new(
+        exchange: Exchange,
+        pair: PredefinedDataPointSource,
+    ) -> Result<Self, DataPointSourceError> {
+        ExchangeSource {
+            exchange,
+            pair,
+            base_url: None,
+        }
+        .validated()
+    }
+
+    /// Checks that the exchange quotes the pair
+    pub fn validated(self) -> Result<Self, DataPointSourceError> {
+        if supported_exchanges(self.pair).contains(&self.exchange) {
+            Ok(self)
+        } else {
+            Err(DataPointSourceError::UnsupportedExchangePair {
+                exchange: self.exchange,
+                pair: self.pair,
+            })
+        }
+    }
+
+    fn base_url(&self) -> &str {
+        self.base_url
+            .as_deref()
+            .unwrap_or_else(|| self.exchange.base_url())
+    }
+
+    /// Price of a single Erg in USD. USDT pairs are taken as USD.
+    fn erg_usd_price(&self) -> Result<Quote, DataPointSourceError> {
+        let base_url = self.base_url();
+        match self.exchange {
+            Exchange::CoinGecko => coingecko::get_price(base_url, "ergo", "usd"),
+            Exchange::KuCoin => kucoin::get_price(base_url, "ERG-USDT"),
+            Exchange::CoinCap => coincap::get_usd_price(base_url, "ergo"),
+            Exchange::Binance | Exchange::Kraken => Err(self.unsupported()),
+        }
+    }
+
+    /// NanoErgs per XAU. Where no direct market exists the rate is derived from ERG/USD and
+    /// PAXG/USD (PAX Gold is backed by one troy ounce of gold), stamped with the older of the two
+    /// quotes.
+    fn nanoerg_xau(&self) -> Result<DataPoint, DataPointSourceError> {
+        let base_url = self.base_url();
+        let paxg_usd_price = match self.exchange {
+            Exchange::CoinGecko => {
+                let erg_xau_price = coingecko::get_price(base_url, "ergo", "xau")?;
+                return Ok(DataPoint {
+                    value: erg_xau_price
+                        .price
+                        .inverse_in_smallest_units(NANO_ERG_DECIMALS)?,
+                    timestamp: erg_xau_price.timestamp,
+                });
+            }
+            Exchange::KuCoin => kucoin::get_price(base_url, "PAXG-USDT")?,
+            Exchange::CoinCap => coincap::get_usd_price(base_url, "pax-gold")?,
+            Exchange::Binance | Exchange::Kraken => return Err(self.unsupported()),
+        };
+        let erg_usd_price = self.erg_usd_price()?;
+        // USD per XAU divided by USD per Erg
+        Ok(DataPoint {
+            value: ratio_in_smallest_units(
+                paxg_usd_price.price,
+                erg_usd_price.price,
+                NANO_ERG_DECIMALS,
+            )?,
+            timestamp: paxg_usd_price.timestamp.min(erg_usd_price.timestamp),
+        })
+    }
+
+    /// Price of a single Ada in USD. USDT pairs are taken as USD.
+    fn ada_usd_price(&self) -> Result<Quote, DataPointSourceError> {
+        let base_url = self.base_url();
+        match self.exchange {
+            Exchange::CoinGecko => coingecko::get_price(base_url, "cardano", "usd"),
+            Exchange::Binance => binance::get_price(base_url, "ADAUSDT"),
+            Exchange::Kraken => kraken::get_price(base_url, "ADAUSD"),
+            Exchange::KuCoin => kucoin::get_price(base_url, "ADA-USDT"),
+            Exchange::CoinCap => coincap::get_usd_price(base_url, "cardano"),
+        }
+    }
+
+    fn unsupported(&self) -> DataPointSourceError {
+        DataPointSourceError::UnsupportedExchangePair {
+            exchange: self.exchange,
+            pair: self.pair,
+        }
+    }
+}
+
+impl DataPointSource for ExchangeSource {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+        let (quote, decimals) = match self.pair {
+            PredefinedDataPointSource::NanoErgUsd => (self.erg_usd_price()?, NANO_ERG_DECIMALS),
+            PredefinedDataPointSource::NanoErgXau => return self.nanoerg_xau(),
+            PredefinedDataPointSource::NanoAdaUsd => (self.ada_usd_price()?, LOVELACE_DECIMALS),
+        };
+        Ok(DataPoint {
+            value: quote.price.inverse_in_smallest_units(decimals)?,
+            timestamp: quote.timestamp,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    fn make_source(
+        server: &StubServer,
+        exchange: Exchange,
+        pair: PredefinedDataPointSource,
+    ) -> ExchangeSource {
+        ExchangeSource {
+            base_url: Some(server.base_url()),
+            ..ExchangeSource::new(exchange, pair).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_erg_usd() {
+        let server = StubServer::start(vec![
+            (
+                "/api/v3/simple/price?ids=ergo&vs_currencies=usd",
+                include_str!("fixtures/coingecko_ergo_usd.json"),
+            ),
+            (
+                "/api/v1/market/orderbook/level1?symbol=ERG-USDT",
+                include_str!("fixtures/kucoin_erg_usdt.json"),
+            ),
+            (
+                "/v2/assets/ergo",
+                include_str!("fixtures/coincap_ergo.json"),
+            ),
+        ]);
+        let pair = PredefinedDataPointSource::NanoErgUsd;
+        let datapoints: Vec<i64> = supported_exchanges(pair)
+            .iter()
+            .map(|exchange| {
+                make_source(&server, *exchange, pair)
+                    .get_datapoint()
+                    .unwrap()
+                    .value
+            })
+            .collect();
+        assert_eq!(datapoints, vec![657894736, 658457891, 657744093]);
+    }
+
+    #[test]
+    fn test_erg_xau_cross_rate() {
+        let server = StubServer::start(vec![
+            (
+                "/v2/assets/ergo",
+                include_str!("fixtures/coincap_ergo.json"),
+            ),
+            (
+                "/v2/assets/pax-gold",
+                include_str!("fixtures/coincap_pax_gold.json"),
+            ),
+        ]);
+        let source = make_source(
+            &server,
+            Exchange::CoinCap,
+            PredefinedDataPointSource::NanoErgXau,
+        );
+        // 1946.61 USD per XAU / 1.5203 USD per Erg
+        assert_eq!(source.get_datapoint().unwrap().value, 1280373806421);
+        assert_eq!(
+            server.requests(),
+            vec!["/v2/assets/pax-gold", "/v2/assets/ergo"]
+        );
+    }
+
+    #[test]
+    fn test_erg_xau_direct() {
+        let server = StubServer::start(vec![(
+            "/api/v3/simple/price?ids=ergo&vs_currencies=xau",
+            include_str!("fixtures/coingecko_ergo_xau.json"),
+        )]);
+        let source = make_source(
+            &server,
+            Exchange::CoinGecko,
+            PredefinedDataPointSource::NanoErgXau,
+        );
+        assert_eq!(source.get_datapoint().unwrap().value, 1280409731113);
+    }
+
+    #[test]
+    fn test_ada_usd() {
+        let server = StubServer::start(vec![
+            (
+                "/api/v3/ticker/price?symbol=ADAUSDT",
+                include_str!("fixtures/binance_adausdt.json"),
+            ),
+            (
+                "/0/public/Ticker?pair=ADAUSD",
+                include_str!("fixtures/kraken_adausd.json"),
+            ),
+        ]);
+        let pair = PredefinedDataPointSource::NanoAdaUsd;
+        let binance = make_source(&server, Exchange::Binance, pair);
+        assert_eq!(binance.get_datapoint().unwrap().value, 2614379);
+        let kraken = make_source(&server, Exchange::Kraken, pair);
+        assert_eq!(kraken.get_datapoint().unwrap().value, 2612917);
+    }
+
+    #[test]
+    fn test_unsupported_pair() {
+        assert!(matches!(
+            ExchangeSource::new(Exchange::Binance, PredefinedDataPointSource::NanoErgUsd),
+            Err(DataPointSourceError::UnsupportedExchangePair { .. })
+        ));
+    }
+
+    #[test]
+    fn test_failed_request() {
+        let server = StubServer::start(vec![]);
+        let source = make_source(
+            &server,
+            Exchange::KuCoin,
+            PredefinedDataPointSource::NanoErgUsd,
+        );
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::Reqwest(_))
+        ));
+    }
+}
@@ -0,0 +1,447 @@
+//! Datapoint source running an external program and reading the datapoint from its stdout
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{unix_timestamp_now, DataPoint, DataPointSource, DataPointSourceError};
+
+#[derive(Debug, From, Error)]
+pub enum ExternalScriptError {
+    #[error("external script child process error: {0}")]
+    ChildProcess(std::io::Error),
+    #[error("String from bytes error: {0}")]
+    StringFromBytes(std::string::FromUtf8Error),
+    #[error("Parse i64 from string error: {0}")]
+    ParseInt(std::num::ParseIntError),
+    #[error("Parse JSON output error: {0}")]
+    JsonOutput(serde_json::Error),
+    #[error("external script timed out after {0:?} and was killed")]
+    Timeout(Duration),
+    #[error("external script failed with {status}, stderr: {stderr}")]
+    ExitStatus { status: ExitStatus, stderr: String },
+}
+
+/// Format of the script's stdout
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ExternalScriptOutputFormat {
+    /// Datapoint as a single integer
+    Plain,
+    /// `ExternalScriptOutput` as JSON
+    Json,
+}
+
+/// Output of a script in the `Json` output format
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExternalScriptOutput {
+    pub datapoint: i64,
+    /// Unix timestamp (in seconds) of the quote
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    /// Where the script got the quote from
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// External script as given in the oracle config file. Either a bare command or a map of the
+/// fields below.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ExternalScriptSpecSerde", into = "ExternalScriptSpecSerde")]
+pub struct ExternalScriptSpec {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Environment variables set in addition to the inherited ones
+    pub env: BTreeMap<String, String>,
+    pub working_dir: Option<String>,
+    pub timeout_secs: u64,
+    pub output_format: ExternalScriptOutputFormat,
+}
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+impl ExternalScriptSpec {
+    pub fn new(command: String) -> Self {
+        ExternalScriptSpec {
+            command,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            working_dir: None,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            output_format: ExternalScriptOutputFormat::Plain,
+        }
+    }
+}
+
+/// Leaves out the values of `env`, which may hold API keys
+impl std::fmt::Debug for ExternalScriptSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExternalScriptSpec")
+            .field("command", &self.command)
+            .field("env", &self.env.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ExternalScriptSpecSerde {
+    Command(String),
+    Spec {
+        command: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        working_dir: Option<String>,
+        #[serde(default = "default_timeout_secs")]
+        timeout_secs: u64,
+        #[serde(default = "default_output_format")]
+        output_format: ExternalScriptOutputFormat,
+    },
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+fn default_output_format() -> ExternalScriptOutputFormat {
+    ExternalScriptOutputFormat::Plain
+}
+
+impl From<ExternalScriptSpecSerde> for ExternalScriptSpec {
+    fn from(s: ExternalScriptSpecSerde) -> Self {
+        match s {
+            ExternalScriptSpecSerde::Command(command) => ExternalScriptSpec::new(command),
+            ExternalScriptSpecSerde::Spec {
+                command,
+                args,
+                env,
+                working_dir,
+                timeout_secs,
+                output_format,
+            } => ExternalScriptSpec {
+                command,
+                args,
+                env,
+                working_dir,
+                timeout_secs,
+                output_format,
+            },
+        }
+    }
+}
+
+impl From<ExternalScriptSpec> for ExternalScriptSpecSerde {
+    fn from(s: ExternalScriptSpec) -> Self {
+        // keep the short form for specs that only have a command
+        if s == ExternalScriptSpec::new(s.command.clone()) {
+            ExternalScriptSpecSerde::Command(s.command)
+        } else {
+            ExternalScriptSpecSerde::Spec {
+                command: s.command,
+                args: s.args,
+                env: s.env,
+                working_dir: s.working_dir,
+                timeout_secs: s.timeout_secs,
+                output_format: s.output_format,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExternalScript(ExternalScriptSpec);
+
+impl ExternalScript {
+    pub fn new(spec: ExternalScriptSpec) -> Self {
+        ExternalScript(spec)
+    }
+
+    /// Runs the script and returns its stdout, killing it if it does not exit and close its
+    /// output in time
+    fn run(&self) -> Result<String, ExternalScriptError> {
+        let spec = &self.0;
+        let mut command = Command::new(&spec.command);
+        command
+            .args(&spec.args)
+            .envs(&spec.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(working_dir) = &spec.working_dir {
+            command.current_dir(working_dir);
+        }
+        // in its own process group, so that the processes it starts can be killed along with it
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command.spawn()?;
+        // read the pipes in the background so that a chatty script cannot block on a full pipe
+        let stdout_reader = spawn_pipe_reader(child.stdout.take());
+        let stderr_reader = spawn_pipe_reader(child.stderr.take());
+        let timeout = Duration::from_secs(spec.timeout_secs);
+        let deadline = Instant::now() + timeout;
+        let status = match wait_until(&mut child, deadline)? {
+            Some(status) => status,
+            None => {
+                kill_process_group(&mut child)?;
+                return Err(ExternalScriptError::Timeout(timeout));
+            }
+        };
+        // processes the script left running in the background may keep the pipes open
+        let (stdout, stderr) = match (
+            recv_until(&stdout_reader, deadline),
+            recv_until(&stderr_reader, deadline),
+        ) {
+            (Some(stdout), Some(stderr)) => (stdout, stderr),
+            (None, _) | (_, None) => {
+                kill_process_group(&mut child)?;
+                return Err(ExternalScriptError::Timeout(timeout));
+            }
+        };
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+            return Err(ExternalScriptError::ExitStatus { status, stderr });
+        }
+        Ok(String::from_utf8(stdout)?)
+    }
+
+    /// Runs the script and parses its output
+    pub fn get_output(&self) -> Result<ExternalScriptOutput, ExternalScriptError> {
+        let stdout = self.run()?;
+        let stdout = stdout.trim();
+        match self.0.output_format {
+            ExternalScriptOutputFormat::Plain => Ok(ExternalScriptOutput {
+                datapoint: stdout.parse()?,
+                timestamp: None,
+                source: None,
+            }),
+            ExternalScriptOutputFormat::Json => Ok(serde_json::from_str(stdout)?),
+        }
+    }
+}
+
+/// Reads the pipe to the end in a background thread, the content is sent once the pipe is closed
+fn spawn_pipe_reader<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        let _ = sender.send(buf);
+    });
+    receiver
+}
+
+/// Content of the pipe, `None` if it is still open at the deadline
+fn recv_until(reader: &Receiver<Vec<u8>>, deadline: Instant) -> Option<Vec<u8>> {
+    reader
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Waits for the child to exit, returns `None` if it is still running at the deadline
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<Option<ExitStatus>, std::io::Error> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Kills the script and everything still running in its process group
+fn kill_process_group(child: &mut Child) -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    {
+        // the group id is the pid of the script, see `process_group(0)`
+        let group_killed = Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", child.id())])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_or(false, |status| status.success());
+        if group_killed {
+            child.wait()?;
+            return Ok(());
+        }
+    }
+    match child.try_wait()? {
+        // exited already, only the processes it left behind were running
+        Some(_) => Ok(()),
+        None => {
+            child.kill()?;
+            child.wait()?;
+            Ok(())
+        }
+    }
+}
+
+impl DataPointSource for ExternalScript {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+        let output = self.get_output()?;
+        log::debug!("External script {} returned {:?}", self.0.command, output);
+        Ok(DataPoint {
+            value: output.datapoint,
+            // scripts that do not report the time of the quote are taken to quote it right now
+            timestamp: output.timestamp.unwrap_or_else(unix_timestamp_now),
+        })
+    }
+
+    fn name(&self) -> String {
+        self.0.command.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> ExternalScriptSpec {
+        ExternalScriptSpec {
+            args: vec!["-c".to_string(), script.to_string()],
+            ..ExternalScriptSpec::new("sh".to_string())
+        }
+    }
+
+    #[test]
+    fn test_plain_output_with_newline() {
+        let script = ExternalScript::new(sh("echo 12345"));
+        assert_eq!(script.get_datapoint().unwrap().value, 12345);
+    }
+
+    #[test]
+    fn test_args_env_and_working_dir() {
+        let spec = ExternalScriptSpec {
+            command: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                r#"[ "$(pwd)" = "/" ] && echo $(( $1 + OFFSET ))"#.to_string(),
+                "sh".to_string(),
+                "40".to_string(),
+            ],
+            env: vec![("OFFSET".to_string(), "2".to_string())]
+                .into_iter()
+                .collect(),
+            working_dir: Some("/".to_string()),
+            ..ExternalScriptSpec::new("sh".to_string())
+        };
+        assert_eq!(ExternalScript::new(spec).get_datapoint().unwrap().value, 42);
+    }
+
+    #[test]
+    fn test_env_values_not_printed() {
+        let spec = ExternalScriptSpec {
+            env: vec![("API_KEY".to_string(), "secret".to_string())]
+                .into_iter()
+                .collect(),
+            ..sh("echo 1")
+        };
+        let script = ExternalScript::new(spec);
+        assert_eq!(script.name(), "sh");
+        let debug = format!("{:?}", script);
+        assert!(debug.contains("API_KEY"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let spec = ExternalScriptSpec {
+            output_format: ExternalScriptOutputFormat::Json,
+            ..sh(r#"echo '{"datapoint": 7, "timestamp": 1677145536, "source": "test"}'"#)
+        };
+        let script = ExternalScript::new(spec);
+        assert_eq!(
+            script.get_output().unwrap(),
+            ExternalScriptOutput {
+                datapoint: 7,
+                timestamp: Some(1677145536),
+                source: Some("test".to_string()),
+            }
+        );
+        assert_eq!(
+            script.get_datapoint().unwrap(),
+            DataPoint {
+                value: 7,
+                timestamp: 1677145536
+            }
+        );
+    }
+
+    #[test]
+    fn test_exit_code_and_stderr() {
+        let script = ExternalScript::new(sh("echo 1; echo 'no quote' >&2; exit 3"));
+        match script.get_output() {
+            Err(ExternalScriptError::ExitStatus { status, stderr }) => {
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(stderr, "no quote");
+            }
+            res => panic!("expected ExitStatus error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_timeout_kills_script() {
+        let spec = ExternalScriptSpec {
+            timeout_secs: 1,
+            ..sh("sleep 10; echo 1")
+        };
+        let started = Instant::now();
+        assert!(matches!(
+            ExternalScript::new(spec).get_output(),
+            Err(ExternalScriptError::Timeout(_))
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_timeout_with_background_process_holding_stdout() {
+        let spec = ExternalScriptSpec {
+            timeout_secs: 1,
+            ..sh("sleep 100 & echo 1")
+        };
+        let started = Instant::now();
+        assert!(matches!(
+            ExternalScript::new(spec).get_output(),
+            Err(ExternalScriptError::Timeout(_))
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_spec_serde() {
+        let spec: ExternalScriptSpec = serde_yaml::from_str("/path/to/script.sh").unwrap();
+        assert_eq!(
+            spec,
+            ExternalScriptSpec::new("/path/to/script.sh".to_string())
+        );
+        assert_eq!(
+            serde_yaml::to_string(&spec).unwrap().trim(),
+            "/path/to/script.sh"
+        );
+        let spec: ExternalScriptSpec = serde_yaml::from_str(
+            r#"
+command: /path/to/script.sh
+args: [--pair, ERG/USD]
+timeout_secs: 5
+output_format: Json
+            "#,
+        )
+        .unwrap();
+        assert_eq!(spec.args, vec!["--pair", "ERG/USD"]);
+        assert_eq!(spec.timeout_secs, 5);
+        assert_eq!(spec.output_format, ExternalScriptOutputFormat::Json);
+        assert_eq!(spec.working_dir, None);
+    }
+}
@@ -0,0 +1,448 @@
+//! Async datapoint fetching on the tokio runtime with per-attempt timeouts, exponential backoff
+//! between retries and cancellation.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::{Handle, Runtime};
+use tokio_util::sync::CancellationToken;
+
+use super::{DataPoint, DataPointSource, DataPointSourceError, SourceQuote};
+
+/// Handle of the main tokio runtime, set on launch. Datapoint sources fall back to a runtime of
+/// their own if it is not set (e.g. in tests).
+pub static RUNTIME_HANDLE: OnceCell<Handle> = OnceCell::new();
+
+lazy_static! {
+    /// Parent of the cancellation tokens of all datapoint fetches
+    static ref FETCH_CANCELLATION: CancellationToken = CancellationToken::new();
+    /// Cancellation of the fetches of the current main loop iteration
+    pub static ref ITERATION_CANCELLATION: IterationCancellation =
+        IterationCancellation::default();
+}
+
+/// Cancels all pending and future datapoint fetches (e.g. on shutdown)
+pub fn cancel_datapoint_fetches() {
+    FETCH_CANCELLATION.cancel();
+}
+
+/// Token of the current main loop iteration, shared with the sources that fetch during it
+#[derive(Debug, Clone)]
+pub struct IterationCancellation(Arc<Mutex<CancellationToken>>);
+
+impl Default for IterationCancellation {
+    fn default() -> Self {
+        IterationCancellation(Arc::new(Mutex::new(FETCH_CANCELLATION.child_token())))
+    }
+}
+
+impl IterationCancellation {
+    /// Fetches started until the returned scope is dropped are cancelled with it
+    pub fn start_iteration(&self) -> IterationScope {
+        let token = FETCH_CANCELLATION.child_token();
+        *self.0.lock().unwrap() = token.clone();
+        IterationScope(token)
+    }
+
+    fn token(&self) -> CancellationToken {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Cancels the pending fetches of the iteration when dropped, i.e. when the iteration returns
+#[must_use]
+pub struct IterationScope(CancellationToken);
+
+impl Drop for IterationScope {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[async_trait]
+pub trait AsyncDataPointSource: std::fmt::Debug + Send + Sync {
+    async fn get_datapoint_async(&self) -> Result<DataPoint, DataPointSourceError>;
+
+    /// See `DataPointSource::name`
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// See `DataPointSource::source_quotes`
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        Vec::new()
+    }
+}
+
+/// Retry policy for a single datapoint source
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FetchPolicy {
+    /// Timeout of a single attempt
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Total number of attempts
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// Delay before the first retry, doubled on every subsequent retry
+    #[serde(default = "default_initial_backoff_millis")]
+    pub initial_backoff_millis: u64,
+    #[serde(default = "default_max_backoff_millis")]
+    pub max_backoff_millis: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_initial_backoff_millis() -> u64 {
+    500
+}
+
+fn default_max_backoff_millis() -> u64 {
+    8000
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        FetchPolicy {
+            timeout_secs: default_timeout_secs(),
+            attempts: default_attempts(),
+            initial_backoff_millis: default_initial_backoff_millis(),
+            max_backoff_millis: default_max_backoff_millis(),
+        }
+    }
+}
+
+impl FetchPolicy {
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    fn max_backoff(&self) -> Duration {
+        Duration::from_millis(self.max_backoff_millis)
+    }
+
+    /// Delay before the given retry (starting from 1)
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        Duration::from_millis(
+            self.initial_backoff_millis
+                .saturating_mul(factor)
+                .min(self.max_backoff_millis),
+        )
+    }
+}
+
+/// Gets a datapoint from the source according to the policy, making at least one attempt
+pub async fn fetch_datapoint(
+    source: &dyn AsyncDataPointSource,
+    policy: &FetchPolicy,
+) -> Result<DataPoint, DataPointSourceError> {
+    let mut attempt = 1;
+    loop {
+        let res = match tokio::time::timeout(policy.timeout(), source.get_datapoint_async()).await {
+            Ok(res) => res,
+            Err(_) => Err(DataPointSourceError::Timeout(policy.timeout())),
+        };
+        match res {
+            Ok(datapoint) => return Ok(datapoint),
+            Err(e) if attempt >= policy.attempts => return Err(e),
+            // no point in retrying if the API asks us to wait longer than we would back off
+            Err(
+                e @ DataPointSourceError::RateLimited {
+                    retry_after: Some(retry_after),
+                    ..
+                },
+            ) if retry_after > policy.max_backoff() => return Err(e),
+            Err(e) => {
+                let backoff = if let DataPointSourceError::RateLimited {
+                    retry_after: Some(retry_after),
+                    ..
+                } = &e
+                {
+                    policy.backoff(attempt).max(*retry_after)
+                } else {
+                    policy.backoff(attempt)
+                };
+                log::warn!(
+                    "Failed to get datapoint from source {}: {}, retrying in {:?} ...",
+                    source.name(),
+                    e,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Runs a blocking source on the blocking thread pool of the runtime
+#[derive(Debug, Clone)]
+pub struct BlockingSource(pub Arc<dyn DataPointSource + Send + Sync>);
+
+#[async_trait]
+impl AsyncDataPointSource for BlockingSource {
+    async fn get_datapoint_async(&self) -> Result<DataPoint, DataPointSourceError> {
+        let source = self.0.clone();
+        // on timeout or cancellation the blocking call keeps running until its own (HTTP) timeout
+        // and its result is discarded
+        tokio::task::spawn_blocking(move || source.get_datapoint()).await?
+    }
+
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        self.0.source_quotes()
+    }
+}
+
+/// Applies a `FetchPolicy` to a source
+#[derive(Debug)]
+pub struct RetryingSource {
+    source: Box<dyn AsyncDataPointSource>,
+    policy: FetchPolicy,
+}
+
+impl RetryingSource {
+    pub fn new(source: Box<dyn AsyncDataPointSource>, policy: FetchPolicy) -> Self {
+        RetryingSource { source, policy }
+    }
+}
+
+#[async_trait]
+impl AsyncDataPointSource for RetryingSource {
+    async fn get_datapoint_async(&self) -> Result<DataPoint, DataPointSourceError> {
+        fetch_datapoint(&*self.source, &self.policy).await
+    }
+
+    fn name(&self) -> String {
+        self.source.name()
+    }
+
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        self.source.source_quotes()
+    }
+}
+
+/// Exposes an async source to the (blocking) main loop. The fetch is driven by the main tokio
+/// runtime and is aborted when the iteration it was started in is over, or when
+/// `cancel_datapoint_fetches` is called.
+#[derive(Debug)]
+pub struct RuntimeBoundSource {
+    source: Arc<dyn AsyncDataPointSource>,
+    cancel: IterationCancellation,
+    fallback_runtime: OnceCell<Runtime>,
+}
+
+impl RuntimeBoundSource {
+    pub fn new(source: Arc<dyn AsyncDataPointSource>, cancel: IterationCancellation) -> Self {
+        RuntimeBoundSource {
+            source,
+            cancel,
+            fallback_runtime: OnceCell::new(),
+        }
+    }
+}
+
+impl DataPointSource for RuntimeBoundSource {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+        let cancel = self.cancel.token().child_token();
+        let source = self.source.clone();
+        let fetch = async move {
+            tokio::select! {
+                _ = cancel.cancelled() => Err(DataPointSourceError::Cancelled),
+                res = source.get_datapoint_async() => res,
+            }
+        };
+        match RUNTIME_HANDLE.get() {
+            Some(handle) => handle.block_on(fetch),
+            None => self
+                .fallback_runtime
+                .get_or_try_init(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                })
+                .map_err(DataPointSourceError::Runtime)?
+                .block_on(fetch),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.source.name()
+    }
+
+    fn source_quotes(&self) -> Vec<SourceQuote> {
+        self.source.source_quotes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    /// Fails the first `failures` calls, sleeping for `delay` on every call
+    #[derive(Debug)]
+    struct FlakySource {
+        failures: u32,
+        delay: Duration,
+        calls: AtomicU32,
+    }
+
+    impl FlakySource {
+        fn new(failures: u32, delay: Duration) -> Self {
+            FlakySource {
+                failures,
+                delay,
+                calls: AtomicU32::new(0),
+            }
+        }
+    }
+
+    impl DataPointSource for FlakySource {
+        fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+            std::thread::sleep(self.delay);
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(DataPointSourceError::JsonMissingField)
+            } else {
+                Ok(DataPoint::now(42))
+            }
+        }
+    }
+
+    fn policy(timeout_secs: u64, attempts: u32) -> FetchPolicy {
+        FetchPolicy {
+            timeout_secs,
+            attempts,
+            initial_backoff_millis: 10,
+            max_backoff_millis: 20,
+        }
+    }
+
+    fn make_source(source: Arc<FlakySource>, policy: FetchPolicy) -> RuntimeBoundSource {
+        RuntimeBoundSource::new(
+            Arc::new(RetryingSource::new(
+                Box::new(BlockingSource(source)),
+                policy,
+            )),
+            IterationCancellation::default(),
+        )
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = FetchPolicy {
+            timeout_secs: 1,
+            attempts: 10,
+            initial_backoff_millis: 100,
+            max_backoff_millis: 350,
+        };
+        let backoffs: Vec<u64> = (1..5)
+            .map(|retry| policy.backoff(retry).as_millis() as u64)
+            .collect();
+        assert_eq!(backoffs, vec![100, 200, 350, 350]);
+        assert_eq!(policy.backoff(u32::MAX).as_millis(), 350);
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let flaky = Arc::new(FlakySource::new(2, Duration::ZERO));
+        let source = make_source(flaky.clone(), policy(1, 3));
+        assert_eq!(source.get_datapoint().unwrap().value, 42);
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_attempts_exhausted() {
+        let flaky = Arc::new(FlakySource::new(5, Duration::ZERO));
+        let source = make_source(flaky.clone(), policy(1, 2));
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let flaky = Arc::new(FlakySource::new(0, Duration::from_millis(1500)));
+        let source = make_source(flaky, policy(1, 1));
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::Timeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let flaky = Arc::new(FlakySource::new(0, Duration::from_secs(2)));
+        let source = make_source(flaky, policy(10, 1));
+        let scope = source.cancel.start_iteration();
+        // the iteration moves on while the slow fetch is pending
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(scope);
+        });
+        let started = std::time::Instant::now();
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::Cancelled)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // the next iteration fetches again
+        let _scope = source.cancel.start_iteration();
+        assert_eq!(source.get_datapoint().unwrap().value, 42);
+    }
+
+    #[test]
+    fn test_rate_limited_is_not_retried_before_retry_after() {
+        #[derive(Debug)]
+        struct RateLimitedSource {
+            calls: AtomicU32,
+        }
+
+        impl DataPointSource for RateLimitedSource {
+            fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                Err(DataPointSourceError::RateLimited {
+                    url: "https://example.com".to_string(),
+                    retry_after: Some(Duration::from_secs(60)),
+                })
+            }
+        }
+
+        let rate_limited = Arc::new(RateLimitedSource {
+            calls: AtomicU32::new(0),
+        });
+        let source = RuntimeBoundSource::new(
+            Arc::new(RetryingSource::new(
+                Box::new(BlockingSource(rate_limited.clone())),
+                policy(1, 3),
+            )),
+            IterationCancellation::default(),
+        );
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::RateLimited { .. })
+        ));
+        assert_eq!(rate_limited.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_default_policy_from_empty_config() {
+        let policy: FetchPolicy = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(policy, FetchPolicy::default());
+    }
+}
@@ -0,0 +1,157 @@
+//! HTTP requests of the datapoint sources. All sources share a single client (reusing
+//! connections), responses are cached for a short time per URL and hosts answering with
+//! `429 Too Many Requests` are not queried again until their `Retry-After` has passed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+
+use super::{unix_timestamp_now, DataPointSourceError};
+
+/// Timeout for requests to the exchange APIs
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a response is served from the cache
+const CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Used for hosts that answer with 429 without a (numeric) `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+type CacheKey = (String, BTreeMap<String, String>);
+
+/// Parsed response body with the time it was fetched, which is kept for responses served from
+/// the cache
+#[derive(Debug, Clone)]
+pub(super) struct JsonResponse {
+    pub body: serde_json::Value,
+    /// Unix timestamp (in seconds) of the request
+    pub fetched_at: i64,
+}
+
+lazy_static! {
+    static ref HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::new();
+    static ref RESPONSE_CACHE: Mutex<HashMap<CacheKey, (Instant, JsonResponse)>> =
+        Mutex::new(HashMap::new());
+    /// Hosts (with port) that rate limited us, with the time we may query them again
+    static ref RATE_LIMITED_HOSTS: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Sends a GET request and parses the response body as JSON. Responses younger than `CACHE_TTL`
+/// are served from the cache, stamped with the time of the original request.
+pub(super) fn get_json(
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    timeout: Duration,
+) -> Result<JsonResponse, DataPointSourceError> {
+    let key = (url.to_string(), headers.clone());
+    if let Some((fetched_at, resp)) = RESPONSE_CACHE.lock().unwrap().get(&key) {
+        if fetched_at.elapsed() < CACHE_TTL {
+            log::trace!("Serving {} from cache", url);
+            return Ok(resp.clone());
+        }
+    }
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            let port = u.port_or_known_default()?;
+            u.host_str().map(|h| format!("{}:{}", h, port))
+        })
+        .unwrap_or_else(|| url.to_string());
+    if let Some(until) = RATE_LIMITED_HOSTS.lock().unwrap().get(&host) {
+        let now = Instant::now();
+        if *until > now {
+            return Err(DataPointSourceError::RateLimited {
+                url: url.to_string(),
+                retry_after: Some(*until - now),
+            });
+        }
+    }
+    let mut req = HTTP_CLIENT.get(url).timeout(timeout);
+    for (name, value) in headers {
+        req = req.header(name, value);
+    }
+    let fetched_at = unix_timestamp_now();
+    let resp = req.send()?;
+    if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = resp
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        log::warn!(
+            "Rate limited by {}, not querying it for {:?}",
+            host,
+            retry_after.unwrap_or(DEFAULT_RETRY_AFTER)
+        );
+        RATE_LIMITED_HOSTS.lock().unwrap().insert(
+            host,
+            Instant::now() + retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+        );
+        return Err(DataPointSourceError::RateLimited {
+            url: url.to_string(),
+            retry_after,
+        });
+    }
+    let resp = JsonResponse {
+        body: serde_json::from_str(&resp.error_for_status()?.text()?)?,
+        fetched_at,
+    };
+    RESPONSE_CACHE
+        .lock()
+        .unwrap()
+        .insert(key, (Instant::now(), resp.clone()));
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    #[test]
+    fn test_cached_response() {
+        let server = StubServer::start(vec![("/price", r#"{"price": 1.5}"#)]);
+        let url = format!("{}/price", server.base_url());
+        let first = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT).unwrap();
+        assert_eq!(first.body["price"].as_f64(), Some(1.5));
+        std::thread::sleep(Duration::from_millis(1100));
+        for _ in 0..2 {
+            let resp = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT).unwrap();
+            assert_eq!(resp.body["price"].as_f64(), Some(1.5));
+            // cached responses keep the time of the original request
+            assert_eq!(resp.fetched_at, first.fetched_at);
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_rate_limited() {
+        let server = StubServer::start_raw(vec![(
+            "/price",
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        )]);
+        let url = format!("{}/price", server.base_url());
+        assert!(matches!(
+            get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT),
+            Err(DataPointSourceError::RateLimited {
+                retry_after: Some(d),
+                ..
+            }) if d == Duration::from_secs(30)
+        ));
+        // the host is not queried again until the retry-after has passed
+        let other_url = format!("{}/other", server.base_url());
+        assert!(matches!(
+            get_json(&other_url, &BTreeMap::new(), DEFAULT_TIMEOUT),
+            Err(DataPointSourceError::RateLimited {
+                retry_after: Some(_),
+                ..
+            })
+        ));
+        assert_eq!(server.requests().len(), 1);
+    }
+}
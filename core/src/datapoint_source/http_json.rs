@@ -0,0 +1,218 @@
+//! Datapoint source fully described in the oracle config: the price is fetched from an HTTP
+//! endpoint returning JSON, extracted with a JSON pointer and converted to the posted datapoint.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::http::get_json;
+use super::price::ratio_in_smallest_units;
+use super::{DataPoint, DataPointSource, DataPointSourceError, Price};
+
+/// Configuration of `HttpJsonSource` as given in the oracle config file.
+///
+/// The datapoint is computed as `price` (or `1 / price` if `invert` is set), multiplied by
+/// `multiply` and by `10^decimals`, and rounded down to an integer. The computation is done in
+/// fixed-point decimals, prices that are zero, negative or result in a datapoint out of the `i64`
+/// range are refused.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HttpJsonSourceConfig {
+    /// URL to send the GET request to
+    pub url: String,
+    /// Additional HTTP headers (e.g. API keys)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// JSON pointer (RFC 6901) to the price in the response, e.g. `/ergo/usd`
+    pub json_pointer: String,
+    /// Take the reciprocal of the price (e.g. Erg/USD -> USD/Erg)
+    #[serde(default)]
+    pub invert: bool,
+    #[serde(default = "default_multiply")]
+    pub multiply: f64,
+    /// Number of decimal places to shift the price by (e.g. 9 for nanoErgs)
+    #[serde(default)]
+    pub decimals: u32,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl HttpJsonSourceConfig {
+    /// URL without the query string, which may hold an API key
+    fn url_without_query(&self) -> &str {
+        self.url.split('?').next().unwrap_or_default()
+    }
+}
+
+/// Leaves out the headers (API keys) and the query string of the URL
+impl std::fmt::Debug for HttpJsonSourceConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpJsonSourceConfig")
+            .field("url", &self.url_without_query())
+            .field("json_pointer", &self.json_pointer)
+            .finish_non_exhaustive()
+    }
+}
+
+fn default_multiply() -> f64 {
+    1.0
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Clone)]
+pub struct HttpJsonSource {
+    config: HttpJsonSourceConfig,
+}
+
+impl std::fmt::Debug for HttpJsonSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HttpJsonSource").field(&self.config).finish()
+    }
+}
+
+impl HttpJsonSource {
+    pub fn new(config: HttpJsonSourceConfig) -> Self {
+        HttpJsonSource { config }
+    }
+
+    /// Extracts the price from the response and converts it into a datapoint
+    fn datapoint_from_json(&self, resp: &serde_json::Value) -> Result<i64, DataPointSourceError> {
+        let price = json_to_price(
+            resp.pointer(&self.config.json_pointer)
+                .unwrap_or(&serde_json::Value::Null),
+        )?;
+        let multiply = Price::from_f64(self.config.multiply)?;
+        let decimals = self.config.decimals;
+        let datapoint = if self.config.invert {
+            ratio_in_smallest_units(multiply, price, decimals)?
+        } else {
+            price.checked_mul(multiply)?.to_smallest_units(decimals)?
+        };
+        Ok(datapoint)
+    }
+}
+
+impl DataPointSource for HttpJsonSource {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+        let resp = get_json(
+            &self.config.url,
+            &self.config.headers,
+            Duration::from_secs(self.config.timeout_secs),
+        )?;
+        Ok(DataPoint {
+            value: self.datapoint_from_json(&resp.body)?,
+            timestamp: resp.fetched_at,
+        })
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{}#{}",
+            self.config.url_without_query(),
+            self.config.json_pointer
+        )
+    }
+}
+
+/// Reads a price that is either a JSON number or a numeric string (some exchanges return prices
+/// as strings to preserve precision)
+pub(super) fn json_to_price(value: &serde_json::Value) -> Result<Price, DataPointSourceError> {
+    match value {
+        serde_json::Value::Number(n) => Ok(n.to_string().parse()?),
+        serde_json::Value::String(s) => Ok(s.parse()?),
+        serde_json::Value::Null
+        | serde_json::Value::Bool(_)
+        | serde_json::Value::Array(_)
+        | serde_json::Value::Object(_) => Err(DataPointSourceError::JsonMissingField),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::PriceError;
+    use super::*;
+
+    fn make_source(yaml: &str) -> HttpJsonSource {
+        HttpJsonSource::new(serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn test_coingecko_nanoerg_usd() {
+        let source = make_source(
+            r#"
+url: https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD
+json_pointer: /ergo/usd
+invert: true
+decimals: 9
+            "#,
+        );
+        let resp = serde_json::json!({ "ergo": { "usd": 2.0 } });
+        assert_eq!(source.datapoint_from_json(&resp).unwrap(), 500_000_000);
+    }
+
+    #[test]
+    fn test_string_price_and_multiply() {
+        let source = make_source(
+            r#"
+url: https://example.com
+headers:
+  X-Api-Key: secret
+json_pointer: /data/0/price
+multiply: 2.5
+decimals: 2
+            "#,
+        );
+        let resp = serde_json::json!({ "data": [{ "price": "1.5" }] });
+        assert_eq!(source.datapoint_from_json(&resp).unwrap(), 375);
+    }
+
+    #[test]
+    fn test_secrets_not_printed() {
+        let source = make_source(
+            r#"
+url: https://example.com/price?apikey=secret1
+headers:
+  X-Api-Key: secret2
+json_pointer: /price
+            "#,
+        );
+        assert_eq!(source.name(), "https://example.com/price#/price");
+        let debug = format!("{:?}", source);
+        assert!(debug.contains("https://example.com/price"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let source = make_source("url: https://example.com\njson_pointer: /ergo/eur");
+        let resp = serde_json::json!({ "ergo": { "usd": 2.0 } });
+        assert!(matches!(
+            source.datapoint_from_json(&resp),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_price() {
+        let source = make_source("url: https://example.com\njson_pointer: /price\ninvert: true");
+        let resp = serde_json::json!({ "price": 0.0 });
+        assert!(matches!(
+            source.datapoint_from_json(&resp),
+            Err(DataPointSourceError::Price(PriceError::Zero))
+        ));
+        let resp = serde_json::json!({ "price": "-1.5" });
+        assert!(matches!(
+            source.datapoint_from_json(&resp),
+            Err(DataPointSourceError::Price(PriceError::Negative))
+        ));
+        // would be truncated to 0 in f64
+        let resp = serde_json::json!({ "price": 1e12 });
+        assert!(matches!(
+            source.datapoint_from_json(&resp),
+            Err(DataPointSourceError::Price(PriceError::OutOfRange(_)))
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+//! Kraken public ticker API
+
+use std::collections::BTreeMap;
+
+use super::http::{get_json, DEFAULT_TIMEOUT};
+use super::http_json::json_to_price;
+use super::price::Quote;
+use super::DataPointSourceError;
+
+pub const BASE_URL: &str = "https://api.kraken.com";
+
+/// Last trade closed price of the `pair` (e.g. `ADAUSD`)
+pub fn get_price(base_url: &str, pair: &str) -> Result<Quote, DataPointSourceError> {
+    let url = format!("{}/0/public/Ticker?pair={}", base_url, pair);
+    let resp = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT)?;
+    if let Some(errors) = resp.body["error"].as_array().filter(|e| !e.is_empty()) {
+        return Err(DataPointSourceError::InvalidDatapoint(format!(
+            "Kraken returned errors: {:?}",
+            errors
+        )));
+    }
+    // the result is keyed by Kraken's internal pair name (e.g. `XXBTZUSD` for `XBTUSD`)
+    let ticker = resp.body["result"]
+        .as_object()
+        .and_then(|result| result.values().next())
+        .ok_or(DataPointSourceError::JsonMissingField)?;
+    Ok(Quote {
+        price: json_to_price(&ticker["c"][0])?,
+        timestamp: resp.fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let server = StubServer::start(vec![(
+            "/0/public/Ticker?pair=ADAUSD",
+            include_str!("fixtures/kraken_adausd.json"),
+        )]);
+        assert_eq!(
+            get_price(&server.base_url(), "ADAUSD").unwrap().price,
+            "0.382714".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_response() {
+        let server = StubServer::start(vec![(
+            "/0/public/Ticker",
+            r#"{"error":["EQuery:Unknown asset pair"]}"#,
+        )]);
+        assert!(matches!(
+            get_price(&server.base_url(), "ERGUSD"),
+            Err(DataPointSourceError::InvalidDatapoint(_))
+        ));
+    }
+}
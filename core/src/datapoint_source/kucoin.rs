@@ -0,0 +1,54 @@
+//! KuCoin level 1 market data API
+
+use std::collections::BTreeMap;
+
+use super::http::{get_json, DEFAULT_TIMEOUT};
+use super::http_json::json_to_price;
+use super::price::Quote;
+use super::DataPointSourceError;
+
+pub const BASE_URL: &str = "https://api.kucoin.com";
+
+/// Last traded price of the `symbol` pair (e.g. `ERG-USDT`)
+pub fn get_price(base_url: &str, symbol: &str) -> Result<Quote, DataPointSourceError> {
+    let url = format!(
+        "{}/api/v1/market/orderbook/level1?symbol={}",
+        base_url, symbol
+    );
+    let resp = get_json(&url, &BTreeMap::new(), DEFAULT_TIMEOUT)?;
+    Ok(Quote {
+        price: json_to_price(&resp.body["data"]["price"])?,
+        timestamp: resp.fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::StubServer;
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let server = StubServer::start(vec![(
+            "/api/v1/market/orderbook/level1?symbol=ERG-USDT",
+            include_str!("fixtures/kucoin_erg_usdt.json"),
+        )]);
+        assert_eq!(
+            get_price(&server.base_url(), "ERG-USDT").unwrap().price,
+            "1.5187".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unknown_symbol() {
+        // KuCoin answers with `data: null` for unknown symbols
+        let server = StubServer::start(vec![(
+            "/api/v1/market/orderbook/level1",
+            r#"{"code":"200000","data":null}"#,
+        )]);
+        assert!(matches!(
+            get_price(&server.base_url(), "ERG-XAU"),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+    }
+}
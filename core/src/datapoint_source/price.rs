@@ -0,0 +1,378 @@
+//! Checked fixed-point price arithmetic. Prices quoted by the APIs are parsed exactly into
+//! decimals and converted into datapoints ("price of X in Y, expressed in smallest units of Z")
+//! with integer arithmetic, failing explicitly where `f64` would silently lose precision, truncate
+//! to zero or overflow.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+/// Number of decimal places of nanoErgs
+pub const NANO_ERG_DECIMALS: u32 = 9;
+
+/// Number of decimal places of Lovelaces
+pub const LOVELACE_DECIMALS: u32 = 6;
+
+/// Digits beyond this are truncated (more than any API quotes, and products of two mantissas
+/// still fit into an u128)
+const MAX_SIGNIFICANT_DIGITS: u32 = 19;
+
+/// Prices with larger (absolute) decimal exponents are refused
+const MAX_EXPONENT: i64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PriceError {
+    #[error("price is zero")]
+    Zero,
+    #[error("price is negative")]
+    Negative,
+    #[error("price is NaN or infinite")]
+    NotFinite,
+    #[error("invalid price {0:?}")]
+    Invalid(String),
+    #[error("price {0} is out of range")]
+    OutOfRange(String),
+}
+
+/// Positive decimal price `mantissa * 10^exponent` with at most `MAX_SIGNIFICANT_DIGITS` digits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Price {
+    mantissa: u128,
+    exponent: i32,
+}
+
+impl Price {
+    pub const ONE: Price = Price {
+        mantissa: 1,
+        exponent: 0,
+    };
+
+    /// Price of `mantissa * 10^exponent`, digits beyond `MAX_SIGNIFICANT_DIGITS` are truncated
+    pub fn new(mantissa: u128, exponent: i64) -> Result<Price, PriceError> {
+        if mantissa == 0 {
+            return Err(PriceError::Zero);
+        }
+        let (mut mantissa, mut exponent) = (mantissa, exponent);
+        while mantissa >= 10u128.pow(MAX_SIGNIFICANT_DIGITS) || mantissa % 10 == 0 {
+            mantissa /= 10;
+            exponent = exponent.saturating_add(1);
+        }
+        if exponent.checked_abs().map_or(true, |e| e > MAX_EXPONENT) {
+            return Err(PriceError::OutOfRange(format!("{}e{}", mantissa, exponent)));
+        }
+        Ok(Price {
+            mantissa,
+            exponent: exponent as i32,
+        })
+    }
+
+    pub fn from_f64(price: f64) -> Result<Price, PriceError> {
+        if !price.is_finite() {
+            return Err(PriceError::NotFinite);
+        }
+        if price < 0.0 {
+            return Err(PriceError::Negative);
+        }
+        // shortest representation that round-trips, e.g. `1.52e0`
+        format!("{:e}", price).parse()
+    }
+
+    pub fn checked_mul(self, other: Price) -> Result<Price, PriceError> {
+        Price::new(
+            self.mantissa * other.mantissa,
+            self.exponent as i64 + other.exponent as i64,
+        )
+    }
+
+    /// The price in units of `10^-decimals`, rounded down (e.g. 1.5 USD with 2 decimals is 150
+    /// cents)
+    pub fn to_smallest_units(self, decimals: u32) -> Result<i64, PriceError> {
+        ratio_in_smallest_units(self, Price::ONE, decimals)
+    }
+
+    /// How much of the priced asset a single unit of the quote currency buys, in units of
+    /// `10^-decimals` of the asset, rounded down (e.g. nanoErgs per USD for the Erg price in USD)
+    pub fn inverse_in_smallest_units(self, decimals: u32) -> Result<i64, PriceError> {
+        ratio_in_smallest_units(Price::ONE, self, decimals)
+    }
+}
+
+/// Price as quoted by an exchange
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quote {
+    pub price: Price,
+    /// Unix timestamp (in seconds) of the request the price was read from
+    pub timestamp: i64,
+}
+
+impl std::fmt::Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}e{}", self.mantissa, self.exponent)
+    }
+}
+
+impl FromStr for Price {
+    type Err = PriceError;
+
+    /// Parses decimals as quoted by the APIs, e.g. `0.3825`, `1946.61`, `1.5e-7`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PriceError::Invalid(s.to_string());
+        let trimmed = s.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (decimal, mut exponent) = match unsigned.split_once(|c: char| c == 'e' || c == 'E') {
+            Some((decimal, exponent)) => (decimal, exponent.parse::<i64>().map_err(|_| invalid())?),
+            None => (unsigned, 0),
+        };
+        let (int_part, frac_part) = decimal.split_once('.').unwrap_or((decimal, ""));
+        let digits: Vec<u32> = int_part
+            .chars()
+            .chain(frac_part.chars())
+            .map(|c| c.to_digit(10))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        if digits.is_empty() || exponent.checked_abs().map_or(true, |e| e > MAX_EXPONENT) {
+            return Err(invalid());
+        }
+        exponent -= frac_part.len() as i64;
+        let mut mantissa: u128 = 0;
+        let mut significant_digits = 0;
+        for digit in digits {
+            if significant_digits < MAX_SIGNIFICANT_DIGITS {
+                mantissa = mantissa * 10 + digit as u128;
+                if mantissa > 0 {
+                    significant_digits += 1;
+                }
+            } else {
+                // truncate the digits that do not fit
+                exponent += 1;
+            }
+        }
+        if mantissa == 0 {
+            return Err(PriceError::Zero);
+        }
+        if negative {
+            return Err(PriceError::Negative);
+        }
+        Price::new(mantissa, exponent)
+    }
+}
+
+/// `numerator / denominator` in units of `10^-decimals`, rounded down. Fails if the result does
+/// not fit into an i64 or rounds down to zero.
+pub fn ratio_in_smallest_units(
+    numerator: Price,
+    denominator: Price,
+    decimals: u32,
+) -> Result<i64, PriceError> {
+    let scale = numerator.exponent as i64 - denominator.exponent as i64 + decimals as i64;
+    match scaled_div(numerator.mantissa, denominator.mantissa, scale) {
+        Some(units) if units > 0 => Ok(units),
+        _ => Err(PriceError::OutOfRange(format!(
+            "{} / {} with {} decimals",
+            numerator, denominator, decimals
+        ))),
+    }
+}
+
+/// `a * 10^scale / b` rounded down, `None` if the result does not fit into an i64
+fn scaled_div(a: u128, b: u128, scale: i64) -> Option<i64> {
+    let mut quotient = a / b;
+    if scale < 0 {
+        for _ in 0..scale.unsigned_abs() {
+            if quotient == 0 {
+                break;
+            }
+            quotient /= 10;
+        }
+    } else {
+        // long division, one decimal digit at a time
+        let mut remainder = a % b;
+        for _ in 0..scale {
+            remainder *= 10;
+            quotient = quotient.checked_mul(10)? + remainder / b;
+            remainder %= b;
+            if quotient > i64::MAX as u128 {
+                return None;
+            }
+        }
+    }
+    i64::try_from(quotient).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Mantissas with more digits are truncated
+    const MAX_MANTISSA: u64 = 10_000_000_000_000_000_000;
+
+    fn price(s: &str) -> Price {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(price("1.52"), Price::new(152, -2).unwrap());
+        assert_eq!(price("0.38250000"), Price::new(3825, -4).unwrap());
+        assert_eq!(
+            price("1946.6139184317225316"),
+            price("1946.613918431722531")
+        );
+        assert_eq!(price("1.5e-7"), Price::new(15, -8).unwrap());
+        assert_eq!(price("+2E3"), Price::new(2, 3).unwrap());
+        assert_eq!(price(".5"), price("0.5"));
+        assert_eq!("0.000".parse::<Price>(), Err(PriceError::Zero));
+        assert_eq!("-1.5".parse::<Price>(), Err(PriceError::Negative));
+        for invalid in ["", ".", "abc", "1.2.3", "1e", "1e99999", "NaN", "0x10"] {
+            assert!(
+                matches!(invalid.parse::<Price>(), Err(PriceError::Invalid(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(Price::from_f64(1.52), Ok(price("1.52")));
+        assert_eq!(Price::from_f64(1e-7), Ok(price("0.0000001")));
+        assert_eq!(Price::from_f64(0.0), Err(PriceError::Zero));
+        assert_eq!(Price::from_f64(-0.0), Err(PriceError::Zero));
+        assert_eq!(Price::from_f64(-1.0), Err(PriceError::Negative));
+        assert_eq!(Price::from_f64(f64::NAN), Err(PriceError::NotFinite));
+        assert_eq!(Price::from_f64(f64::INFINITY), Err(PriceError::NotFinite));
+    }
+
+    #[test]
+    fn test_conversions() {
+        // nanoErgs per USD for 1.52 USD per Erg
+        assert_eq!(
+            price("1.52").inverse_in_smallest_units(NANO_ERG_DECIMALS),
+            Ok(657894736)
+        );
+        // nanoErgs per XAU from ERG/USD and PAXG/USD
+        assert_eq!(
+            ratio_in_smallest_units(price("1946.2"), price("1.5187"), NANO_ERG_DECIMALS),
+            Ok(1281490748666)
+        );
+        assert_eq!(price("1.5").to_smallest_units(2), Ok(150));
+        assert_eq!(price("2.5").checked_mul(price("1.5")), Ok(price("3.75")));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        // f64 would give 0 and i64::MAX (saturated) here
+        assert!(matches!(
+            price("1e12").inverse_in_smallest_units(NANO_ERG_DECIMALS),
+            Err(PriceError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            price("1e-12").inverse_in_smallest_units(NANO_ERG_DECIMALS),
+            Err(PriceError::OutOfRange(_))
+        ));
+        assert_eq!(
+            price("1e-9").inverse_in_smallest_units(NANO_ERG_DECIMALS),
+            Ok(1_000_000_000_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_extreme_exponents() {
+        assert!(matches!(
+            "1e-9223372036854775808".parse::<Price>(),
+            Err(PriceError::Invalid(_))
+        ));
+        assert!(matches!(
+            "1e9223372036854775807".parse::<Price>(),
+            Err(PriceError::Invalid(_))
+        ));
+        assert!(matches!(
+            Price::new(1, i64::MIN),
+            Err(PriceError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            Price::new(10, i64::MAX),
+            Err(PriceError::OutOfRange(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_roundtrip(mantissa in 1u64..=u64::MAX, exponent in -40i64..40) {
+            let expected = Price::new(mantissa as u128, exponent).unwrap();
+            let parsed = format!("{}e{}", mantissa, exponent).parse::<Price>();
+            prop_assert_eq!(parsed, Ok(expected));
+            prop_assert_eq!(expected.to_string().parse::<Price>(), Ok(expected));
+        }
+
+        #[test]
+        fn test_inverse_is_exact(nanos in 1u64..MAX_MANTISSA, decimals in 0u32..=9) {
+            // price of `nanos * 10^-9`
+            let res = Price::new(nanos as u128, -9)
+                .unwrap()
+                .inverse_in_smallest_units(decimals);
+            let expected = 10u128.pow(decimals + 9) / nanos as u128;
+            if expected == 0 || expected > i64::MAX as u128 {
+                prop_assert!(matches!(res, Err(PriceError::OutOfRange(_))));
+            } else {
+                prop_assert_eq!(res, Ok(expected as i64));
+            }
+        }
+
+        #[test]
+        fn test_to_smallest_units_is_exact(
+            mantissa in 1u64..MAX_MANTISSA,
+            exponent in -20i64..=0,
+            decimals in 0u32..=9,
+        ) {
+            let res = Price::new(mantissa as u128, exponent)
+                .unwrap()
+                .to_smallest_units(decimals);
+            let expected =
+                mantissa as u128 * 10u128.pow(decimals) / 10u128.pow((-exponent) as u32);
+            if expected == 0 || expected > i64::MAX as u128 {
+                prop_assert!(matches!(res, Err(PriceError::OutOfRange(_))));
+            } else {
+                prop_assert_eq!(res, Ok(expected as i64));
+            }
+        }
+
+        #[test]
+        fn test_from_f64_never_panics(p in any::<f64>()) {
+            match Price::from_f64(p) {
+                Ok(price) => {
+                    prop_assert!(p > 0.0);
+                    let _ = price.inverse_in_smallest_units(NANO_ERG_DECIMALS);
+                    let _ = price.to_smallest_units(NANO_ERG_DECIMALS);
+                }
+                Err(_) => prop_assert!(!(p > 0.0 && p.is_finite())),
+            }
+        }
+
+        #[test]
+        fn test_matches_f64_for_sane_prices(p in 1e-6f64..1e6) {
+            let datapoint = Price::from_f64(p)
+                .unwrap()
+                .inverse_in_smallest_units(NANO_ERG_DECIMALS)
+                .unwrap();
+            let approx = 1e9 / p;
+            prop_assert!((datapoint as f64 - approx).abs() <= approx * 1e-12 + 1.0);
+        }
+
+        #[test]
+        fn test_any_str_never_panics(s in "\\PC*") {
+            let _ = s.parse::<Price>();
+        }
+
+        #[test]
+        fn test_any_exponent_never_panics(mantissa in any::<u128>(), exponent in any::<i64>()) {
+            let _ = Price::new(mantissa, exponent);
+            let _ = format!("{}e{}", mantissa, exponent).parse::<Price>();
+        }
+    }
+}
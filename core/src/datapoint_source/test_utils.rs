@@ -0,0 +1,95 @@
+//! Local HTTP server serving recorded exchange responses to the datapoint source tests
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+pub(crate) struct StubServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl StubServer {
+    /// Starts a server answering requests whose target (path and query) starts with a route
+    /// prefix with the route's JSON body, and all other requests with 404
+    pub(crate) fn start(routes: Vec<(&str, &str)>) -> StubServer {
+        Self::start_raw(
+            routes
+                .into_iter()
+                .map(|(prefix, body)| {
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    (prefix, response)
+                })
+                .collect(),
+        )
+    }
+
+    /// Like `start`, but the routes are answered with the given raw HTTP responses
+    pub(crate) fn start_raw(routes: Vec<(&str, String)>) -> StubServer {
+        let routes: Vec<(String, String)> = routes
+            .into_iter()
+            .map(|(prefix, response)| (prefix.to_string(), response))
+            .collect();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_log = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, &routes, &requests_log);
+            }
+        });
+        StubServer { addr, requests }
+    }
+
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub(crate) fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Targets of the requests received so far
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn respond(
+    mut stream: TcpStream,
+    routes: &[(String, String)],
+    requests_log: &Mutex<Vec<String>>,
+) -> Option<()> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    // skip the headers and the body (e.g. of a POST), the stub only routes on the target
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    reader.read_exact(&mut vec![0; content_length]).ok()?;
+    let target = request_line.split_whitespace().nth(1)?.to_string();
+    // record before answering so that the client sees the request logged once it has the response
+    requests_log.lock().unwrap().push(target.clone());
+    let response = match routes.iter().find(|(prefix, _)| target.starts_with(prefix)) {
+        Some((_, response)) => response.clone(),
+        None => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+    };
+    stream.write_all(response.as_bytes()).ok()
+}
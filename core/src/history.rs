@@ -0,0 +1,319 @@
+//! Local history of the datapoints fetched and posted by this oracle and of the pool rates they
+//! ended up in. Stored as JSON in the data dir (next to `scanIDs.json`) for post-mortems, see the
+//! `history` command.
+
+use std::path::PathBuf;
+
+use derive_more::From;
+use once_cell::sync;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::datapoint_source::{unix_timestamp_now, DataPoint, SourceQuote};
+use crate::oracle_state::LiveEpochState;
+use crate::EpochID;
+
+pub static HISTORY_DIR_PATH: sync::OnceCell<PathBuf> = sync::OnceCell::new();
+
+pub fn get_history_file_path() -> PathBuf {
+    HISTORY_DIR_PATH
+        .get()
+        .unwrap()
+        .join("datapointHistory.json")
+}
+
+#[derive(Debug, From, Error)]
+pub enum HistoryError {
+    #[error("history file IO error: {0}")]
+    Io(std::io::Error),
+    #[error("history file JSON error: {0}")]
+    Json(serde_json::Error),
+}
+
+/// What this oracle did in an epoch (identified by the pool box epoch counter) and how the epoch
+/// ended
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochRecord {
+    pub epoch_id: EpochID,
+    /// Quotes of the individual sources behind the posted datapoint
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fetched: Vec<SourceQuote>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posted_datapoint: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posted_tx_id: Option<String>,
+    /// Unix timestamp of the post
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posted_at: Option<i64>,
+    /// Pool rate set by the refresh that ended the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_rate: Option<i64>,
+    /// Reward tokens in our oracle box after the refresh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reward_tokens: Option<u64>,
+    /// Change of `reward_tokens` since the previous epoch, `None` if that epoch is not recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reward_delta: Option<i64>,
+}
+
+/// Filter for `DatapointHistory::query`
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub from_epoch: Option<EpochID>,
+    pub to_epoch: Option<EpochID>,
+    /// Only return the latest records
+    pub last: Option<usize>,
+}
+
+/// JSON file holding `EpochRecord`s ordered by epoch
+#[derive(Debug, Clone)]
+pub struct DatapointHistory {
+    path: PathBuf,
+}
+
+impl DatapointHistory {
+    pub fn new(path: PathBuf) -> Self {
+        DatapointHistory { path }
+    }
+
+    /// History in the data dir
+    pub fn local() -> Self {
+        Self::new(get_history_file_path())
+    }
+
+    pub fn load(&self) -> Result<Vec<EpochRecord>, HistoryError> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(&self.path)?)?)
+    }
+
+    fn save(&self, records: &[EpochRecord]) -> Result<(), HistoryError> {
+        // replace the file in one go so that a crash cannot leave a truncated history behind
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(records)?)?;
+        std::fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Applies `f` to the record of the epoch (created if missing) along with the record of the
+    /// latest earlier epoch. Nothing is written if `f` returns false.
+    fn update_epoch<F>(&self, epoch_id: EpochID, f: F) -> Result<(), HistoryError>
+    where
+        F: FnOnce(&mut EpochRecord, Option<&EpochRecord>) -> bool,
+    {
+        let mut records = self.load()?;
+        let idx = match records.binary_search_by_key(&epoch_id, |r| r.epoch_id) {
+            Ok(idx) => idx,
+            Err(idx) => {
+                records.insert(
+                    idx,
+                    EpochRecord {
+                        epoch_id,
+                        ..Default::default()
+                    },
+                );
+                idx
+            }
+        };
+        let (earlier, rest) = records.split_at_mut(idx);
+        if f(&mut rest[0], earlier.last()) {
+            self.save(&records)?;
+        }
+        Ok(())
+    }
+
+    /// Records a datapoint posted in the epoch (replacing an earlier post in the same epoch)
+    pub fn record_posted_datapoint(
+        &self,
+        epoch_id: EpochID,
+        datapoint: DataPoint,
+        source_quotes: Vec<SourceQuote>,
+        tx_id: String,
+    ) -> Result<(), HistoryError> {
+        self.update_epoch(epoch_id, |record, _| {
+            record.fetched = source_quotes;
+            record.posted_datapoint = Some(datapoint.value);
+            record.posted_tx_id = Some(tx_id);
+            record.posted_at = Some(unix_timestamp_now());
+            true
+        })
+    }
+
+    /// Records the outcome of the epoch once it was ended by a refresh. Epochs are only settled
+    /// once, later calls for the same epoch are ignored.
+    pub fn record_settled_epoch(
+        &self,
+        epoch_id: EpochID,
+        pool_rate: i64,
+        reward_tokens: Option<u64>,
+    ) -> Result<(), HistoryError> {
+        self.update_epoch(epoch_id, |record, previous| {
+            if record.pool_rate.is_some() {
+                return false;
+            }
+            record.pool_rate = Some(pool_rate);
+            record.reward_tokens = reward_tokens;
+            // after downtime the previous epochs are missing, the change would span several
+            // epochs then
+            record.reward_delta = reward_tokens
+                .zip(
+                    previous
+                        .filter(|p| p.epoch_id + 1 == epoch_id)
+                        .and_then(|p| p.reward_tokens),
+                )
+                .map(|(now, before)| now as i64 - before as i64);
+            true
+        })
+    }
+
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<EpochRecord>, HistoryError> {
+        let mut records: Vec<EpochRecord> = self
+            .load()?
+            .into_iter()
+            .filter(|r| query.from_epoch.map_or(true, |from| r.epoch_id >= from))
+            .filter(|r| query.to_epoch.map_or(true, |to| r.epoch_id <= to))
+            .collect();
+        if let Some(last) = query.last {
+            records = records.split_off(records.len().saturating_sub(last));
+        }
+        Ok(records)
+    }
+}
+
+/// Settles the epoch that was ended by the refresh which created the current pool box. Failures
+/// are logged since the history must not get in the way of the oracle.
+pub fn record_live_epoch(history: &DatapointHistory, live_epoch: &LiveEpochState) {
+    if live_epoch.pool_box_epoch_id == 0 {
+        return;
+    }
+    if let Err(e) = history.record_settled_epoch(
+        live_epoch.pool_box_epoch_id - 1,
+        live_epoch.latest_pool_datapoint as i64,
+        live_epoch.local_reward_tokens,
+    ) {
+        log::error!("Failed to record pool rate in the datapoint history: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_history(name: &str) -> DatapointHistory {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-history-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        DatapointHistory::new(path)
+    }
+
+    fn quote(source: &str, value: i64) -> SourceQuote {
+        SourceQuote {
+            source: source.to_string(),
+            value,
+            timestamp: 1000,
+            accepted: true,
+        }
+    }
+
+    #[test]
+    fn test_empty_history() {
+        let history = make_history("empty");
+        assert_eq!(history.load().unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_posted_and_settled() {
+        let history = make_history("posted");
+        history
+            .record_posted_datapoint(
+                5,
+                DataPoint {
+                    value: 101,
+                    timestamp: 1000,
+                },
+                vec![quote("a", 100), quote("b", 102)],
+                "tx1".to_string(),
+            )
+            .unwrap();
+        history.record_settled_epoch(4, 90, Some(10)).unwrap();
+        history.record_settled_epoch(5, 100, Some(11)).unwrap();
+        // settled epochs are not overwritten
+        history.record_settled_epoch(5, 200, Some(20)).unwrap();
+        let records = history.load().unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.epoch_id).collect::<Vec<_>>(),
+            vec![4, 5]
+        );
+        let record = &records[1];
+        assert_eq!(record.fetched.len(), 2);
+        assert_eq!(record.posted_datapoint, Some(101));
+        assert_eq!(record.posted_tx_id.as_deref(), Some("tx1"));
+        assert_eq!(record.pool_rate, Some(100));
+        assert_eq!(record.reward_tokens, Some(11));
+        assert_eq!(record.reward_delta, Some(1));
+        assert_eq!(records[0].reward_delta, None);
+    }
+
+    #[test]
+    fn test_reward_delta_after_gap() {
+        let history = make_history("gap");
+        history.record_settled_epoch(4, 90, Some(10)).unwrap();
+        // the oracle was down in epochs 5 and 6
+        history.record_settled_epoch(7, 100, Some(13)).unwrap();
+        history.record_settled_epoch(8, 100, Some(14)).unwrap();
+        let records = history.load().unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.epoch_id).collect::<Vec<_>>(),
+            vec![4, 7, 8]
+        );
+        assert_eq!(records[1].reward_tokens, Some(13));
+        assert_eq!(records[1].reward_delta, None);
+        assert_eq!(records[2].reward_delta, Some(1));
+    }
+
+    #[test]
+    fn test_query() {
+        let history = make_history("query");
+        for epoch_id in 1..=10 {
+            history
+                .record_settled_epoch(epoch_id, epoch_id as i64 * 10, None)
+                .unwrap();
+        }
+        let epochs = |query: HistoryQuery| {
+            history
+                .query(&query)
+                .unwrap()
+                .iter()
+                .map(|r| r.epoch_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            epochs(HistoryQuery {
+                from_epoch: Some(3),
+                to_epoch: Some(5),
+                last: None,
+            }),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            epochs(HistoryQuery {
+                from_epoch: Some(3),
+                to_epoch: None,
+                last: Some(2),
+            }),
+            vec![9, 10]
+        );
+        assert_eq!(
+            epochs(HistoryQuery {
+                last: Some(20),
+                ..Default::default()
+            })
+            .len(),
+            10
+        );
+    }
+}
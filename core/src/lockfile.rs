@@ -0,0 +1,106 @@
+//! Lockfile in the data dir (next to `scanIDs.json`) that keeps a second `run` from using the same
+//! data dir and double-posting. The file is locked with an exclusive advisory lock for the life of
+//! the process, so the OS releases it when the process exits, however it exits. The file itself
+//! is left in place and only holds the PID of the last instance for the error message.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use derive_more::From;
+use fs2::FileExt;
+use thiserror::Error;
+
+pub const LOCK_FILE_NAME: &str = "oracle-core.lock";
+
+#[derive(Debug, From, Error)]
+pub enum LockError {
+    #[error("another oracle-core instance (PID {}) is running with the data dir of {path:?}", .pid.map_or_else(|| "unknown".to_string(), |pid| pid.to_string()))]
+    AlreadyRunning { pid: Option<u32>, path: PathBuf },
+    #[error("lockfile IO error: {0}")]
+    Io(std::io::Error),
+}
+
+/// Held for as long as the oracle runs
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+}
+
+impl InstanceLock {
+    pub fn acquire(data_dir: &Path) -> Result<Self, LockError> {
+        let path = data_dir.join(LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if let Err(e) = file.try_lock_exclusive() {
+            if e.raw_os_error() != fs2::lock_contended_error().raw_os_error() {
+                return Err(e.into());
+            }
+            let mut contents = String::new();
+            // the holder might not have written its PID yet
+            let pid = file
+                .read_to_string(&mut contents)
+                .ok()
+                .and_then(|_| contents.trim().parse().ok());
+            return Err(LockError::AlreadyRunning { pid, path });
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(InstanceLock { file })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // the file is not removed, another instance may already have it open and wait for it
+        if let Err(e) = self.file.unlock() {
+            log::warn!("Failed to unlock lockfile: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_data_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("oracle-core-lock-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_second_instance_refused() {
+        let dir = make_data_dir("second");
+        let lock = InstanceLock::acquire(&dir).unwrap();
+        // the lock is per open file, so a second acquire in the same process is refused too
+        let pid = std::process::id();
+        assert!(matches!(
+            InstanceLock::acquire(&dir),
+            Err(LockError::AlreadyRunning { pid: Some(p), .. }) if p == pid
+        ));
+        drop(lock);
+        assert!(InstanceLock::acquire(&dir).is_ok());
+    }
+
+    #[test]
+    fn test_leftover_lockfile_taken_over() {
+        let dir = make_data_dir("leftover");
+        // left behind by an instance that exited, or not a PID at all
+        std::fs::write(dir.join(LOCK_FILE_NAME), "not a pid, longer than one").unwrap();
+        let _lock = InstanceLock::acquire(&dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join(LOCK_FILE_NAME)).unwrap(),
+            std::process::id().to_string()
+        );
+    }
+}
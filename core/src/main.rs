@@ -21,21 +21,29 @@ mod address_util;
 mod api;
 mod box_kind;
 mod cli_commands;
+mod collector_election;
 mod contracts;
 mod datapoint_source;
 mod default_parameters;
+mod history;
+mod lockfile;
 mod logging;
+mod mempool;
 mod node_interface;
+mod node_pool;
 mod oracle_config;
 mod oracle_state;
 mod pool_commands;
 mod scans;
+mod scheduler;
 mod serde;
+mod signer;
 mod spec_token;
 mod state;
 mod templates;
 #[cfg(test)]
 mod tests;
+mod tx_tracker;
 mod wallet;
 
 use actions::execute_action;
@@ -44,6 +52,7 @@ use anyhow::anyhow;
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 use crossbeam::channel::bounded;
+use datapoint_source::ITERATION_CANCELLATION;
 use ergo_lib::ergo_chain_types::Digest32;
 use ergo_lib::ergotree_ir::chain::address::Address;
 use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
@@ -54,30 +63,51 @@ use ergo_lib::ergotree_ir::chain::token::TokenId;
 use log::debug;
 use log::error;
 use log::LevelFilter;
-use node_interface::assert_wallet_unlocked;
+use node_interface::assert_signer_ready;
 use node_interface::current_block_height;
-use node_interface::get_wallet_status;
-use node_interface::new_node_interface;
+use node_pool::NodeHealthCheck;
+use node_pool::NodeInfoSource;
+use node_pool::NodePool;
+use node_pool::LAST_NODE_HEALTH;
+use node_pool::NODE_POOL;
 use oracle_config::ORACLE_CONFIG;
 use oracle_state::register_and_save_scans;
 use oracle_state::OraclePool;
 use pool_commands::build_action;
+use pool_commands::deviation_guard::DeviationGuard;
 use pool_commands::publish_datapoint::PublishDatapointActionError::DataPointSource;
+use pool_commands::publish_datapoint::PublishDatapointActionError::DeviationNotConfirmed;
+use pool_commands::publish_datapoint::PublishDatapointActionError::FutureDataPoint;
+use pool_commands::publish_datapoint::PublishDatapointActionError::StaleDataPoint;
 use pool_commands::refresh::RefreshActionError;
 use pool_commands::PoolCommandError;
+use scheduler::listen_for_shutdown;
+use scheduler::Scheduler;
+use signer::SecretKind;
 use state::process;
+use state::reward_warning_threshold;
+use state::EpochState;
+use state::PoolParams;
 use state::PoolState;
+use state::LAST_DECISION;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::env;
 use std::path::Path;
-use std::thread;
-use std::time::Duration;
+use std::sync::Arc;
 use wallet::WalletData;
 
 use crate::api::start_rest_server;
 use crate::default_parameters::print_contract_hashes;
+use crate::history::DatapointHistory;
+use crate::history::HistoryQuery;
+use crate::lockfile::InstanceLock;
+use crate::mempool::pending_txs;
+use crate::mempool::MempoolSource;
 use crate::oracle_config::MAYBE_ORACLE_CONFIG;
+use crate::tx_tracker::RebroadcastWindow;
+use crate::tx_tracker::TrackedTx;
+use crate::tx_tracker::TX_TRACKER;
 
 /// A Base58 encoded String of a Ergo P2PK address. Using this type def until sigma-rust matures further with the actual Address type.
 pub type P2PKAddress = String;
@@ -138,6 +168,10 @@ enum Command {
         #[clap(long)]
         /// Set this flag to enable the REST API. NOTE: SSL is not used!
         enable_rest_api: bool,
+        #[clap(long)]
+        /// Post the next datapoint even if it deviates from the pool rate by more than the
+        /// configured `data_point_deviation_guard` allows.
+        allow_next_datapoint_deviation: bool,
     },
 
     /// Send reward tokens accumulated in the oracle box to a chosen address
@@ -149,6 +183,14 @@ enum Command {
     /// Print the number of reward tokens earned by the oracle (in the last posted/collected oracle box)
     PrintRewardTokens,
 
+    /// Show which datapoints a refresh would collect right now, the resulting pool rate and
+    /// reward distribution. Nothing is signed or submitted.
+    SimulateRefresh {
+        /// Print the report as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
     /// Transfer an oracle token to a chosen address.
     TransferOracleToken {
         /// Base58 encoded address to send oracle token to
@@ -190,6 +232,32 @@ enum Command {
     /// Print the current config file with zeroed sensitive/private fields.
     /// Intended to be shared with pool operators.
     PrintSafeConfig,
+
+    /// Encrypt a secret key into a key file for the `local` tx signer. The secret is read from
+    /// stdin, the password from the ORACLE_CORE_SIGNER_PASSWORD env var.
+    EncryptSignerKey {
+        /// Name of the key file to create
+        key_file: String,
+        /// The secret is a hex encoded DLog secret instead of a mnemonic
+        #[clap(long)]
+        dlog: bool,
+    },
+
+    /// Print the local datapoint history (fetched and posted datapoints, pool rates, rewards)
+    History {
+        /// First epoch to print
+        #[clap(long)]
+        from_epoch: Option<u32>,
+        /// Last epoch to print
+        #[clap(long)]
+        to_epoch: Option<u32>,
+        /// Print only the given number of latest epochs
+        #[clap(long)]
+        last: Option<usize>,
+        /// Print the records as JSON
+        #[clap(long)]
+        json: bool,
+    },
 }
 
 fn main() {
@@ -214,9 +282,18 @@ fn main() {
         env::current_dir().unwrap()
     };
     logging::setup_log(cmdline_log_level, &data_dir_path);
+    history::HISTORY_DIR_PATH
+        .set(data_dir_path.clone())
+        .unwrap();
+    tx_tracker::TX_TRACKER_DIR_PATH
+        .set(data_dir_path.clone())
+        .unwrap();
     scans::SCANS_DIR_PATH.set(data_dir_path).unwrap();
 
     let mut tokio_runtime = tokio::runtime::Runtime::new().unwrap();
+    datapoint_source::RUNTIME_HANDLE
+        .set(tokio_runtime.handle().clone())
+        .unwrap();
 
     #[allow(clippy::wildcard_enum_match_arm)]
     match args.command {
@@ -242,6 +319,35 @@ fn main() {
             print_contract_hashes();
         }
         Command::PrintSafeConfig => cli_commands::print_conf::print_safe_config(&ORACLE_CONFIG),
+        Command::History {
+            from_epoch,
+            to_epoch,
+            last,
+            json,
+        } => {
+            let query = HistoryQuery {
+                from_epoch,
+                to_epoch,
+                last,
+            };
+            if let Err(e) =
+                cli_commands::print_history::print_history(&DatapointHistory::local(), &query, json)
+            {
+                error!("Fatal history error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
+        Command::EncryptSignerKey { key_file, dlog } => {
+            let kind = if dlog {
+                SecretKind::Dlog
+            } else {
+                SecretKind::Mnemonic
+            };
+            if let Err(e) = cli_commands::encrypt_signer_key::encrypt_signer_key(&key_file, kind) {
+                error!("Fatal encrypt-signer-key error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
         oracle_command => handle_oracle_command(oracle_command, &mut tokio_runtime),
     }
 }
@@ -249,28 +355,62 @@ fn main() {
 /// Handle all non-bootstrap commands that require ORACLE_CONFIG/OraclePool
 fn handle_oracle_command(command: Command, tokio_runtime: &mut tokio::runtime::Runtime) {
     log_on_launch();
-    assert_wallet_unlocked(&new_node_interface());
+    assert_signer_ready();
+    // taken before the scans are registered, held until the loop stops
+    let _instance_lock = if matches!(command, Command::Run { .. }) {
+        match InstanceLock::acquire(scans::SCANS_DIR_PATH.get().unwrap()) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                error!("Fatal run error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
+    } else {
+        None
+    };
     register_and_save_scans().unwrap();
     let op = OraclePool::new().unwrap();
     match command {
         Command::Run {
             read_only,
             enable_rest_api,
+            allow_next_datapoint_deviation,
         } => {
-            assert_wallet_unlocked(&new_node_interface());
+            assert_signer_ready();
             let (_, repost_receiver) = bounded::<bool>(1);
+            let deviation_guard = Arc::new(DeviationGuard::new(
+                ORACLE_CONFIG.data_point_deviation_guard,
+                allow_next_datapoint_deviation,
+            ));
+            let shutdown = listen_for_shutdown(tokio_runtime);
 
             // Start Oracle Core GET API Server
-            if enable_rest_api {
-                tokio_runtime.spawn(start_rest_server(repost_receiver));
-            }
+            let rest_server = if enable_rest_api {
+                Some(tokio_runtime.spawn(start_rest_server(
+                    repost_receiver,
+                    deviation_guard.clone(),
+                    shutdown.clone(),
+                )))
+            } else {
+                None
+            };
+            let mut scheduler =
+                Scheduler::new(ORACLE_CONFIG.main_loop.unwrap_or_default(), shutdown);
             loop {
-                if let Err(e) = main_loop_iteration(&op, read_only) {
+                if let Err(e) = main_loop_iteration(&op, read_only, &deviation_guard) {
                     error!("error: {:?}", e);
                 }
-                // Delay loop restart
-                thread::sleep(Duration::new(30, 0));
+                if !scheduler.wait_for_next_iteration(current_block_height) {
+                    break;
+                }
             }
+            if let Some(rest_server) = rest_server {
+                // the server stops on its own once shutdown is requested
+                if let Err(e) = tokio_runtime.block_on(rest_server) {
+                    error!("REST server error: {}", e);
+                }
+            }
+            log::info!("Oracle core stopped");
         }
 
         Command::ExtractRewardTokens { rewards_address } => {
@@ -294,6 +434,24 @@ fn handle_oracle_command(command: Command, tokio_runtime: &mut tokio::runtime::R
             }
         }
 
+        Command::SimulateRefresh { json } => {
+            if let Err(e) = (|| -> Result<(), anyhow::Error> {
+                let height = current_block_height()? as u32;
+                let change_address = get_change_address()?;
+                cli_commands::simulate_refresh::simulate_refresh(
+                    &op,
+                    &WalletData {},
+                    height,
+                    change_address,
+                    json,
+                )?;
+                Ok(())
+            })() {
+                error!("Fatal simulate-refresh error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
+
         Command::TransferOracleToken {
             oracle_token_address,
         } => {
@@ -353,30 +511,76 @@ fn handle_oracle_command(command: Command, tokio_runtime: &mut tokio::runtime::R
             }
         }
         Command::Bootstrap { .. } | Command::PrintContractHashes => unreachable!(),
-        Command::PrintSafeConfig => unreachable!(),
+        Command::PrintSafeConfig | Command::History { .. } => unreachable!(),
+        Command::EncryptSignerKey { .. } => unreachable!(),
     }
 }
 
-fn main_loop_iteration(op: &OraclePool, read_only: bool) -> std::result::Result<(), anyhow::Error> {
-    let height = current_block_height().context("Failed to get the current height")? as u32;
-    let wallet = WalletData::new();
-    let network_change_address = get_change_address_from_node()?;
-    let pool_state = match op.get_live_epoch_state() {
-        Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
-        Err(error) => {
-            log::debug!("error getting live epoch state: {}", error);
-            PoolState::NeedsBootstrap
-        }
+fn main_loop_iteration(
+    op: &OraclePool,
+    read_only: bool,
+    deviation_guard: &DeviationGuard,
+) -> std::result::Result<(), anyhow::Error> {
+    // datapoint fetches still pending when the iteration returns are abandoned
+    let _fetch_scope = ITERATION_CANCELLATION.start_iteration();
+    // the selection only changes here, so that the height, the pool boxes and the mempool of the
+    // iteration all come from this node
+    let node = NODE_POOL.select();
+    let height = match check_node(&node, &NODE_POOL)? {
+        Some(height) => height,
+        None => return Ok(()),
     };
-    let epoch_length = ORACLE_CONFIG
+    let wallet = WalletData::new();
+    let network_change_address = get_change_address()?;
+    let contract_parameters = ORACLE_CONFIG
         .refresh_box_wrapper_inputs
         .contract_inputs
-        .contract_parameters()
-        .epoch_length() as u32;
-    if let Some(cmd) = process(pool_state, epoch_length, height) {
+        .contract_parameters();
+    let rebroadcast_window = RebroadcastWindow {
+        max_datapoint_age: ORACLE_CONFIG.data_point_max_age(),
+        refresh_buffer_blocks: contract_parameters.buffer_length() as u32,
+    };
+    if let Err(e) = TX_TRACKER.update(
+        &node,
+        &ORACLE_CONFIG.tx_tracker.unwrap_or_default(),
+        &rebroadcast_window,
+        height,
+    ) {
+        log::warn!("Failed to update the status of the submitted txs: {}", e);
+    }
+    let pool_state = read_pool_state(&node, op, &DatapointHistory::local(), &TX_TRACKER.list());
+    let params = PoolParams {
+        epoch_length: contract_parameters.epoch_length() as u32,
+        min_data_points: contract_parameters.min_data_points() as u32,
+        collector_election: ORACLE_CONFIG.refresh_collector_election,
+        reward_warning_threshold: reward_warning_threshold(
+            ORACLE_CONFIG.pool_reward_warning_threshold,
+            contract_parameters.min_data_points() as u32,
+        ),
+    };
+    let decision = process(pool_state, &params, height);
+    if decision.state == EpochState::NeedsBootstrap {
+        log::warn!("{}", decision.reason);
+    } else {
+        log::debug!("Height {height}. {:?}: {}", decision.state, decision.reason);
+    }
+    if decision.pool_rewards_low {
+        log::warn!(
+            "Pool reward tokens are below the warning threshold of {}, the pool needs an update with new reward tokens",
+            params.reward_warning_threshold
+        );
+    }
+    *LAST_DECISION.lock().unwrap() = Some(decision.clone());
+    if let Some(cmd) = decision.command {
         log::debug!("Height {height}. Building action for command: {:?}", cmd);
-        let build_action_res =
-            build_action(cmd, op, &wallet, height, network_change_address.address());
+        let build_action_res = build_action(
+            cmd,
+            op,
+            &wallet,
+            height,
+            network_change_address.address(),
+            deviation_guard,
+        );
         if let Some(action) =
             log_and_continue_if_non_fatal(network_change_address.network(), build_action_res)?
         {
@@ -388,6 +592,57 @@ fn main_loop_iteration(op: &OraclePool, read_only: bool) -> std::result::Result<
     Ok(())
 }
 
+/// Full height of the node, or `None` if it is unhealthy and the iteration should not act
+fn check_node(
+    node: &dyn NodeInfoSource,
+    node_pool: &NodePool,
+) -> std::result::Result<Option<u32>, anyhow::Error> {
+    let node_info = node
+        .get_node_info()
+        .context("Failed to get the node info")?;
+    let node_health = node_pool.check(&node_info);
+    *LAST_NODE_HEALTH.lock().unwrap() = Some(NodeHealthCheck::new(node_info, &node_health));
+    match node_health {
+        Ok(height) => Ok(Some(height)),
+        Err(e) => {
+            log::warn!("Node is unhealthy, skipping actions: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// Pool state with the mempool of `node`. `op` has to look up the pool boxes on the same node.
+fn read_pool_state(
+    node: &dyn MempoolSource,
+    op: &OraclePool,
+    history: &DatapointHistory,
+    tracked_txs: &[TrackedTx],
+) -> PoolState {
+    match op.get_live_epoch_state() {
+        Ok(live_epoch_state) => {
+            history::record_live_epoch(history, &live_epoch_state);
+            let pending_txs = op
+                .get_watched_box_ids()
+                .map_err(anyhow::Error::from)
+                .and_then(|box_ids| pending_txs(node, tracked_txs, &box_ids).map_err(Into::into));
+            match pending_txs {
+                Ok(pending_txs) if !pending_txs.is_empty() => {
+                    PoolState::AwaitingConfirmation(pending_txs)
+                }
+                Ok(_) => PoolState::LiveEpoch(live_epoch_state),
+                Err(e) => {
+                    log::warn!("Failed to check the mempool for pending txs: {}", e);
+                    PoolState::LiveEpoch(live_epoch_state)
+                }
+            }
+        }
+        Err(error) => {
+            log::debug!("error getting live epoch state: {}", error);
+            PoolState::NeedsBootstrap
+        }
+    }
+}
+
 fn log_and_continue_if_non_fatal(
     network_prefix: NetworkPrefix,
     res: Result<PoolAction, PoolCommandError>,
@@ -398,26 +653,53 @@ fn log_and_continue_if_non_fatal(
             expected,
             found_public_keys,
             found_num,
+            excluded,
         })) => {
             let found_oracle_addresses: String = found_public_keys
                 .into_iter()
                 .map(|pk| NetworkAddress::new(network_prefix, &Address::P2Pk(pk)).to_base58())
                 .collect::<Vec<String>>()
                 .join(", ");
-            log::error!("Refresh failed, not enough datapoints. The minimum number of datapoints within the deviation range: required minumum {expected}, found {found_num} from addresses {found_oracle_addresses},");
+            let excluded_oracles: String = excluded
+                .into_iter()
+                .map(|e| {
+                    let address = NetworkAddress::new(network_prefix, &Address::P2Pk(e.public_key));
+                    format!("{} ({}: {})", address.to_base58(), e.rate, e.reason)
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+            log::error!("Refresh failed, not enough datapoints. The minimum number of datapoints within the deviation range: required minumum {expected}, found {found_num} from addresses {found_oracle_addresses}, excluded {excluded_oracles}");
+            Ok(None)
+        }
+        Err(PoolCommandError::RefreshActionError(
+            e @ RefreshActionError::InsufficientPoolRewards { .. },
+        )) => {
+            log::error!(
+                "Refresh failed: {}. The pool needs an update with new reward tokens",
+                e
+            );
             Ok(None)
         }
         Err(PoolCommandError::PublishDatapointActionError(DataPointSource(e))) => {
             log::error!("Failed to get datapoint with error: {}", e);
             Ok(None)
         }
+        Err(PoolCommandError::PublishDatapointActionError(
+            e @ (StaleDataPoint { .. } | FutureDataPoint { .. }),
+        )) => {
+            log::error!("Refusing to publish datapoint: {}", e);
+            Ok(None)
+        }
+        Err(PoolCommandError::PublishDatapointActionError(e @ DeviationNotConfirmed { .. })) => {
+            log::warn!("Holding back datapoint: {}", e);
+            Ok(None)
+        }
         Err(e) => Err(e),
     }
 }
 
-fn get_change_address_from_node() -> Result<NetworkAddress, anyhow::Error> {
-    let change_address_str = get_wallet_status()?
-        .change_address
+fn get_change_address() -> Result<NetworkAddress, anyhow::Error> {
+    let change_address_str = wallet::change_address()?
         .ok_or_else(|| anyhow!("failed to get wallet's change address (locked wallet?)"))?;
     let addr = AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
     Ok(addr)
@@ -0,0 +1,131 @@
+//! Finds unconfirmed transactions spending the pool box or the local oracle box and our own
+//! unconfirmed transactions, so that the main loop does not build an action again while the
+//! previous one is still waiting in the mempool.
+
+use ergo_node_interface::node_interface::NodeError;
+
+use crate::tx_tracker::{ActionKind, TrackedTx};
+
+/// Transaction in the node's mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnconfirmedTx {
+    pub tx_id: String,
+    pub input_box_ids: Vec<String>,
+}
+
+/// Unconfirmed transaction the oracle has to wait for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTx {
+    pub tx_id: String,
+    /// Watched box spent by the tx
+    pub spent_box_id: Option<String>,
+    /// Set if the tx was submitted by us
+    pub own_action: Option<ActionKind>,
+}
+
+pub trait MempoolSource {
+    fn get_unconfirmed_txs(&self) -> Result<Vec<UnconfirmedTx>, NodeError>;
+}
+
+/// Unconfirmed txs spending any of `watched_box_ids` and our own txs still in the mempool
+pub fn pending_txs(
+    mempool: &dyn MempoolSource,
+    own_txs: &[TrackedTx],
+    watched_box_ids: &[String],
+) -> Result<Vec<PendingTx>, NodeError> {
+    let unconfirmed = mempool.get_unconfirmed_txs()?;
+    Ok(unconfirmed
+        .into_iter()
+        .filter_map(|tx| {
+            let spent_box_id = tx
+                .input_box_ids
+                .iter()
+                .find(|id| watched_box_ids.contains(*id))
+                .cloned();
+            let own_action = own_txs
+                .iter()
+                .find(|own| own.tx_id == tx.tx_id)
+                .map(|own| own.kind);
+            if spent_box_id.is_some() || own_action.is_some() {
+                Some(PendingTx {
+                    tx_id: tx.tx_id,
+                    spent_box_id,
+                    own_action,
+                })
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_tracker::TxStatus;
+
+    struct MempoolMock {
+        txs: Vec<UnconfirmedTx>,
+    }
+
+    impl MempoolSource for MempoolMock {
+        fn get_unconfirmed_txs(&self) -> Result<Vec<UnconfirmedTx>, NodeError> {
+            Ok(self.txs.clone())
+        }
+    }
+
+    fn unconfirmed(tx_id: &str, input_box_ids: &[&str]) -> UnconfirmedTx {
+        UnconfirmedTx {
+            tx_id: tx_id.to_string(),
+            input_box_ids: input_box_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    fn submitted(tx_id: &str, kind: ActionKind) -> TrackedTx {
+        TrackedTx {
+            tx_id: tx_id.to_string(),
+            kind,
+            input_box_ids: Vec::new(),
+            submitted_at: 1000,
+            status: TxStatus::Unconfirmed,
+            rebroadcasts: 0,
+            signed_tx: None,
+        }
+    }
+
+    #[test]
+    fn test_pending_txs() {
+        let submitted_txs = vec![
+            submitted("own-publish", ActionKind::PublishDatapoint),
+            submitted("own-refresh", ActionKind::Refresh),
+        ];
+        let mempool = MempoolMock {
+            txs: vec![
+                unconfirmed("other", &["box-a"]),
+                unconfirmed("refresh", &["pool-box", "box-b"]),
+                unconfirmed("own-publish", &["wallet-box"]),
+            ],
+        };
+        let watched = vec!["pool-box".to_string(), "oracle-box".to_string()];
+        let pending = pending_txs(&mempool, &submitted_txs, &watched).unwrap();
+        assert_eq!(
+            pending,
+            vec![
+                PendingTx {
+                    tx_id: "refresh".to_string(),
+                    spent_box_id: Some("pool-box".to_string()),
+                    own_action: None,
+                },
+                PendingTx {
+                    tx_id: "own-publish".to_string(),
+                    spent_box_id: None,
+                    own_action: Some(ActionKind::PublishDatapoint),
+                },
+            ]
+        );
+        let empty_mempool = MempoolMock { txs: Vec::new() };
+        assert!(pending_txs(&empty_mempool, &submitted_txs, &watched)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,10 +1,16 @@
 use crate::{
-    oracle_config::{get_node_api_key, get_node_ip, get_node_port},
-    wallet::{WalletDataError, WalletDataSource},
+    mempool::{MempoolSource, UnconfirmedTx},
+    node_pool::{NodeInfo, NodeInfoSource, NODE_POOL},
+    oracle_config::ORACLE_CONFIG,
+    scans::TokenBoxSource,
+    signer::{SignAction, SignerError, SigningContextSource, TxSigner, TxSignerConfig},
+    tx_tracker::TxStatusSource,
+    wallet::{AddressBoxSource, WalletDataError, WalletDataSource},
 };
 use ergo_lib::{
     chain::transaction::{unsigned::UnsignedTransaction, Transaction, TxIoVec},
-    ergotree_ir::chain::ergo_box::ErgoBox,
+    ergo_chain_types::Header,
+    ergotree_ir::chain::{ergo_box::ErgoBox, token::TokenId},
 };
 use ergo_node_interface::{
     node_interface::{NodeError, NodeInterface, WalletStatus},
@@ -29,7 +35,7 @@ pub trait SignTransaction {
         unsigned_tx: &UnsignedTransaction,
         inputs: TxIoVec<ErgoBox>,
         data_boxes: Option<TxIoVec<ErgoBox>>,
-    ) -> Result<Transaction>;
+    ) -> std::result::Result<Transaction, SignerError>;
 }
 
 // Note that we need the following trait implementations for `NodeInterface` because we can't rely
@@ -52,12 +58,12 @@ impl SignTransaction for NodeInterface {
         unsigned_tx: &ergo_lib::chain::transaction::unsigned::UnsignedTransaction,
         inputs: ergo_lib::chain::transaction::TxIoVec<ErgoBox>,
         data_boxes: Option<ergo_lib::chain::transaction::TxIoVec<ErgoBox>>,
-    ) -> Result<Transaction> {
-        self.sign_transaction(
+    ) -> std::result::Result<Transaction, SignerError> {
+        Ok(self.sign_transaction(
             unsigned_tx,
             Some(inputs.as_vec().clone()),
             data_boxes.map(|bs| bs.as_vec().clone()),
-        )
+        )?)
     }
 }
 
@@ -67,44 +73,253 @@ impl WalletDataSource for NodeInterface {
     }
 }
 
+impl AddressBoxSource for NodeInterface {
+    fn get_unspent_address_boxes(&self, address: &str) -> Result<Vec<ErgoBox>> {
+        // needs the node to run with `extraIndex = true`
+        const PAGE_SIZE: usize = 100;
+        let mut boxes = Vec::new();
+        loop {
+            let resp = self.send_post_req(
+                &format!(
+                    "/blockchain/box/unspent/byAddress?offset={}&limit={}",
+                    boxes.len(),
+                    PAGE_SIZE
+                ),
+                serde_json::to_string(address).unwrap(),
+            )?;
+            let page: Vec<ErgoBox> = resp
+                .text()
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok())
+                .ok_or_else(|| {
+                    NodeError::FailedParsingNodeResponse(format!("unspent boxes of {}", address))
+                })?;
+            let page_len = page.len();
+            boxes.extend(page);
+            if page_len < PAGE_SIZE {
+                return Ok(boxes);
+            }
+        }
+    }
+}
+
+impl MempoolSource for NodeInterface {
+    fn get_unconfirmed_txs(&self) -> Result<Vec<UnconfirmedTx>> {
+        // the node returns at most 100 txs per request
+        const PAGE_SIZE: usize = 100;
+        let mut txs = Vec::new();
+        loop {
+            let resp = self.send_get_req(&format!(
+                "/transactions/unconfirmed?offset={}&limit={}",
+                txs.len(),
+                PAGE_SIZE
+            ))?;
+            let page: Vec<serde_json::Value> = resp
+                .text()
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok())
+                .ok_or_else(|| {
+                    NodeError::FailedParsingNodeResponse("unconfirmed transactions".to_string())
+                })?;
+            let page_len = page.len();
+            txs.extend(page.into_iter().map(|tx| {
+                UnconfirmedTx {
+                    tx_id: tx["id"].as_str().unwrap_or_default().to_string(),
+                    input_box_ids: tx["inputs"]
+                        .as_array()
+                        .map(|inputs| {
+                            inputs
+                                .iter()
+                                .filter_map(|input| input["boxId"].as_str().map(str::to_string))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                }
+            }));
+            if page_len < PAGE_SIZE {
+                return Ok(txs);
+            }
+        }
+    }
+}
+
+impl TxStatusSource for NodeInterface {
+    fn get_tx_confirmations(&self, tx_id: &str) -> Result<Option<u32>> {
+        let index_lookup = format!("/blockchain/transaction/byId/{}", tx_id);
+        // with fallback nodes or without the node wallet, only the node's extra index knows the tx
+        let resp = if ORACLE_CONFIG.uses_blockchain_index() {
+            self.send_get_req(&index_lookup)?
+        } else {
+            // our txs spend wallet boxes (fee) so the wallet knows about them
+            let resp = self.send_get_req(&format!("/wallet/transactionById?id={}", tx_id))?;
+            if resp.status().is_success() {
+                resp
+            } else {
+                // unless the fee boxes come from an address wallet, then the node's extra index
+                // is needed to find them
+                self.send_get_req(&index_lookup)?
+            }
+        };
+        if !resp.status().is_success() {
+            return Ok(None);
+        }
+        let tx: serde_json::Value = resp
+            .text()
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| NodeError::FailedParsingNodeResponse("transaction".to_string()))?;
+        Ok(tx["numConfirmations"]
+            .as_u64()
+            .filter(|confirmations| *confirmations > 0)
+            .map(|confirmations| confirmations as u32))
+    }
+
+    fn is_tx_unconfirmed(&self, tx_id: &str) -> Result<bool> {
+        let resp = self.send_get_req(&format!(
+            "/transactions/unconfirmed/byTransactionId/{}",
+            tx_id
+        ))?;
+        Ok(resp.status().is_success())
+    }
+
+    fn is_box_unspent(&self, box_id: &str) -> Result<bool> {
+        let resp = self.send_get_req(&format!("/utxo/byId/{}", box_id))?;
+        Ok(resp.status().is_success())
+    }
+
+    fn resubmit_tx(&self, tx: &Transaction) -> Result<String> {
+        self.submit_transaction(tx)
+    }
+}
+
+impl TokenBoxSource for NodeInterface {
+    fn get_unspent_token_boxes(&self, token_id: &TokenId) -> Result<Vec<ErgoBox>> {
+        // needs the node to run with `extraIndex = true`
+        const PAGE_SIZE: usize = 100;
+        let token_id = String::from(token_id.clone());
+        let mut boxes = Vec::new();
+        loop {
+            let resp = self.send_get_req(&format!(
+                "/blockchain/box/unspent/byTokenId/{}?offset={}&limit={}",
+                token_id,
+                boxes.len(),
+                PAGE_SIZE
+            ))?;
+            let page: Vec<ErgoBox> = resp
+                .text()
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok())
+                .ok_or_else(|| {
+                    NodeError::FailedParsingNodeResponse(format!(
+                        "unspent boxes of token {}",
+                        token_id
+                    ))
+                })?;
+            let page_len = page.len();
+            boxes.extend(page);
+            if page_len < PAGE_SIZE {
+                return Ok(boxes);
+            }
+        }
+    }
+}
+
+impl NodeInfoSource for NodeInterface {
+    fn get_node_info(&self) -> Result<NodeInfo> {
+        let resp = self.send_get_req("/info")?;
+        resp.text()
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| NodeError::FailedParsingNodeResponse("node info".to_string()))
+    }
+}
+
+impl SigningContextSource for NodeInterface {
+    fn get_box(&self, box_id: &str) -> Result<ErgoBox> {
+        let resp = self.send_get_req(&format!("/utxo/withPool/byId/{}", box_id))?;
+        resp.text()
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| NodeError::FailedParsingNodeResponse(format!("box {}", box_id)))
+    }
+
+    fn get_last_headers(&self) -> Result<[Header; 10]> {
+        let resp = self.send_get_req("/blocks/lastHeaders/10")?;
+        let mut headers: Vec<Header> = resp
+            .text()
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| NodeError::FailedParsingNodeResponse("last headers".to_string()))?;
+        headers.sort_by(|a, b| b.height.cmp(&a.height));
+        headers.try_into().map_err(|_| {
+            NodeError::FailedParsingNodeResponse("expected 10 last headers".to_string())
+        })
+    }
+}
+
+/// Selected node for chain reads, see `node_pool`
 pub fn new_node_interface() -> NodeInterface {
-    NodeInterface::new(&get_node_api_key(), &get_node_ip(), &get_node_port())
+    NODE_POOL.node()
+}
+
+/// The configured node, for the wallet and the scans
+pub fn primary_node_interface() -> NodeInterface {
+    NODE_POOL.primary()
+}
+
+/// Boxes from the blockchain index of the selected node
+pub struct SelectedNode;
+
+impl TokenBoxSource for SelectedNode {
+    fn get_unspent_token_boxes(&self, token_id: &TokenId) -> Result<Vec<ErgoBox>> {
+        new_node_interface().get_unspent_token_boxes(token_id)
+    }
+}
+
+/// Signer set in the oracle config
+pub fn new_tx_signer() -> std::result::Result<TxSigner, SignerError> {
+    let config = ORACLE_CONFIG.tx_signer.clone().unwrap_or_default();
+    let node = match config {
+        TxSignerConfig::Node => primary_node_interface(),
+        TxSignerConfig::Local(_) | TxSignerConfig::Remote(_) => new_node_interface(),
+    };
+    TxSigner::from_config(&config, node, &ORACLE_CONFIG.oracle_address)
 }
 
 /// Registers a scan with the node and either returns the `scan_id` or an error
 pub fn register_scan(scan_json: &serde_json::Value) -> Result<ScanID> {
     let scan_json_t = json::parse(&serde_json::to_string(scan_json).unwrap()).unwrap();
-    new_node_interface().register_scan(&scan_json_t)
+    primary_node_interface().register_scan(&scan_json_t)
 }
 
 /// Acquires unspent boxes from the node wallet
 pub fn get_unspent_wallet_boxes() -> Result<Vec<ErgoBox>> {
-    new_node_interface().unspent_boxes()
+    primary_node_interface().unspent_boxes()
 }
 
 /// Acquires the unspent box with the highest value of Ergs inside
 /// from the wallet
 pub fn get_highest_value_unspent_box() -> Result<ErgoBox> {
-    new_node_interface().highest_value_unspent_box()
+    primary_node_interface().highest_value_unspent_box()
 }
 
 pub fn unspent_boxes_with_min_total(total: u64) -> Result<Vec<ErgoBox>> {
-    new_node_interface().unspent_boxes_with_min_total(total)
+    primary_node_interface().unspent_boxes_with_min_total(total)
 }
 
 /// Acquires the unspent box with the highest value of Ergs inside
 /// from the wallet and serializes it
 pub fn get_serialized_highest_value_unspent_box() -> Result<String> {
-    new_node_interface().serialized_highest_value_unspent_box()
+    primary_node_interface().serialized_highest_value_unspent_box()
 }
 
 /// Using the `scan_id` of a registered scan, acquires unspent boxes which have been found by said scan
 pub fn get_scan_boxes(scan_id: &String) -> Result<Vec<ErgoBox>> {
-    new_node_interface().scan_boxes(scan_id)
+    primary_node_interface().scan_boxes(scan_id)
 }
 
 pub fn rescan_from_height(height: u32) -> Result<()> {
-    new_node_interface().send_post_req(
+    primary_node_interface().send_post_req(
         "/wallet/rescan",
         format!("{{ \"fromHeight\": {} }} ", height),
     )?;
@@ -117,32 +332,51 @@ pub fn current_block_height() -> Result<BlockHeight> {
 }
 
 pub fn get_wallet_status() -> Result<WalletStatus> {
-    new_node_interface().wallet_status()
-}
-
-// /// Sign an `UnsignedTransaction`.
-// pub fn sign_transaction(unsigned_tx: &UnsignedTransaction) -> Result<Transaction> {
-//     new_node_interface().sign_transaction(unsigned_tx)
-// }
-
-/// Submit a `Transaction` to the mempool.
-pub fn submit_transaction(signed_tx: &Transaction) -> Result<TxId> {
-    new_node_interface().submit_transaction(signed_tx)
+    primary_node_interface().wallet_status()
 }
 
-/// Sign an `UnsignedTransaction` and then submit it to the mempool.
-pub fn sign_and_submit_transaction(unsigned_tx: &UnsignedTransaction) -> Result<TxId> {
-    let node = new_node_interface();
+/// Sign an `UnsignedTransaction` with the configured signer.
+pub fn sign_transaction(
+    unsigned_tx: &UnsignedTransaction,
+    action: SignAction,
+) -> std::result::Result<Transaction, SignerError> {
     log::trace!(
         "Signing transaction: {}",
         serde_json::to_string_pretty(&unsigned_tx).unwrap()
     );
-    let signed_tx = node.sign_transaction(unsigned_tx, None, None)?;
+    new_tx_signer()?.sign_transaction(unsigned_tx, action)
+}
+
+/// Submit a `Transaction` to the mempool.
+pub fn submit_transaction(signed_tx: &Transaction) -> Result<TxId> {
     log::trace!(
         "Submitting signed transaction: {}",
         serde_json::to_string_pretty(&signed_tx).unwrap()
     );
-    node.submit_transaction(&signed_tx)
+    NODE_POOL.submit_transaction(signed_tx)
+}
+
+/// Sign an `UnsignedTransaction` with the configured signer and then submit it to the mempool.
+pub fn sign_and_submit_transaction(
+    unsigned_tx: &UnsignedTransaction,
+    action: SignAction,
+) -> std::result::Result<TxId, SignerError> {
+    let signed_tx = sign_transaction(unsigned_tx, action)?;
+    Ok(submit_transaction(&signed_tx)?)
+}
+
+/// Exits if the configured signer can't sign, i.e. the node wallet is locked or the key file of
+/// the local signer can't be decrypted
+pub fn assert_signer_ready() {
+    match new_tx_signer() {
+        Ok(TxSigner::Node(node)) => assert_wallet_unlocked(&node),
+        Ok(TxSigner::Local(_)) => debug!("Signing with the local key"),
+        Ok(TxSigner::Remote(_)) => debug!("Signing with the remote signer"),
+        Err(e) => {
+            error!("Signer error: {}", e);
+            std::process::exit(exitcode::SOFTWARE);
+        }
+    }
 }
 
 pub fn assert_wallet_unlocked(node: &NodeInterface) {
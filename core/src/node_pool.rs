@@ -0,0 +1,530 @@
+//! Failover between the configured node (`node_ip`/`node_port`) and the `fallback_nodes`. At the
+//! start of each main loop iteration the nodes are checked with `/info` if the last check is older
+//! than `health_check_secs`, and the first node that is synced and not behind the others is
+//! selected. The selection stays the same until the next iteration, so that the height, the pool
+//! boxes and the mempool an iteration acts on come from the same node. With fallback nodes the
+//! pool boxes are looked up in the blockchain index of the selected node rather than with the
+//! scans of the configured node. The node wallet is kept by each node on its own, so wallet
+//! requests always go to the configured node. See the `/nodeInfo` endpoint for the health and
+//! error counts of each node.
+//!
+//! Before acting the main loop checks the sync status and the peers of the selected node with
+//! the same `NodePoolConfig::check`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ergo_lib::chain::transaction::Transaction;
+use ergo_node_interface::node_interface::{NodeError, NodeInterface};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::datapoint_source::unix_timestamp_now;
+use crate::node_interface::TxId;
+use crate::oracle_config::ORACLE_CONFIG;
+
+lazy_static! {
+    pub static ref NODE_POOL: NodePool = NodePool::new(
+        NodeEndpointConfig {
+            ip: ORACLE_CONFIG.node_ip.clone(),
+            port: ORACLE_CONFIG.node_port,
+            api_key: Some(ORACLE_CONFIG.node_api_key.clone()),
+        },
+        ORACLE_CONFIG.node_pool.clone().unwrap_or_default(),
+    );
+    /// Latest node health check of the main loop, for the REST API
+    pub static ref LAST_NODE_HEALTH: Mutex<Option<NodeHealthCheck>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeEndpointConfig {
+    pub ip: String,
+    pub port: u16,
+    /// Defaults to `node_api_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodePoolConfig {
+    /// Tried in order when the configured node is unhealthy
+    #[serde(default)]
+    pub fallback_nodes: Vec<NodeEndpointConfig>,
+    /// Blocks a node may be behind its headers, its peers or the other nodes and still be healthy
+    #[serde(default = "default_max_height_lag")]
+    pub max_height_lag: u32,
+    #[serde(default = "default_min_peers")]
+    pub min_peers: u32,
+    #[serde(default = "default_health_check_secs")]
+    pub health_check_secs: u64,
+    /// Submit txs to all healthy nodes, not only the selected one
+    #[serde(default)]
+    pub broadcast: bool,
+}
+
+fn default_max_height_lag() -> u32 {
+    2
+}
+
+fn default_min_peers() -> u32 {
+    1
+}
+
+fn default_health_check_secs() -> u64 {
+    30
+}
+
+impl Default for NodePoolConfig {
+    fn default() -> Self {
+        NodePoolConfig {
+            fallback_nodes: Vec::new(),
+            max_height_lag: default_max_height_lag(),
+            min_peers: default_min_peers(),
+            health_check_secs: default_health_check_secs(),
+            broadcast: false,
+        }
+    }
+}
+
+/// Sync status from the node's `/info`
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    /// Height of the last fully validated block
+    pub full_height: Option<u32>,
+    pub headers_height: Option<u32>,
+    pub max_peer_height: Option<u32>,
+    #[serde(default)]
+    pub peers_count: u32,
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum NodeHealthError {
+    #[error("node has not validated any blocks yet")]
+    NoFullHeight,
+    #[error("node is syncing, full height {full_height}, headers height {headers_height}")]
+    Syncing {
+        full_height: u32,
+        headers_height: u32,
+    },
+    /// Stuck, or forked off
+    #[error("node is behind its peers, full height {full_height}, peers at {max_peer_height}")]
+    BehindPeers {
+        full_height: u32,
+        max_peer_height: u32,
+    },
+    #[error("node has {peers} peers, at least {min_peers} required")]
+    TooFewPeers { peers: u32, min_peers: u32 },
+}
+
+impl NodePoolConfig {
+    /// Returns the full height if the node is synced and has enough peers
+    pub fn check(&self, info: &NodeInfo) -> Result<u32, NodeHealthError> {
+        let full_height = info.full_height.ok_or(NodeHealthError::NoFullHeight)?;
+        let headers_height = info.headers_height.unwrap_or(full_height);
+        if headers_height > full_height + self.max_height_lag {
+            return Err(NodeHealthError::Syncing {
+                full_height,
+                headers_height,
+            });
+        }
+        if let Some(max_peer_height) = info.max_peer_height {
+            if max_peer_height > full_height + self.max_height_lag {
+                return Err(NodeHealthError::BehindPeers {
+                    full_height,
+                    max_peer_height,
+                });
+            }
+        }
+        if info.peers_count < self.min_peers {
+            return Err(NodeHealthError::TooFewPeers {
+                peers: info.peers_count,
+                min_peers: self.min_peers,
+            });
+        }
+        Ok(full_height)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeHealthCheck {
+    /// Unix timestamp
+    pub checked_at: i64,
+    pub info: NodeInfo,
+    pub healthy: bool,
+    /// Set if the node is unhealthy
+    pub reason: Option<String>,
+}
+
+impl NodeHealthCheck {
+    pub fn new(info: NodeInfo, result: &Result<u32, NodeHealthError>) -> Self {
+        NodeHealthCheck {
+            checked_at: unix_timestamp_now(),
+            info,
+            healthy: result.is_ok(),
+            reason: result.as_ref().err().map(ToString::to_string),
+        }
+    }
+}
+
+pub trait NodeInfoSource {
+    fn get_node_info(&self) -> Result<NodeInfo, NodeError>;
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub url: String,
+    pub selected: bool,
+    /// False until the first health check
+    pub healthy: bool,
+    pub info: Option<NodeInfo>,
+    /// Failed health checks and submissions
+    pub error_count: u64,
+    pub last_error: Option<String>,
+}
+
+struct NodeEndpoint {
+    ip: String,
+    port: String,
+    api_key: String,
+}
+
+impl NodeEndpoint {
+    fn interface(&self) -> NodeInterface {
+        NodeInterface::new(&self.api_key, &self.ip, &self.port)
+    }
+}
+
+struct PoolState {
+    nodes: Vec<NodeStatus>,
+    selected: usize,
+    last_check: Option<Instant>,
+}
+
+pub struct NodePool {
+    endpoints: Vec<NodeEndpoint>,
+    config: NodePoolConfig,
+    state: Mutex<PoolState>,
+}
+
+impl NodePool {
+    pub fn new(primary: NodeEndpointConfig, config: NodePoolConfig) -> Self {
+        let primary_api_key = primary.api_key.clone().unwrap_or_default();
+        let endpoints: Vec<NodeEndpoint> = std::iter::once(primary)
+            .chain(config.fallback_nodes.iter().cloned())
+            .map(|node| NodeEndpoint {
+                ip: node.ip,
+                port: node.port.to_string(),
+                api_key: node.api_key.unwrap_or_else(|| primary_api_key.clone()),
+            })
+            .collect();
+        let nodes = endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, endpoint)| NodeStatus {
+                url: format!("http://{}:{}", endpoint.ip, endpoint.port),
+                selected: i == 0,
+                healthy: false,
+                info: None,
+                error_count: 0,
+                last_error: None,
+            })
+            .collect();
+        NodePool {
+            endpoints,
+            config,
+            state: Mutex::new(PoolState {
+                nodes,
+                selected: 0,
+                last_check: None,
+            }),
+        }
+    }
+
+    /// Checks the nodes if the last check is too old and returns the selected node. Called at the
+    /// start of each main loop iteration, which then only reads from the returned node.
+    pub fn select(&self) -> NodeInterface {
+        if self.health_check_due() {
+            self.check_health();
+        }
+        self.node()
+    }
+
+    /// Node for reads, only changed by `select`. The configured node until then.
+    pub fn node(&self) -> NodeInterface {
+        let selected = self.state.lock().unwrap().selected;
+        self.endpoints[selected].interface()
+    }
+
+    /// The configured node, which holds the wallet and the scans
+    pub fn primary(&self) -> NodeInterface {
+        self.endpoints[0].interface()
+    }
+
+    /// Health check of a single node, see `NodePoolConfig::check`
+    pub fn check(&self, info: &NodeInfo) -> Result<u32, NodeHealthError> {
+        self.config.check(info)
+    }
+
+    pub fn status(&self) -> Vec<NodeStatus> {
+        self.state.lock().unwrap().nodes.clone()
+    }
+
+    /// Claims the next check, so that concurrent callers do not check as well
+    fn health_check_due(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let due = state.last_check.map_or(true, |t| {
+            t.elapsed() >= Duration::from_secs(self.config.health_check_secs)
+        });
+        if due {
+            state.last_check = Some(Instant::now());
+        }
+        due
+    }
+
+    /// Queries all nodes and selects the first healthy one. Stays on the selected node if none
+    /// is healthy.
+    pub fn check_health(&self) {
+        let infos: Vec<Result<NodeInfo, NodeError>> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.interface().get_node_info())
+            .collect();
+        let healthy = healthy_nodes(
+            &infos
+                .iter()
+                .map(|info| info.as_ref().ok().copied())
+                .collect::<Vec<_>>(),
+            &self.config,
+        );
+        let mut state = self.state.lock().unwrap();
+        for ((node, info), healthy) in state.nodes.iter_mut().zip(infos).zip(&healthy) {
+            if node.healthy && !healthy {
+                log::warn!("Node {} is unhealthy", node.url);
+            }
+            node.healthy = *healthy;
+            match info {
+                Ok(info) => node.info = Some(info),
+                Err(e) => {
+                    node.info = None;
+                    node.error_count += 1;
+                    node.last_error = Some(e.to_string());
+                }
+            }
+        }
+        let selected = healthy
+            .iter()
+            .position(|healthy| *healthy)
+            .unwrap_or(state.selected);
+        if selected != state.selected {
+            log::warn!(
+                "Switching from node {} to node {}",
+                state.nodes[state.selected].url,
+                state.nodes[selected].url
+            );
+            state.nodes[state.selected].selected = false;
+            state.nodes[selected].selected = true;
+            state.selected = selected;
+        }
+    }
+
+    /// Submits to the selected node, and with `broadcast` to the other healthy nodes as well.
+    /// Succeeds if any node accepts the tx.
+    pub fn submit_transaction(&self, tx: &Transaction) -> Result<TxId, NodeError> {
+        let targets: Vec<usize> = {
+            let state = self.state.lock().unwrap();
+            std::iter::once(state.selected)
+                .chain(
+                    (0..state.nodes.len())
+                        .filter(|i| self.config.broadcast && *i != state.selected)
+                        .filter(|i| state.nodes[*i].healthy),
+                )
+                .collect()
+        };
+        let mut accepted = None;
+        let mut first_error = None;
+        for i in targets {
+            match self.endpoints[i].interface().submit_transaction(tx) {
+                Ok(tx_id) => {
+                    accepted.get_or_insert(tx_id);
+                }
+                Err(e) => {
+                    let mut state = self.state.lock().unwrap();
+                    let node = &mut state.nodes[i];
+                    log::warn!(
+                        "Node {} rejected tx {}: {}",
+                        node.url,
+                        String::from(tx.id()),
+                        e
+                    );
+                    node.error_count += 1;
+                    node.last_error = Some(e.to_string());
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match (accepted, first_error) {
+            (Some(tx_id), _) => Ok(tx_id),
+            (None, Some(e)) => Err(e),
+            (None, None) => unreachable!("the selected node is always a target"),
+        }
+    }
+}
+
+/// Nodes that pass the check and are at most `max_height_lag` blocks behind the highest node
+fn healthy_nodes(infos: &[Option<NodeInfo>], config: &NodePoolConfig) -> Vec<bool> {
+    let heights: Vec<Option<u32>> = infos
+        .iter()
+        .map(|info| info.as_ref().and_then(|info| config.check(info).ok()))
+        .collect();
+    let best_height = heights.iter().flatten().max().copied();
+    heights
+        .iter()
+        .map(|height| {
+            height.zip(best_height).map_or(false, |(height, best)| {
+                height + config.max_height_lag >= best
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint_source::test_utils::StubServer;
+
+    fn info(full_height: u32, headers_height: u32, max_peer_height: Option<u32>) -> NodeInfo {
+        NodeInfo {
+            full_height: Some(full_height),
+            headers_height: Some(headers_height),
+            max_peer_height,
+            peers_count: 10,
+        }
+    }
+
+    #[test]
+    fn test_healthy_nodes() {
+        let infos = [
+            // unreachable
+            None,
+            // still validating blocks
+            Some(info(900, 1000, Some(1000))),
+            Some(info(1000, 1000, Some(1000))),
+            Some(info(999, 999, None)),
+            // behind the other nodes
+            Some(info(990, 990, None)),
+            Some(NodeInfo {
+                full_height: None,
+                headers_height: None,
+                max_peer_height: None,
+                peers_count: 0,
+            }),
+        ];
+        let config = NodePoolConfig::default();
+        assert_eq!(
+            healthy_nodes(&infos, &config),
+            vec![false, false, true, true, false, false]
+        );
+        assert_eq!(healthy_nodes(&[None, None], &config), vec![false, false]);
+    }
+
+    #[test]
+    fn test_node_health_check() {
+        let config = NodePoolConfig::default();
+        assert_eq!(config.check(&info(1000, 1001, Some(1002))), Ok(1000));
+        assert_eq!(
+            config.check(&info(900, 1000, Some(1000))),
+            Err(NodeHealthError::Syncing {
+                full_height: 900,
+                headers_height: 1000
+            })
+        );
+        assert_eq!(
+            config.check(&info(1000, 1000, Some(1010))),
+            Err(NodeHealthError::BehindPeers {
+                full_height: 1000,
+                max_peer_height: 1010
+            })
+        );
+        let no_peers = NodeInfo {
+            peers_count: 0,
+            ..info(1000, 1000, None)
+        };
+        assert_eq!(
+            config.check(&no_peers),
+            Err(NodeHealthError::TooFewPeers {
+                peers: 0,
+                min_peers: 1
+            })
+        );
+        let not_started = NodeInfo {
+            full_height: None,
+            ..no_peers
+        };
+        assert_eq!(
+            config.check(&not_started),
+            Err(NodeHealthError::NoFullHeight)
+        );
+    }
+
+    fn endpoint(server: &StubServer) -> NodeEndpointConfig {
+        NodeEndpointConfig {
+            ip: server.addr().ip().to_string(),
+            port: server.addr().port(),
+            api_key: Some("key".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_selection_changes_on_select_only() {
+        // the configured node fell behind, the fallback is at the tip
+        let primary = StubServer::start(vec![(
+            "/info",
+            r#"{"fullHeight": 1000, "headersHeight": 1000, "maxPeerHeight": 1010, "peersCount": 5}"#,
+        )]);
+        let fallback = StubServer::start(vec![(
+            "/info",
+            r#"{"fullHeight": 1010, "headersHeight": 1010, "maxPeerHeight": 1010, "peersCount": 5}"#,
+        )]);
+        let pool = NodePool::new(
+            endpoint(&primary),
+            NodePoolConfig {
+                fallback_nodes: vec![endpoint(&fallback)],
+                ..NodePoolConfig::default()
+            },
+        );
+        let height = |node: NodeInterface| node.get_node_info().unwrap().full_height;
+        assert_eq!(height(pool.node()), Some(1000));
+        assert_eq!(height(pool.select()), Some(1010));
+        let status = pool.status();
+        assert!(!status[0].healthy);
+        assert!(status[1].selected);
+        assert_eq!(height(pool.node()), Some(1010));
+        // not checked again before `health_check_secs` passed
+        pool.select();
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(fallback.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_node_info_from_json() {
+        let json = r#"{"fullHeight": 1000, "headersHeight": 1001, "maxPeerHeight": null,
+            "peersCount": 5, "name": "node"}"#;
+        let info: NodeInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(info.full_height, Some(1000));
+        assert_eq!(info.max_peer_height, None);
+        assert_eq!(info.peers_count, 5);
+        assert_eq!(NodePoolConfig::default().check(&info), Ok(1000));
+        let no_lag = NodePoolConfig {
+            max_height_lag: 0,
+            ..NodePoolConfig::default()
+        };
+        assert_eq!(
+            no_lag.check(&info),
+            Err(NodeHealthError::Syncing {
+                full_height: 1000,
+                headers_height: 1001
+            })
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
     box_kind::{
@@ -6,14 +8,26 @@ use crate::{
         RefreshBoxWrapperInputs, UpdateBoxWrapperInputs,
     },
     cli_commands::bootstrap::BootstrapConfig,
+    collector_election::CollectorElectionConfig,
     contracts::{
         ballot::BallotContractError, oracle::OracleContractError, pool::PoolContractError,
         refresh::RefreshContractError, update::UpdateContractError,
     },
-    datapoint_source::{DataPointSource, ExternalScript, PredefinedDataPointSource},
+    datapoint_source::{
+        AggregateDataPointSource, AggregateDataPointSourceConfig, BlockingSource, DataPointSource,
+        ExternalScript, ExternalScriptSpec, FetchPolicy, HttpJsonSource, HttpJsonSourceConfig,
+        PredefinedDataPointSource, RetryingSource, RuntimeBoundSource, ITERATION_CANCELLATION,
+    },
+    node_pool::NodePoolConfig,
+    pool_commands::aggregation::AggregationStrategyConfig,
+    pool_commands::deviation_guard::DeviationGuardConfig,
+    scheduler::MainLoopConfig,
+    signer::TxSignerConfig,
     spec_token::{
         BallotTokenId, OracleTokenId, PoolTokenId, RefreshTokenId, RewardTokenId, UpdateTokenId,
     },
+    tx_tracker::TxTrackerConfig,
+    wallet::WalletSourceConfig,
 };
 use anyhow::anyhow;
 use derive_more::From;
@@ -30,6 +44,9 @@ use thiserror::Error;
 
 pub const DEFAULT_CONFIG_FILE_NAME: &str = "oracle_config.yaml";
 
+/// Datapoints quoted earlier than this are not published
+pub const DEFAULT_DATA_POINT_MAX_AGE_SECS: u64 = 300;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(
     try_from = "crate::serde::OracleConfigSerde",
@@ -44,7 +61,20 @@ pub struct OracleConfig {
     pub core_api_port: u16,
     pub oracle_address: NetworkAddress,
     pub data_point_source: Option<PredefinedDataPointSource>,
-    pub data_point_source_custom_script: Option<String>,
+    pub data_point_source_custom_script: Option<ExternalScriptSpec>,
+    pub data_point_source_aggregate: Option<AggregateDataPointSourceConfig>,
+    pub data_point_source_http_json: Option<HttpJsonSourceConfig>,
+    pub data_point_fetch_policy: Option<FetchPolicy>,
+    pub data_point_max_age_secs: Option<u64>,
+    pub data_point_deviation_guard: Option<DeviationGuardConfig>,
+    pub refresh_aggregation_strategy: Option<AggregationStrategyConfig>,
+    pub refresh_collector_election: Option<CollectorElectionConfig>,
+    pub pool_reward_warning_threshold: Option<u64>,
+    pub tx_tracker: Option<TxTrackerConfig>,
+    pub main_loop: Option<MainLoopConfig>,
+    pub tx_signer: Option<TxSignerConfig>,
+    pub wallet_source: Option<WalletSourceConfig>,
+    pub node_pool: Option<NodePoolConfig>,
     pub oracle_box_wrapper_inputs: OracleBoxWrapperInputs,
     pub pool_box_wrapper_inputs: PoolBoxWrapperInputs,
     pub refresh_box_wrapper_inputs: RefreshBoxWrapperInputs,
@@ -141,6 +171,19 @@ impl OracleConfig {
             oracle_address: bootstrap.oracle_address,
             data_point_source: bootstrap.data_point_source,
             data_point_source_custom_script: bootstrap.data_point_source_custom_script,
+            data_point_source_aggregate: None,
+            data_point_source_http_json: None,
+            data_point_fetch_policy: None,
+            data_point_max_age_secs: None,
+            data_point_deviation_guard: None,
+            refresh_aggregation_strategy: None,
+            refresh_collector_election: None,
+            pool_reward_warning_threshold: None,
+            tx_tracker: None,
+            main_loop: None,
+            tx_signer: bootstrap.tx_signer,
+            wallet_source: bootstrap.wallet_source,
+            node_pool: None,
             oracle_box_wrapper_inputs,
             pool_box_wrapper_inputs,
             refresh_box_wrapper_inputs,
@@ -161,22 +204,57 @@ impl OracleConfig {
         serde_yaml::from_str(config_str).map_err(|e| anyhow!(e))
     }
 
+    pub fn data_point_max_age(&self) -> Duration {
+        Duration::from_secs(
+            self.data_point_max_age_secs
+                .unwrap_or(DEFAULT_DATA_POINT_MAX_AGE_SECS),
+        )
+    }
+
+    /// The pool boxes are looked up in the blockchain index of the selected node (`extraIndex =
+    /// true`) instead of with scans in the wallet of the configured node. Needed with fallback
+    /// nodes, which have no scans, and with the `address` wallet source, which runs without the
+    /// node wallet.
+    pub fn uses_blockchain_index(&self) -> bool {
+        let has_fallback_nodes = self
+            .node_pool
+            .as_ref()
+            .map_or(false, |node_pool| !node_pool.fallback_nodes.is_empty());
+        let tracks_address = matches!(self.wallet_source, Some(WalletSourceConfig::Address { .. }));
+        has_fallback_nodes || tracks_address
+    }
+
+    /// Datapoint source driven on the tokio runtime according to `data_point_fetch_policy`
     pub fn data_point_source(
         &self,
     ) -> Result<Box<dyn DataPointSource + Send + Sync>, anyhow::Error> {
-        let data_point_source: Box<dyn DataPointSource + Send + Sync> = if let Some(
-            external_script_name,
-        ) =
-            self.data_point_source_custom_script.clone()
+        let fetch_policy = self.data_point_fetch_policy.unwrap_or_default();
+        if let Some(aggregate_config) = &self.data_point_source_aggregate {
+            // the policy is applied to each of the aggregated sources
+            let aggregate = AggregateDataPointSource::from_config(aggregate_config)?
+                .with_fetch_policy(fetch_policy);
+            return Ok(Box::new(RuntimeBoundSource::new(
+                Arc::new(aggregate),
+                ITERATION_CANCELLATION.clone(),
+            )));
+        }
+        let source: Arc<dyn DataPointSource + Send + Sync> = if let Some(http_json_config) =
+            &self.data_point_source_http_json
         {
-            Box::new(ExternalScript::new(external_script_name.clone()))
+            Arc::new(HttpJsonSource::new(http_json_config.clone()))
+        } else if let Some(external_script_spec) = &self.data_point_source_custom_script {
+            Arc::new(ExternalScript::new(external_script_spec.clone()))
         } else {
             match self.data_point_source {
-                Some(datasource) => Box::new(datasource),
-                _ => return Err(anyhow!("Config: data_point_source is invalid (must be one of 'NanoErgUsd', 'NanoErgXau' or 'NanoAdaUsd'")),
-            }
+                    Some(datasource) => Arc::new(datasource),
+                    None => return Err(anyhow!("Config: data_point_source is invalid (must be one of 'NanoErgUsd', 'NanoErgXau' or 'NanoAdaUsd'")),
+                }
         };
-        Ok(data_point_source)
+        let retrying = RetryingSource::new(Box::new(BlockingSource(source)), fetch_policy);
+        Ok(Box::new(RuntimeBoundSource::new(
+            Arc::new(retrying),
+            ITERATION_CANCELLATION.clone(),
+        )))
     }
 }
 
@@ -5,21 +5,27 @@ use crate::box_kind::{
     RefreshBoxWrapperInputs, UpdateBoxError, UpdateBoxWrapper, UpdateBoxWrapperInputs,
     VoteBallotBoxWrapper,
 };
+use crate::collector_election::collector_rank;
 use crate::contracts::ballot::BallotContract;
 use crate::contracts::oracle::OracleContract;
 use crate::datapoint_source::{DataPointSource, DataPointSourceError};
-use crate::node_interface::{current_block_height, get_wallet_status, rescan_from_height};
-use crate::oracle_config::ORACLE_CONFIG;
+use crate::node_interface::{
+    get_wallet_status, primary_node_interface, rescan_from_height, SelectedNode,
+};
+use crate::oracle_config::{OracleConfig, ORACLE_CONFIG};
 use crate::scans::{
-    load_scan_ids, register_ballot_box_scan, register_datapoint_scan,
-    register_local_ballot_box_scan, register_local_oracle_datapoint_scan, register_pool_box_scan,
-    register_refresh_box_scan, register_update_box_scan, save_scan_ids, Scan, ScanError,
+    ballot_box_rule, datapoint_rule, load_scan_ids, local_ballot_box_rule,
+    local_oracle_datapoint_rule, pool_box_rule, refresh_box_rule, save_scan_ids, update_box_rule,
+    BoxLookup, Scan, ScanError, BALLOT_BOX_SCAN, DATAPOINT_SCAN, LOCAL_BALLOT_BOX_SCAN,
+    LOCAL_DATAPOINT_SCAN, POOL_BOX_SCAN, REFRESH_BOX_SCAN, UPDATE_BOX_SCAN,
 };
 use crate::state::PoolState;
 use anyhow::Error;
 use derive_more::From;
+use std::sync::Arc;
 
 use ergo_lib::ergo_chain_types::blake2b256_hash;
+use ergo_lib::ergo_chain_types::EcPoint;
 use ergo_lib::ergotree_ir::chain::address::Address;
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_lib::ergotree_ir::mir::constant::TryExtractFromError;
@@ -163,6 +169,11 @@ pub struct LiveEpochState {
     pub local_datapoint_box_state: Option<LocalDatapointState>,
     pub latest_pool_datapoint: u64,
     pub latest_pool_box_height: u32,
+    pub pool_reward_tokens: u64,
+    /// Reward tokens in the local oracle box
+    pub local_reward_tokens: Option<u64>,
+    /// Collector rank of the local oracle among the oracles that posted in this epoch
+    pub local_collector_rank: Option<u32>,
 }
 
 /// Last posted datapoint box info by the local oracle
@@ -173,61 +184,98 @@ pub enum LocalDatapointState {
 }
 
 impl<'a> OraclePool<'a> {
-    /// Create a new `OraclePool` struct
+    /// Create a new `OraclePool` struct. The boxes are looked up with the scans in
+    /// `scanIDs.json`, or in the blockchain index of the selected node, see
+    /// `OracleConfig::uses_blockchain_index`.
     pub fn new() -> std::result::Result<OraclePool<'static>, Error> {
-        let config = &ORACLE_CONFIG;
+        let lookup = if ORACLE_CONFIG.uses_blockchain_index() {
+            BoxLookup::BlockchainIndex(Arc::new(SelectedNode))
+        } else {
+            BoxLookup::NodeScans(load_scan_ids()?)
+        };
+        OraclePool::with_box_lookup(&ORACLE_CONFIG, lookup)
+    }
 
+    pub fn with_box_lookup(
+        config: &'a OracleConfig,
+        lookup: BoxLookup,
+    ) -> std::result::Result<OraclePool<'a>, Error> {
         let data_point_source = config.data_point_source()?;
 
-        let refresh_box_scan_name = "Refresh Box Scan";
-
         let datapoint_contract =
             OracleContract::checked_load(&config.oracle_box_wrapper_inputs.contract_inputs)?
                 .ergo_tree();
-
-        let scan_json = load_scan_ids()?;
+        let ballot_contract =
+            BallotContract::checked_load(&config.ballot_box_wrapper_inputs.contract_inputs)?
+                .ergo_tree();
+        let oracle_token_id = &config.token_ids.oracle_token_id;
 
         // Create all `Scan` structs for protocol
         let datapoint_scan = Scan::new(
-            "All Oracle Datapoints Scan",
-            &scan_json["All Datapoints Scan"].to_string(),
+            DATAPOINT_SCAN,
+            datapoint_rule(oracle_token_id, &datapoint_contract),
+            &lookup,
         );
-        let local_scan_str = "Local Oracle Datapoint Scan";
         let local_oracle_datapoint_scan = LocalOracleDatapointScan {
             scan: Scan::new(
-                "Local Oracle Datapoint Scan",
-                &scan_json[local_scan_str].to_string(),
+                LOCAL_DATAPOINT_SCAN,
+                local_oracle_datapoint_rule(
+                    oracle_token_id,
+                    &datapoint_contract,
+                    &config.oracle_address,
+                )?,
+                &lookup,
             ),
             oracle_box_wrapper_inputs: &config.oracle_box_wrapper_inputs,
         };
 
-        let local_scan_str = "Local Ballot Box Scan";
         let local_ballot_box_scan = LocalBallotBoxScan {
-            scan: Scan::new(local_scan_str, &scan_json[local_scan_str].to_string()),
+            scan: Scan::new(
+                LOCAL_BALLOT_BOX_SCAN,
+                local_ballot_box_rule(
+                    &ballot_contract,
+                    &config.token_ids.ballot_token_id,
+                    &config.oracle_address,
+                )?,
+                &lookup,
+            ),
             ballot_box_wrapper_inputs: &config.ballot_box_wrapper_inputs,
             ballot_token_owner_address: config.oracle_address.address(),
         };
 
         let ballot_boxes_scan = BallotBoxesScan {
-            scan: Scan::new("Ballot Box Scan", &scan_json["Ballot Box Scan"].to_string()),
+            scan: Scan::new(
+                BALLOT_BOX_SCAN,
+                ballot_box_rule(&ballot_contract, &config.token_ids.ballot_token_id),
+                &lookup,
+            ),
             ballot_box_wrapper_inputs: &config.ballot_box_wrapper_inputs,
         };
 
         let pool_box_scan = PoolBoxScan {
-            scan: Scan::new("Pool Box Scan", &scan_json["Pool Box Scan"].to_string()),
+            scan: Scan::new(
+                POOL_BOX_SCAN,
+                pool_box_rule(&config.pool_box_wrapper_inputs)?,
+                &lookup,
+            ),
             pool_box_wrapper_inputs: &config.pool_box_wrapper_inputs,
         };
 
         let refresh_box_scan = RefreshBoxScan {
             scan: Scan::new(
-                refresh_box_scan_name,
-                &scan_json[refresh_box_scan_name].to_string(),
+                REFRESH_BOX_SCAN,
+                refresh_box_rule(&config.refresh_box_wrapper_inputs)?,
+                &lookup,
             ),
             refresh_box_wrapper_inputs: &config.refresh_box_wrapper_inputs,
         };
 
         let update_box_scan = UpdateBoxScan {
-            scan: Scan::new("Update Box Scan", &scan_json["Update Box Scan"].to_string()),
+            scan: Scan::new(
+                UPDATE_BOX_SCAN,
+                update_box_rule(&config.token_ids.update_nft_token_id),
+                &lookup,
+            ),
             update_box_wrapper_inputs: &config.update_box_wrapper_inputs,
         };
 
@@ -260,16 +308,40 @@ impl<'a> OraclePool<'a> {
         }
     }
 
+    /// Ids of the pool box and the local oracle box. Unconfirmed txs spending them are waited for
+    /// before building the next action.
+    pub fn get_watched_box_ids(&self) -> Result<Vec<String>> {
+        let mut box_ids = vec![String::from(
+            self.get_pool_box_source()
+                .get_pool_box()?
+                .get_box()
+                .box_id(),
+        )];
+        if let Some(local_box) = self
+            .get_local_datapoint_box_source()
+            .get_local_oracle_datapoint_box()?
+        {
+            box_ids.push(String::from(local_box.get_box().box_id()));
+        }
+        Ok(box_ids)
+    }
+
     /// Get the state of the current oracle pool epoch
     pub fn get_live_epoch_state(&self) -> Result<LiveEpochState> {
         let pool_box = self.get_pool_box_source().get_pool_box()?;
         let epoch_id: u32 = pool_box.epoch_counter();
 
-        // Whether datapoint was commit in the current Live Epoch
-        let local_datapoint_box_state = self
+        let local_datapoint_box = self
             .get_local_datapoint_box_source()
-            .get_local_oracle_datapoint_box()?
-            .map(|local_data_point_box| match local_data_point_box {
+            .get_local_oracle_datapoint_box()?;
+        let local_reward_tokens = local_datapoint_box
+            .as_ref()
+            .map(|b| *b.reward_token().amount.as_u64());
+        let local_public_key = local_datapoint_box.as_ref().map(|b| *b.public_key().h);
+
+        // Whether datapoint was commit in the current Live Epoch
+        let local_datapoint_box_state =
+            local_datapoint_box.map(|local_data_point_box| match local_data_point_box {
                 OracleBoxWrapper::Posted(ref posted_box) => LocalDatapointState::Posted {
                     epoch_id: posted_box.epoch_counter(),
                     height: local_data_point_box.get_box().creation_height,
@@ -279,13 +351,35 @@ impl<'a> OraclePool<'a> {
                 },
             });
 
+        let local_collector_rank = match &local_datapoint_box_state {
+            Some(LocalDatapointState::Posted {
+                epoch_id: local_epoch_id,
+                ..
+            }) if *local_epoch_id == epoch_id => {
+                let posted_public_keys: Vec<EcPoint> = self
+                    .get_datapoint_boxes_source()
+                    .get_oracle_datapoint_boxes()?
+                    .into_iter()
+                    .filter(|b| b.epoch_counter() == epoch_id)
+                    .map(|b| *b.public_key().h)
+                    .collect();
+                local_public_key.and_then(|pk| collector_rank(epoch_id, &pk, &posted_public_keys))
+            }
+            Some(LocalDatapointState::Posted { .. })
+            | Some(LocalDatapointState::Collected { .. })
+            | None => None,
+        };
+
         let latest_pool_datapoint = pool_box.rate() as u64;
 
         let epoch_state = LiveEpochState {
             pool_box_epoch_id: epoch_id,
             latest_pool_datapoint,
             latest_pool_box_height: pool_box.get_box().creation_height,
+            pool_reward_tokens: *pool_box.reward_token().amount.as_u64(),
             local_datapoint_box_state,
+            local_reward_tokens,
+            local_collector_rank,
         };
 
         Ok(epoch_state)
@@ -438,6 +532,12 @@ impl<'a> DatapointBoxesSource for DatapointStage<'a> {
 /// Register scans and save in scanIDs.json (if it doesn't already exist), and wait for rescan to complete
 pub fn register_and_save_scans() -> std::result::Result<(), Error> {
     let config = &ORACLE_CONFIG;
+    if config.uses_blockchain_index() {
+        log::info!(
+            "Looking up the pool boxes in the blockchain index of the nodes, no scans needed"
+        );
+        return Ok(());
+    }
     if load_scan_ids().is_err() {
         register_and_save_scans_inner()?;
     } else {
@@ -472,8 +572,9 @@ pub fn register_and_save_scans() -> std::result::Result<(), Error> {
     }
 
     loop {
+        // the wallet of the configured node, compared with the chain of the same node
         let wallet_height = get_wallet_status()?.height;
-        let block_height = current_block_height()?;
+        let block_height = primary_node_interface().current_block_height()?;
         if wallet_height == block_height {
             break;
         }
@@ -488,11 +589,7 @@ pub fn register_and_save_scans() -> std::result::Result<(), Error> {
 /// WARNING: will overwrite existing `scanIDs.json`!
 fn register_and_save_scans_inner() -> std::result::Result<(), Error> {
     let config = &ORACLE_CONFIG;
-    let local_oracle_address = config.oracle_address.clone();
-
-    let oracle_pool_participant_token_id = config.token_ids.oracle_token_id.clone();
-
-    let refresh_box_scan_name = "Refresh Box Scan";
+    let oracle_token_id = &config.token_ids.oracle_token_id;
 
     let datapoint_contract_address =
         OracleContract::checked_load(&config.oracle_box_wrapper_inputs.contract_inputs)?
@@ -503,27 +600,42 @@ fn register_and_save_scans_inner() -> std::result::Result<(), Error> {
             .ergo_tree();
 
     let scans = vec![
-        register_datapoint_scan(
-            &oracle_pool_participant_token_id,
-            &datapoint_contract_address,
+        Scan::register(
+            DATAPOINT_SCAN,
+            datapoint_rule(oracle_token_id, &datapoint_contract_address),
+        )?,
+        Scan::register(
+            UPDATE_BOX_SCAN,
+            update_box_rule(&config.token_ids.update_nft_token_id),
+        )?,
+        Scan::register(
+            POOL_BOX_SCAN,
+            pool_box_rule(&config.pool_box_wrapper_inputs)?,
+        )?,
+        Scan::register(
+            REFRESH_BOX_SCAN,
+            refresh_box_rule(&config.refresh_box_wrapper_inputs)?,
         )?,
-        register_update_box_scan(&config.token_ids.update_nft_token_id)?,
-        register_pool_box_scan(config.pool_box_wrapper_inputs.clone())?,
-        register_refresh_box_scan(
-            refresh_box_scan_name,
-            config.refresh_box_wrapper_inputs.clone(),
+        Scan::register(
+            LOCAL_DATAPOINT_SCAN,
+            local_oracle_datapoint_rule(
+                oracle_token_id,
+                &datapoint_contract_address,
+                &config.oracle_address,
+            )?,
         )?,
-        register_local_oracle_datapoint_scan(
-            &oracle_pool_participant_token_id,
-            &datapoint_contract_address,
-            &local_oracle_address,
+        Scan::register(
+            LOCAL_BALLOT_BOX_SCAN,
+            local_ballot_box_rule(
+                &ballot_contract_address,
+                &config.token_ids.ballot_token_id,
+                &config.oracle_address,
+            )?,
         )?,
-        register_local_ballot_box_scan(
-            &ballot_contract_address,
-            &config.token_ids.ballot_token_id,
-            &config.oracle_address,
+        Scan::register(
+            BALLOT_BOX_SCAN,
+            ballot_box_rule(&ballot_contract_address, &config.token_ids.ballot_token_id),
         )?,
-        register_ballot_box_scan(&ballot_contract_address, &config.token_ids.ballot_token_id)?,
     ];
 
     log::info!("Registering UTXO-Set Scans");
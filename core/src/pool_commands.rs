@@ -1,6 +1,7 @@
 use derive_more::From;
 use ergo_lib::ergo_chain_types::DigestNError;
 use ergo_lib::ergotree_ir::chain::address::{Address, AddressEncoderError};
+use serde::Serialize;
 use thiserror::Error;
 
 use crate::actions::PoolAction;
@@ -9,6 +10,7 @@ use crate::oracle_config::ORACLE_CONFIG;
 use crate::oracle_state::{OraclePool, StageError};
 use crate::wallet::WalletDataSource;
 
+use self::deviation_guard::DeviationGuard;
 use self::publish_datapoint::build_publish_first_datapoint_action;
 use self::publish_datapoint::{
     build_subsequent_publish_datapoint_action, PublishDatapointActionError,
@@ -16,12 +18,14 @@ use self::publish_datapoint::{
 use self::refresh::build_refresh_action;
 use self::refresh::RefreshActionError;
 
+pub mod aggregation;
+pub mod deviation_guard;
 pub mod publish_datapoint;
 pub mod refresh;
 #[cfg(test)]
 pub(crate) mod test_utils;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub enum PoolCommand {
     Refresh,
     PublishFirstDataPoint,
@@ -52,6 +56,7 @@ pub fn build_action(
     wallet: &dyn WalletDataSource,
     height: u32,
     change_address: Address,
+    deviation_guard: &DeviationGuard,
 ) -> Result<PoolAction, PoolCommandError> {
     let refresh_box_source = op.get_refresh_box_source();
     let datapoint_stage_src = op.get_datapoint_boxes_source();
@@ -72,6 +77,8 @@ pub fn build_action(
             oracle_public_key,
             ORACLE_CONFIG.oracle_box_wrapper_inputs.clone(),
             &*op.data_point_source,
+            ORACLE_CONFIG.data_point_max_age(),
+            current_epoch_counter,
         )
         .map_err(Into::into)
         .map(Into::into),
@@ -87,6 +94,8 @@ pub fn build_action(
                     height,
                     change_address,
                     &*op.data_point_source,
+                    ORACLE_CONFIG.data_point_max_age(),
+                    deviation_guard,
                     new_epoch_counter,
                     pool_box.rate(),
                 )
@@ -112,6 +121,10 @@ pub fn build_action(
                 .contract_inputs
                 .contract_parameters()
                 .min_data_points() as u32,
+            &*ORACLE_CONFIG
+                .refresh_aggregation_strategy
+                .unwrap_or_default()
+                .strategy(),
             wallet,
             height,
             change_address,
@@ -0,0 +1,439 @@
+//! Strategies picking the posted datapoints collected by the refresh tx. The refresh contract
+//! requires the collected rates to be within `max_deviation_percent` of the highest one and sets
+//! the pool rate to their integer mean, so the strategies differ in which datapoints they drop.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::refresh::RefreshActionError;
+
+/// Posted datapoint as seen by the aggregation strategies
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RatedDatapoint {
+    pub rate: u64,
+    /// Reward tokens accumulated in the oracle box
+    pub reputation: u64,
+}
+
+/// Why a posted datapoint is not collected by the refresh tx
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExclusionReason {
+    /// Posted for another epoch
+    WrongEpoch,
+    /// Posted before the current epoch started
+    Expired,
+    /// Dropped to bring the rest within the deviation range
+    Outlier,
+    /// Trimmed off by the `trimmed_mean` strategy
+    Trimmed,
+}
+
+impl fmt::Display for ExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExclusionReason::WrongEpoch => write!(f, "posted for another epoch"),
+            ExclusionReason::Expired => write!(f, "posted before the current epoch"),
+            ExclusionReason::Outlier => write!(f, "outside of the deviation range"),
+            ExclusionReason::Trimmed => write!(f, "trimmed off"),
+        }
+    }
+}
+
+/// Indices of the collected datapoints and of the dropped ones, in the order they were dropped
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub selected: Vec<usize>,
+    pub excluded: Vec<(usize, ExclusionReason)>,
+}
+
+pub trait AggregationStrategy {
+    /// Picks the datapoints to collect. `datapoints` must be sorted by rate, boxes with equal
+    /// rates by box id. Datapoints are dropped one at a time from either end of this order, so
+    /// of several boxes with the same extreme rate the outermost one is dropped first.
+    /// If too few datapoints are left, `RefreshActionError::NotEnoughDatapoints` carries the
+    /// selection as it was when the strategy gave up.
+    fn select(
+        &self,
+        datapoints: &[RatedDatapoint],
+        max_deviation_percent: u32,
+        min_data_points: u32,
+    ) -> Result<Selection, RefreshActionError>;
+}
+
+/// Strategy used by the collector, set in `refresh_aggregation_strategy` (`mean` by default)
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum AggregationStrategyConfig {
+    #[default]
+    Mean,
+    Median,
+    TrimmedMean {
+        trim_percent: u32,
+    },
+    ReputationWeightedMean,
+}
+
+impl AggregationStrategyConfig {
+    pub fn strategy(&self) -> Box<dyn AggregationStrategy> {
+        match *self {
+            AggregationStrategyConfig::Mean => Box::new(Mean),
+            AggregationStrategyConfig::Median => Box::new(Median),
+            AggregationStrategyConfig::TrimmedMean { trim_percent } => {
+                Box::new(TrimmedMean { trim_percent })
+            }
+            AggregationStrategyConfig::ReputationWeightedMean => Box::new(ReputationWeightedMean),
+        }
+    }
+}
+
+/// Drops the lowest or the highest rate, whichever is further from the mean, until the rest is
+/// within the deviation range
+#[derive(Debug, Copy, Clone)]
+pub struct Mean;
+
+impl AggregationStrategy for Mean {
+    fn select(
+        &self,
+        datapoints: &[RatedDatapoint],
+        max_deviation_percent: u32,
+        _min_data_points: u32,
+    ) -> Result<Selection, RefreshActionError> {
+        remove_outliers(
+            datapoints,
+            Selection {
+                selected: (0..datapoints.len()).collect(),
+                excluded: Vec::new(),
+            },
+            max_deviation_percent,
+            mean,
+        )
+    }
+}
+
+/// Like `Mean` but measures the distance from the median, so that a cluster of outliers on one
+/// side does not pull the center towards it
+#[derive(Debug, Copy, Clone)]
+pub struct Median;
+
+impl AggregationStrategy for Median {
+    fn select(
+        &self,
+        datapoints: &[RatedDatapoint],
+        max_deviation_percent: u32,
+        _min_data_points: u32,
+    ) -> Result<Selection, RefreshActionError> {
+        remove_outliers(
+            datapoints,
+            Selection {
+                selected: (0..datapoints.len()).collect(),
+                excluded: Vec::new(),
+            },
+            max_deviation_percent,
+            median,
+        )
+    }
+}
+
+/// Drops `trim_percent` of the datapoints on both ends (keeping at least `min_data_points`)
+/// before applying `Mean`
+#[derive(Debug, Copy, Clone)]
+pub struct TrimmedMean {
+    pub trim_percent: u32,
+}
+
+impl AggregationStrategy for TrimmedMean {
+    fn select(
+        &self,
+        datapoints: &[RatedDatapoint],
+        max_deviation_percent: u32,
+        min_data_points: u32,
+    ) -> Result<Selection, RefreshActionError> {
+        let len = datapoints.len();
+        let mut trim = len * self.trim_percent.min(50) as usize / 100;
+        while trim > 0 && len - 2 * trim < min_data_points as usize {
+            trim -= 1;
+        }
+        let excluded = (0..trim)
+            .chain(len - trim..len)
+            .map(|idx| (idx, ExclusionReason::Trimmed))
+            .collect();
+        remove_outliers(
+            datapoints,
+            Selection {
+                selected: (trim..len - trim).collect(),
+                excluded,
+            },
+            max_deviation_percent,
+            mean,
+        )
+    }
+}
+
+/// Like `Mean` but weights the rates by the reputation (reward tokens) of the oracles, so that
+/// long-standing oracles have more say in which side gets dropped
+#[derive(Debug, Copy, Clone)]
+pub struct ReputationWeightedMean;
+
+impl AggregationStrategy for ReputationWeightedMean {
+    fn select(
+        &self,
+        datapoints: &[RatedDatapoint],
+        max_deviation_percent: u32,
+        _min_data_points: u32,
+    ) -> Result<Selection, RefreshActionError> {
+        remove_outliers(
+            datapoints,
+            Selection {
+                selected: (0..datapoints.len()).collect(),
+                excluded: Vec::new(),
+            },
+            max_deviation_percent,
+            weighted_mean,
+        )
+    }
+}
+
+/// Removes the first or the last selected datapoint, whichever is further from `center`, until
+/// the selected rates pass the deviation check
+fn remove_outliers(
+    datapoints: &[RatedDatapoint],
+    mut selection: Selection,
+    max_deviation_percent: u32,
+    center: fn(&[RatedDatapoint], &[usize]) -> f64,
+) -> Result<Selection, RefreshActionError> {
+    if selection.selected.is_empty() {
+        return Ok(selection);
+    }
+    while !deviation_check(max_deviation_percent, datapoints, &selection.selected) {
+        // Check if sufficient number of datapoints to start removing
+        if selection.selected.len() <= 2 {
+            return Err(RefreshActionError::NotEnoughDatapoints { partial: selection });
+        }
+        let selected = &mut selection.selected;
+        let min_rate = datapoints[selected[0]].rate;
+        let max_rate = datapoints[selected[selected.len() - 1]].rate;
+        let center = center(datapoints, selected);
+        let outlier = if max_rate as f64 - center >= center - min_rate as f64 {
+            selected.remove(selected.len() - 1)
+        } else {
+            selected.remove(0)
+        };
+        selection.excluded.push((outlier, ExclusionReason::Outlier));
+    }
+    Ok(selection)
+}
+
+/// The min rate must be within `max_deviation_percent` of the max rate
+fn deviation_check(
+    max_deviation_percent: u32,
+    datapoints: &[RatedDatapoint],
+    selected: &[usize],
+) -> bool {
+    let min_rate = datapoints[selected[0]].rate;
+    let max_rate = datapoints[selected[selected.len() - 1]].rate;
+    let deviation_delta = max_rate * (max_deviation_percent as u64) / 100;
+    max_rate - min_rate <= deviation_delta
+}
+
+fn mean(datapoints: &[RatedDatapoint], selected: &[usize]) -> f64 {
+    let sum: f64 = selected.iter().map(|i| datapoints[*i].rate as f64).sum();
+    sum / selected.len() as f64
+}
+
+fn median(datapoints: &[RatedDatapoint], selected: &[usize]) -> f64 {
+    let mid = selected.len() / 2;
+    if selected.len() % 2 == 0 {
+        (datapoints[selected[mid - 1]].rate as f64 + datapoints[selected[mid]].rate as f64) / 2.0
+    } else {
+        datapoints[selected[mid]].rate as f64
+    }
+}
+
+fn weighted_mean(datapoints: &[RatedDatapoint], selected: &[usize]) -> f64 {
+    // oracles without reward tokens still count
+    let weight = |i: &usize| datapoints[*i].reputation.max(1) as f64;
+    let weighted_sum: f64 = selected
+        .iter()
+        .map(|i| datapoints[*i].rate as f64 * weight(i))
+        .sum();
+    let total_weight: f64 = selected.iter().map(weight).sum();
+    weighted_sum / total_weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datapoints(rates: &[u64]) -> Vec<RatedDatapoint> {
+        rates
+            .iter()
+            .map(|rate| RatedDatapoint {
+                rate: *rate,
+                reputation: 1,
+            })
+            .collect()
+    }
+
+    fn selected_rates(
+        strategy: &dyn AggregationStrategy,
+        rates: &[u64],
+        min_data_points: u32,
+    ) -> Result<Vec<u64>, RefreshActionError> {
+        let datapoints = datapoints(rates);
+        Ok(strategy
+            .select(&datapoints, 5, min_data_points)?
+            .selected
+            .into_iter()
+            .map(|i| datapoints[i].rate)
+            .collect())
+    }
+
+    /// Fixtures of the deviation check in the refresh tests, all resolving to the same cluster
+    fn fixtures() -> Vec<Vec<u64>> {
+        vec![
+            vec![95, 96, 97, 98, 99, 200],
+            vec![70, 95, 96, 97, 98, 99, 200],
+            vec![70, 95, 96, 97, 98, 99],
+            vec![70, 70, 95, 96, 97, 98, 99],
+            vec![95, 96, 97, 98, 99, 200, 200],
+        ]
+    }
+
+    #[test]
+    fn test_oracle_deviation_check() {
+        for rates in fixtures() {
+            assert_eq!(
+                selected_rates(&Mean, &rates, 4).unwrap(),
+                vec![95, 96, 97, 98, 99]
+            );
+        }
+    }
+
+    #[test]
+    fn test_median() {
+        for rates in fixtures() {
+            assert_eq!(
+                selected_rates(&Median, &rates, 4).unwrap(),
+                vec![95, 96, 97, 98, 99]
+            );
+        }
+        // the low end pulls the mean down so that the highest rate is dropped as well
+        assert_eq!(
+            selected_rates(&Mean, &[92, 93, 96, 97, 98, 100], 2).unwrap(),
+            vec![96, 97, 98]
+        );
+        assert_eq!(
+            selected_rates(&Median, &[92, 93, 96, 97, 98, 100], 2).unwrap(),
+            vec![96, 97, 98, 100]
+        );
+    }
+
+    #[test]
+    fn test_trimmed_mean() {
+        let strategy = TrimmedMean { trim_percent: 20 };
+        assert_eq!(
+            selected_rates(&strategy, &[70, 95, 96, 97, 98, 99, 200], 4).unwrap(),
+            vec![95, 96, 97, 98, 99]
+        );
+        assert_eq!(
+            selected_rates(&strategy, &[95, 96, 97, 98, 99, 200], 4).unwrap(),
+            vec![96, 97, 98, 99]
+        );
+        // does not trim below the minimum number of datapoints
+        assert_eq!(
+            selected_rates(&strategy, &[95, 96, 97, 98, 99, 200], 5).unwrap(),
+            vec![95, 96, 97, 98, 99]
+        );
+        assert_eq!(
+            selected_rates(
+                &TrimmedMean { trim_percent: 0 },
+                &[95, 96, 97, 98, 99, 200],
+                4
+            )
+            .unwrap(),
+            vec![95, 96, 97, 98, 99]
+        );
+    }
+
+    #[test]
+    fn test_reputation_weighted_mean() {
+        for rates in fixtures() {
+            assert_eq!(
+                selected_rates(&ReputationWeightedMean, &rates, 4).unwrap(),
+                vec![95, 96, 97, 98, 99]
+            );
+        }
+        let weighted_rates = |reputations: [u64; 6]| -> Vec<u64> {
+            let dps: Vec<RatedDatapoint> = [92, 93, 96, 97, 98, 100]
+                .into_iter()
+                .zip(reputations)
+                .map(|(rate, reputation)| RatedDatapoint { rate, reputation })
+                .collect();
+            ReputationWeightedMean
+                .select(&dps, 5, 2)
+                .unwrap()
+                .selected
+                .into_iter()
+                .map(|i| dps[i].rate)
+                .collect()
+        };
+        assert_eq!(weighted_rates([1, 1, 1, 1, 1, 1]), vec![96, 97, 98]);
+        // the highest rate is posted by a long-standing oracle
+        assert_eq!(weighted_rates([1, 1, 1, 1, 1, 10]), vec![96, 97, 98, 100]);
+        // the lowest rates are posted by long-standing oracles
+        assert_eq!(weighted_rates([10, 10, 1, 1, 1, 1]), vec![92, 93, 96]);
+    }
+
+    #[test]
+    fn test_not_enough_datapoints() {
+        let strategies: Vec<Box<dyn AggregationStrategy>> = vec![
+            Box::new(Mean),
+            Box::new(Median),
+            Box::new(TrimmedMean { trim_percent: 20 }),
+            Box::new(ReputationWeightedMean),
+        ];
+        for strategy in strategies {
+            assert!(selected_rates(&*strategy, &[], 4).unwrap().is_empty());
+            assert!(matches!(
+                selected_rates(&*strategy, &[70, 200], 1),
+                Err(RefreshActionError::NotEnoughDatapoints { .. })
+            ));
+        }
+        // the datapoints dropped before giving up are reported
+        let dps = datapoints(&[70, 100, 200]);
+        match Mean.select(&dps, 5, 1) {
+            Err(RefreshActionError::NotEnoughDatapoints { partial }) => assert_eq!(
+                partial,
+                Selection {
+                    selected: vec![0, 1],
+                    excluded: vec![(2, ExclusionReason::Outlier)],
+                }
+            ),
+            other => panic!("unexpected selection: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_one_datapoint_removed_at_a_time() {
+        let dps = datapoints(&[70, 70, 95, 96, 97, 98, 99, 200]);
+        let selection = Mean.select(&dps, 5, 4).unwrap();
+        assert_eq!(selection.selected, vec![2, 3, 4, 5, 6]);
+        assert_eq!(
+            selection.excluded,
+            vec![
+                (7, ExclusionReason::Outlier),
+                (0, ExclusionReason::Outlier),
+                (1, ExclusionReason::Outlier),
+            ]
+        );
+        // of the equal rates the outermost one is trimmed off
+        let dps = datapoints(&[95, 95, 96, 97, 97]);
+        let selection = TrimmedMean { trim_percent: 20 }.select(&dps, 5, 3).unwrap();
+        assert_eq!(selection.selected, vec![1, 2, 3]);
+        assert_eq!(
+            selection.excluded,
+            vec![(0, ExclusionReason::Trimmed), (4, ExclusionReason::Trimmed)]
+        );
+    }
+}
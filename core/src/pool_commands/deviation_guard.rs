@@ -0,0 +1,232 @@
+//! Holds back datapoints that deviate too much from the current pool rate until the move is
+//! confirmed by several consecutive fetches (or allowed by the operator). Protects the pool from a
+//! single fat-finger quote.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::datapoint_source::unix_timestamp_now;
+
+use super::publish_datapoint::PublishDatapointActionError;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeviationGuardConfig {
+    /// Datapoints deviating from the pool rate by more than this percent are held back
+    pub max_deviation_percent: u32,
+    /// Number of consecutive fetches deviating in the same direction needed to post
+    pub confirmations: u32,
+}
+
+/// Deviating datapoint waiting for confirmation
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct PendingDeviation {
+    pub pool_rate: i64,
+    /// Latest fetched datapoint
+    pub datapoint: i64,
+    pub deviation_percent: u64,
+    pub confirmations: u32,
+    pub required_confirmations: u32,
+    /// Unix timestamp of the first deviating fetch
+    pub since: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct DeviationGuardStatus {
+    pub enabled: bool,
+    /// Set if the operator allowed the next deviating datapoint to be posted
+    pub override_pending: bool,
+    pub pending: Option<PendingDeviation>,
+}
+
+#[derive(Debug)]
+pub struct DeviationGuard {
+    config: Option<DeviationGuardConfig>,
+    status: Mutex<DeviationGuardStatus>,
+}
+
+impl DeviationGuard {
+    /// Guard with the given config (disabled if `None`). With `allow_next_deviation` set the next
+    /// deviating datapoint is posted without confirmations.
+    pub fn new(config: Option<DeviationGuardConfig>, allow_next_deviation: bool) -> Self {
+        DeviationGuard {
+            config,
+            status: Mutex::new(DeviationGuardStatus {
+                enabled: config.is_some(),
+                override_pending: allow_next_deviation && config.is_some(),
+                pending: None,
+            }),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(None, false)
+    }
+
+    pub fn status(&self) -> DeviationGuardStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Checks the freshly fetched datapoint against the current pool rate. Returns an error if the
+    /// datapoint should not be posted (yet).
+    pub fn check(&self, datapoint: i64, pool_rate: i64) -> Result<(), PublishDatapointActionError> {
+        let config = match self.config {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        let mut status = self.status.lock().unwrap();
+        if pool_rate <= 0 {
+            // no meaningful rate to compare against
+            status.pending = None;
+            return Ok(());
+        }
+        let delta = (datapoint as i128 - pool_rate as i128).abs();
+        if delta * 100 <= pool_rate as i128 * config.max_deviation_percent as i128 {
+            if let Some(pending) = status.pending.take() {
+                log::info!(
+                    "Datapoint {} is back within {}% of pool rate {}, dropping pending deviation {:?}",
+                    datapoint,
+                    config.max_deviation_percent,
+                    pool_rate,
+                    pending
+                );
+            }
+            return Ok(());
+        }
+        let deviation_percent = (delta * 100 / pool_rate as i128) as u64;
+        if status.override_pending {
+            log::warn!(
+                "Datapoint {} deviates {}% from pool rate {}, posting due to operator override",
+                datapoint,
+                deviation_percent,
+                pool_rate
+            );
+            status.override_pending = false;
+            status.pending = None;
+            return Ok(());
+        }
+        let same_direction = |pending: &PendingDeviation| {
+            pending.pool_rate == pool_rate
+                && (pending.datapoint > pool_rate) == (datapoint > pool_rate)
+        };
+        let pending = match status.pending.take() {
+            Some(pending) if same_direction(&pending) => PendingDeviation {
+                datapoint,
+                deviation_percent,
+                confirmations: pending.confirmations + 1,
+                ..pending
+            },
+            _ => PendingDeviation {
+                pool_rate,
+                datapoint,
+                deviation_percent,
+                confirmations: 1,
+                required_confirmations: config.confirmations,
+                since: unix_timestamp_now(),
+            },
+        };
+        if pending.confirmations >= config.confirmations {
+            log::warn!(
+                "Datapoint {} deviates {}% from pool rate {}, posting after {} confirmations",
+                datapoint,
+                deviation_percent,
+                pool_rate,
+                pending.confirmations
+            );
+            return Ok(());
+        }
+        log::warn!(
+            "Holding back datapoint {} deviating {}% from pool rate {} (max {}%), confirmations {}/{}",
+            datapoint,
+            deviation_percent,
+            pool_rate,
+            config.max_deviation_percent,
+            pending.confirmations,
+            config.confirmations
+        );
+        status.pending = Some(pending.clone());
+        Err(PublishDatapointActionError::DeviationNotConfirmed {
+            datapoint,
+            pool_rate,
+            deviation_percent,
+            confirmations: pending.confirmations,
+            required_confirmations: config.confirmations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_guard(allow_next_deviation: bool) -> DeviationGuard {
+        DeviationGuard::new(
+            Some(DeviationGuardConfig {
+                max_deviation_percent: 10,
+                confirmations: 3,
+            }),
+            allow_next_deviation,
+        )
+    }
+
+    #[test]
+    fn test_within_deviation() {
+        let guard = make_guard(false);
+        assert!(guard.check(110, 100).is_ok());
+        assert!(guard.check(90, 100).is_ok());
+        assert_eq!(guard.status().pending, None);
+    }
+
+    #[test]
+    fn test_confirmed_after_consecutive_fetches() {
+        let guard = make_guard(false);
+        assert!(matches!(
+            guard.check(150, 100),
+            Err(PublishDatapointActionError::DeviationNotConfirmed {
+                deviation_percent: 50,
+                confirmations: 1,
+                ..
+            })
+        ));
+        assert!(guard.check(160, 100).is_err());
+        let pending = guard.status().pending.unwrap();
+        assert_eq!(pending.datapoint, 160);
+        assert_eq!(pending.confirmations, 2);
+        assert!(guard.check(155, 100).is_ok());
+        assert_eq!(guard.status().pending, None);
+    }
+
+    #[test]
+    fn test_direction_change_restarts_confirmations() {
+        let guard = make_guard(false);
+        assert!(guard.check(150, 100).is_err());
+        assert!(guard.check(150, 100).is_err());
+        assert!(guard.check(50, 100).is_err());
+        assert_eq!(guard.status().pending.unwrap().confirmations, 1);
+    }
+
+    #[test]
+    fn test_return_within_range_drops_pending() {
+        let guard = make_guard(false);
+        assert!(guard.check(150, 100).is_err());
+        assert!(guard.check(105, 100).is_ok());
+        assert!(guard.check(150, 100).is_err());
+        assert_eq!(guard.status().pending.unwrap().confirmations, 1);
+    }
+
+    #[test]
+    fn test_operator_override_is_used_once() {
+        let guard = make_guard(true);
+        assert!(guard.status().override_pending);
+        assert!(guard.check(150, 100).is_ok());
+        assert!(!guard.status().override_pending);
+        assert!(guard.check(200, 150).is_err());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let guard = DeviationGuard::disabled();
+        assert!(guard.check(1000, 1).is_ok());
+        assert!(!guard.status().enabled);
+    }
+}
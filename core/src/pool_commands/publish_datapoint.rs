@@ -1,4 +1,5 @@
 use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
 
 use derive_more::From;
 use ergo_lib::{
@@ -19,9 +20,10 @@ use crate::{
     actions::PublishDataPointAction,
     box_kind::{make_oracle_box_candidate, OracleBox, OracleBoxWrapper, OracleBoxWrapperInputs},
     contracts::oracle::{OracleContract, OracleContractError},
-    datapoint_source::{DataPointSource, DataPointSourceError},
+    datapoint_source::{DataPoint, DataPointSource, DataPointSourceError},
     oracle_config::BASE_FEE,
     oracle_state::StageError,
+    pool_commands::deviation_guard::DeviationGuard,
     spec_token::{OracleTokenId, RewardTokenId, SpecToken},
     wallet::{WalletDataError, WalletDataSource},
 };
@@ -44,18 +46,79 @@ pub enum PublishDatapointActionError {
     DataPointSource(DataPointSourceError),
     #[error("oracle contract error: {0}")]
     OracleContract(OracleContractError),
+    #[error("datapoint {datapoint} is stale: quoted {age:?} ago, max age is {max_age:?}")]
+    StaleDataPoint {
+        datapoint: i64,
+        age: Duration,
+        max_age: Duration,
+    },
+    #[error(
+        "datapoint {datapoint} is quoted {ahead:?} in the future, max clock skew is {max_skew:?}"
+    )]
+    FutureDataPoint {
+        datapoint: i64,
+        ahead: Duration,
+        max_skew: Duration,
+    },
+    #[error("datapoint {datapoint} deviates {deviation_percent}% from pool rate {pool_rate}, waiting for confirmation ({confirmations}/{required_confirmations})")]
+    DeviationNotConfirmed {
+        datapoint: i64,
+        pool_rate: i64,
+        deviation_percent: u64,
+        confirmations: u32,
+        required_confirmations: u32,
+    },
+}
+
+/// How far in the future a quote may be timestamped and still be taken as fresh, to allow for
+/// clock skew between the oracle and the source
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// Gets a datapoint from the source, refusing quotes older than `max_age` or further than
+/// `MAX_CLOCK_SKEW` in the future
+fn get_fresh_datapoint(
+    datapoint_source: &dyn DataPointSource,
+    max_age: Duration,
+) -> Result<DataPoint, PublishDatapointActionError> {
+    let datapoint = datapoint_source.get_datapoint()?;
+    let age_secs = datapoint.age_secs();
+    if age_secs < 0 {
+        let ahead = Duration::from_secs(age_secs.unsigned_abs());
+        if ahead > MAX_CLOCK_SKEW {
+            return Err(PublishDatapointActionError::FutureDataPoint {
+                datapoint: datapoint.value,
+                ahead,
+                max_skew: MAX_CLOCK_SKEW,
+            });
+        }
+        return Ok(datapoint);
+    }
+    let age = Duration::from_secs(age_secs as u64);
+    if age > max_age {
+        return Err(PublishDatapointActionError::StaleDataPoint {
+            datapoint: datapoint.value,
+            age,
+            max_age,
+        });
+    }
+    Ok(datapoint)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn build_subsequent_publish_datapoint_action(
     local_datapoint_box: &OracleBoxWrapper,
     wallet: &dyn WalletDataSource,
     height: u32,
     change_address: Address,
     datapoint_source: &dyn DataPointSource,
+    max_datapoint_age: Duration,
+    deviation_guard: &DeviationGuard,
     new_epoch_counter: u32,
-    _pool_datapoint: i64,
+    pool_datapoint: i64,
 ) -> Result<PublishDataPointAction, PublishDatapointActionError> {
-    let new_datapoint = datapoint_source.get_datapoint_retry(3)?;
+    let datapoint = get_fresh_datapoint(datapoint_source, max_datapoint_age)?;
+    let new_datapoint = datapoint.value;
+    deviation_guard.check(new_datapoint, pool_datapoint)?;
     let in_oracle_box = local_datapoint_box;
     if *in_oracle_box.reward_token().amount.as_u64() == 0 {
         return Err(PublishDatapointActionError::NoRewardTokenInOracleBox);
@@ -96,7 +159,12 @@ pub fn build_subsequent_publish_datapoint_action(
     };
     tx_builder.set_context_extension(in_oracle_box.get_box().box_id(), ctx_ext);
     let tx = tx_builder.build()?;
-    Ok(PublishDataPointAction { tx })
+    Ok(PublishDataPointAction {
+        tx,
+        epoch_counter: new_epoch_counter,
+        datapoint,
+        source_quotes: datapoint_source.source_quotes(),
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -107,8 +175,11 @@ pub fn build_publish_first_datapoint_action(
     public_key: ProveDlog,
     inputs: OracleBoxWrapperInputs,
     datapoint_source: &dyn DataPointSource,
+    max_datapoint_age: Duration,
+    pool_epoch_counter: u32,
 ) -> Result<PublishDataPointAction, PublishDatapointActionError> {
-    let new_datapoint = datapoint_source.get_datapoint_retry(3)?;
+    let datapoint = get_fresh_datapoint(datapoint_source, max_datapoint_age)?;
+    let new_datapoint = datapoint.value;
     let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
     let tx_fee = *BASE_FEE;
     let box_selector = SimpleBoxSelector::new();
@@ -157,7 +228,12 @@ pub fn build_publish_first_datapoint_action(
     };
     tx_builder.set_context_extension(box_id, ctx_ext);
     let tx = tx_builder.build()?;
-    Ok(PublishDataPointAction { tx })
+    Ok(PublishDataPointAction {
+        tx,
+        epoch_counter: pool_epoch_counter,
+        datapoint,
+        source_quotes: datapoint_source.source_quotes(),
+    })
 }
 
 #[cfg(test)]
@@ -168,6 +244,7 @@ mod tests {
     use crate::box_kind::PoolBox;
     use crate::contracts::oracle::OracleContractParameters;
     use crate::contracts::pool::PoolContractParameters;
+    use crate::datapoint_source::{unix_timestamp_now, DataPoint};
     use crate::oracle_state::PoolBoxSource;
     use crate::pool_commands::test_utils::{
         find_input_boxes, generate_token_ids, make_datapoint_box, make_pool_box,
@@ -187,17 +264,64 @@ mod tests {
     use ergo_lib::wallet::Wallet;
     use sigma_test_util::force_any_val;
 
+    const MAX_DATAPOINT_AGE: Duration = Duration::from_secs(300);
+
     #[derive(Debug)]
     struct MockDatapointSource {
         datapoint: i64,
+        age_secs: i64,
     }
 
     impl DataPointSource for MockDatapointSource {
-        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
-            Ok(self.datapoint)
+        fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+            Ok(DataPoint {
+                value: self.datapoint,
+                timestamp: unix_timestamp_now() - self.age_secs,
+            })
         }
     }
 
+    #[test]
+    fn test_stale_datapoint_is_refused() {
+        let fresh = MockDatapointSource {
+            datapoint: 201,
+            age_secs: 10,
+        };
+        assert_eq!(
+            get_fresh_datapoint(&fresh, MAX_DATAPOINT_AGE)
+                .unwrap()
+                .value,
+            201
+        );
+        let stale = MockDatapointSource {
+            datapoint: 201,
+            age_secs: 301,
+        };
+        assert!(matches!(
+            get_fresh_datapoint(&stale, MAX_DATAPOINT_AGE),
+            Err(PublishDatapointActionError::StaleDataPoint { datapoint: 201, .. })
+        ));
+        // quotes slightly in the future (clock skew) are taken as fresh
+        let skewed = MockDatapointSource {
+            datapoint: 201,
+            age_secs: -30,
+        };
+        assert_eq!(
+            get_fresh_datapoint(&skewed, MAX_DATAPOINT_AGE)
+                .unwrap()
+                .value,
+            201
+        );
+        let future = MockDatapointSource {
+            datapoint: 201,
+            age_secs: -3600,
+        };
+        assert!(matches!(
+            get_fresh_datapoint(&future, MAX_DATAPOINT_AGE),
+            Err(PublishDatapointActionError::FutureDataPoint { datapoint: 201, .. })
+        ));
+    }
+
     #[test]
     fn test_subsequent_publish_datapoint() {
         let ctx = force_any_val::<ErgoStateContext>();
@@ -256,13 +380,18 @@ mod tests {
             unspent_boxes: vec![wallet_unspent_box],
         };
 
-        let datapoint_source = MockDatapointSource { datapoint: 201 };
+        let datapoint_source = MockDatapointSource {
+            datapoint: 201,
+            age_secs: 0,
+        };
         let action = build_subsequent_publish_datapoint_action(
             &oracle_box,
             &wallet_mock,
             height,
             change_address.clone(),
             &datapoint_source,
+            MAX_DATAPOINT_AGE,
+            &DeviationGuard::disabled(),
             pool_box_epoch_id as u32,
             datapoint_source.datapoint - 1,
         )
@@ -370,7 +499,12 @@ mod tests {
             change_address,
             secret.public_image(),
             oracle_box_wrapper_inputs,
-            &MockDatapointSource { datapoint: 201 },
+            &MockDatapointSource {
+                datapoint: 201,
+                age_secs: 0,
+            },
+            MAX_DATAPOINT_AGE,
+            1,
         )
         .unwrap();
 
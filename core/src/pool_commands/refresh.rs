@@ -17,6 +17,11 @@ use crate::spec_token::SpecToken;
 use crate::wallet::WalletDataError;
 use crate::wallet::WalletDataSource;
 
+use super::aggregation::AggregationStrategy;
+use super::aggregation::ExclusionReason;
+use super::aggregation::RatedDatapoint;
+use super::aggregation::Selection;
+
 use derive_more::From;
 use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilderError;
 use ergo_lib::ergo_chain_types::EcPoint;
@@ -37,14 +42,18 @@ use std::convert::TryInto;
 
 #[derive(Debug, From, Error)]
 pub enum RefreshActionError {
-    #[error("Refresh failed, not enough datapoints. The minimum number of datapoints within the deviation range: required minumum {expected}, found {found_num} from public keys {found_public_keys:?},")]
+    #[error("Refresh failed, not enough datapoints. The minimum number of datapoints within the deviation range: required minumum {expected}, found {found_num} from public keys {found_public_keys:?}, excluded {excluded:?}")]
     FailedToReachConsensus {
         found_public_keys: Vec<ProveDlog>,
         found_num: u32,
         expected: u32,
+        excluded: Vec<ExcludedDatapoint>,
     },
     #[error("Not enough datapoints left during the removal of the outliers")]
-    NotEnoughDatapoints,
+    NotEnoughDatapoints {
+        /// Datapoints selected and excluded when the removal gave up
+        partial: Selection,
+    },
     #[error("stage error: {0}")]
     StageError(StageError),
     #[error("WalletData error: {0}")]
@@ -57,6 +66,121 @@ pub enum RefreshActionError {
     ErgoBoxCandidateBuilderError(ErgoBoxCandidateBuilderError),
     #[error("failed to found my own oracle box in the filtered posted oracle boxes")]
     MyOracleBoxNoFound,
+    #[error("pool box has {available} reward tokens, the refresh needs at least {required} (one stays in the pool box)")]
+    InsufficientPoolRewards { available: u64, required: u64 },
+}
+
+/// Posted datapoint left out of the refresh
+#[derive(Debug, Clone)]
+pub struct ExcludedDatapoint {
+    pub public_key: ProveDlog,
+    pub rate: u64,
+    pub reason: ExclusionReason,
+}
+
+/// Datapoints collected and left out by a refresh
+#[derive(Debug, Clone)]
+pub struct RefreshPlan {
+    /// Sorted by rate
+    pub collected: Vec<PostedOracleBox>,
+    /// In the order they were excluded
+    pub excluded: Vec<ExcludedDatapoint>,
+}
+
+impl RefreshPlan {
+    /// New pool rate, `None` if no datapoint is collected
+    pub fn pool_rate(&self) -> Option<u64> {
+        if self.collected.is_empty() {
+            None
+        } else {
+            Some(calc_pool_rate(
+                self.collected.iter().map(|b| b.rate()).collect(),
+            ))
+        }
+    }
+
+    /// Applies a selection made on the datapoints of the current epoch (as returned by
+    /// `current_epoch_datapoints`)
+    pub fn with_selection(mut self, selection: &Selection) -> RefreshPlan {
+        self.excluded
+            .extend(selection.excluded.iter().map(|(idx, reason)| {
+                let b = &self.collected[*idx];
+                ExcludedDatapoint {
+                    public_key: b.public_key(),
+                    rate: b.rate(),
+                    reason: *reason,
+                }
+            }));
+        RefreshPlan {
+            collected: selection
+                .selected
+                .iter()
+                .map(|idx| self.collected[*idx].clone())
+                .collect(),
+            excluded: self.excluded,
+        }
+    }
+}
+
+/// Splits the posted datapoints into the ones of the current epoch (collected, sorted by rate)
+/// and the ones the refresh can not collect, before any outlier is removed
+pub fn current_epoch_datapoints(
+    in_pool_box: &PoolBoxWrapper,
+    in_refresh_box: &RefreshBoxWrapper,
+    datapoint_stage_src: &dyn DatapointBoxesSource,
+    height: u32,
+) -> Result<RefreshPlan, RefreshActionError> {
+    let min_start_height = height - in_refresh_box.contract().epoch_length() as u32;
+    let in_pool_box_epoch_id = in_pool_box.epoch_counter();
+    let mut excluded = Vec::new();
+    let mut in_oracle_boxes = Vec::new();
+    for b in datapoint_stage_src.get_oracle_datapoint_boxes()? {
+        let exclusion_reason = if b.epoch_counter() != in_pool_box_epoch_id {
+            Some(ExclusionReason::WrongEpoch)
+        } else if b.get_box().creation_height <= min_start_height {
+            Some(ExclusionReason::Expired)
+        } else {
+            None
+        };
+        match exclusion_reason {
+            Some(reason) => excluded.push(ExcludedDatapoint {
+                public_key: b.public_key(),
+                rate: b.rate(),
+                reason,
+            }),
+            None => in_oracle_boxes.push(b),
+        }
+    }
+    // log::info!("Building refresh action {:?}", in_oracle_boxes);
+    // equal rates are ordered by box id so that the outlier removal is deterministic
+    in_oracle_boxes.sort_by_key(|b| (b.rate(), String::from(b.get_box().box_id())));
+    Ok(RefreshPlan {
+        collected: in_oracle_boxes,
+        excluded,
+    })
+}
+
+/// Selects the posted datapoints of the current epoch the refresh tx would collect
+pub fn plan_refresh(
+    in_pool_box: &PoolBoxWrapper,
+    in_refresh_box: &RefreshBoxWrapper,
+    datapoint_stage_src: &dyn DatapointBoxesSource,
+    max_deviation_percent: u32,
+    min_data_points: u32,
+    aggregation: &dyn AggregationStrategy,
+    height: u32,
+) -> Result<RefreshPlan, RefreshActionError> {
+    let epoch = current_epoch_datapoints(in_pool_box, in_refresh_box, datapoint_stage_src, height)?;
+    let datapoints: Vec<RatedDatapoint> = epoch
+        .collected
+        .iter()
+        .map(|b| RatedDatapoint {
+            rate: b.rate(),
+            reputation: *b.reward_token().amount.as_u64(),
+        })
+        .collect();
+    let selection = aggregation.select(&datapoints, max_deviation_percent, min_data_points)?;
+    Ok(epoch.with_selection(&selection))
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -66,6 +190,7 @@ pub fn build_refresh_action(
     datapoint_stage_src: &dyn DatapointBoxesSource,
     max_deviation_percent: u32,
     min_data_points: u32,
+    aggregation: &dyn AggregationStrategy,
     wallet: &dyn WalletDataSource,
     height: u32,
     change_address: Address,
@@ -74,38 +199,35 @@ pub fn build_refresh_action(
     let tx_fee = *BASE_FEE;
     let in_pool_box = pool_box_source.get_pool_box()?;
     let in_refresh_box = refresh_box_source.get_refresh_box()?;
-    let min_start_height = height - in_refresh_box.contract().epoch_length() as u32;
-    let in_pool_box_epoch_id = in_pool_box.epoch_counter();
-    let mut in_oracle_boxes: Vec<PostedOracleBox> = datapoint_stage_src
-        .get_oracle_datapoint_boxes()?
-        .into_iter()
-        .filter(|b| {
-            b.get_box().creation_height > min_start_height
-                && b.epoch_counter() == in_pool_box_epoch_id
-        })
-        .collect();
-    // log::info!("Building refresh action {:?}", in_oracle_boxes);
-    let deviation_range = max_deviation_percent;
-    in_oracle_boxes.sort_by_key(|b| b.rate());
-    let valid_in_oracle_boxes_datapoints = filtered_oracle_boxes_by_rate(
-        in_oracle_boxes.iter().map(|b| b.rate()).collect(),
-        deviation_range,
+    let plan = plan_refresh(
+        &in_pool_box,
+        &in_refresh_box,
+        datapoint_stage_src,
+        max_deviation_percent,
+        min_data_points,
+        aggregation,
+        height,
     )?;
-    let valid_in_oracle_boxes = in_oracle_boxes
-        .into_iter()
-        .filter(|b| valid_in_oracle_boxes_datapoints.contains(&b.rate()))
-        .collect::<Vec<_>>();
-    if (valid_in_oracle_boxes.len() as u32) < min_data_points {
-        return Err(RefreshActionError::FailedToReachConsensus {
-            found_num: valid_in_oracle_boxes.len() as u32,
-            expected: min_data_points,
-            found_public_keys: valid_in_oracle_boxes
-                .iter()
-                .map(|b| b.public_key())
-                .collect(),
-        });
+    for e in &plan.excluded {
+        log::info!(
+            "Refresh: excluding datapoint {} of {:?}: {}",
+            e.rate,
+            e.public_key,
+            e.reason
+        );
     }
-    let rate = calc_pool_rate(valid_in_oracle_boxes.iter().map(|b| b.rate()).collect());
+    let rate = match plan.pool_rate() {
+        Some(rate) if plan.collected.len() as u32 >= min_data_points => rate,
+        Some(_) | None => {
+            return Err(RefreshActionError::FailedToReachConsensus {
+                found_num: plan.collected.len() as u32,
+                expected: min_data_points,
+                found_public_keys: plan.collected.iter().map(|b| b.public_key()).collect(),
+                excluded: plan.excluded,
+            })
+        }
+    };
+    let valid_in_oracle_boxes = plan.collected;
     let reward_decrement = valid_in_oracle_boxes.len() as u64 * 2;
     let out_pool_box = build_out_pool_box(&in_pool_box, height, rate, reward_decrement)?;
     let out_refresh_box = build_out_refresh_box(&in_refresh_box, height)?;
@@ -172,61 +294,6 @@ pub fn build_refresh_action(
     Ok(RefreshAction { tx })
 }
 
-fn filtered_oracle_boxes_by_rate(
-    oracle_boxes: Vec<u64>,
-    deviation_range: u32,
-) -> Result<Vec<u64>, RefreshActionError> {
-    if oracle_boxes.is_empty() {
-        return Ok(oracle_boxes);
-    }
-    let mut successful_boxes = oracle_boxes.clone();
-    // The min oracle box's rate must be within deviation_range(5%) of that of the max
-    while !deviation_check(deviation_range, &successful_boxes) {
-        // Removing largest deviation outlier
-        successful_boxes = remove_largest_local_deviation_datapoint(successful_boxes)?;
-    }
-    // dbg!(&successful_boxes);
-    Ok(successful_boxes)
-}
-
-fn deviation_check(max_deviation_range: u32, datapoint_boxes: &Vec<u64>) -> bool {
-    let min_datapoint = datapoint_boxes.iter().min().unwrap();
-    let max_datapoint = datapoint_boxes.iter().max().unwrap();
-    let deviation_delta = max_datapoint * (max_deviation_range as u64) / 100;
-    max_datapoint - min_datapoint <= deviation_delta
-}
-
-/// Finds whether the max or the min value in a list of sorted Datapoint boxes
-/// deviates more compared to their adjacted datapoint, and then removes
-/// said datapoint which deviates further.
-fn remove_largest_local_deviation_datapoint(
-    datapoint_boxes: Vec<u64>,
-) -> Result<Vec<u64>, RefreshActionError> {
-    // Check if sufficient number of datapoint boxes to start removing
-    if datapoint_boxes.len() <= 2 {
-        Err(RefreshActionError::NotEnoughDatapoints)
-    } else {
-        let mean = (datapoint_boxes.iter().sum::<u64>() as f32) / datapoint_boxes.len() as f32;
-        let min_datapoint = *datapoint_boxes.iter().min().unwrap();
-        let max_datapoint = *datapoint_boxes.iter().max().unwrap();
-        let front_deviation = max_datapoint as f32 - mean;
-        let back_deviation = mean - min_datapoint as f32;
-        if front_deviation >= back_deviation {
-            // Remove largest datapoint if front deviation is greater
-            Ok(datapoint_boxes
-                .into_iter()
-                .filter(|dp| *dp != max_datapoint)
-                .collect())
-        } else {
-            // Remove smallest datapoint if back deviation is greater
-            Ok(datapoint_boxes
-                .into_iter()
-                .filter(|dp| *dp != min_datapoint)
-                .collect())
-        }
-    }
-}
-
 fn calc_pool_rate(oracle_boxes_rates: Vec<u64>) -> u64 {
     let datapoints_sum: u64 = oracle_boxes_rates.iter().sum();
     datapoints_sum / oracle_boxes_rates.len() as u64
@@ -240,12 +307,17 @@ fn build_out_pool_box(
 ) -> Result<ErgoBoxCandidate, RefreshActionError> {
     let new_epoch_counter: i32 = (in_pool_box.epoch_counter() + 1) as i32;
     let reward_token = in_pool_box.reward_token();
+    let new_amount = reward_decrement
+        .try_into()
+        .ok()
+        .and_then(|decrement| reward_token.amount.checked_sub(&decrement).ok())
+        .ok_or(RefreshActionError::InsufficientPoolRewards {
+            available: *reward_token.amount.as_u64(),
+            required: reward_decrement + 1,
+        })?;
     let new_reward_token: SpecToken<RewardTokenId> = SpecToken {
         token_id: reward_token.token_id,
-        amount: reward_token
-            .amount
-            .checked_sub(&reward_decrement.try_into().unwrap())
-            .unwrap(),
+        amount: new_amount,
     };
 
     make_pool_box_candidate(
@@ -308,129 +380,30 @@ fn build_out_oracle_boxes(
 
 #[cfg(test)]
 mod tests {
-    use std::convert::TryFrom;
-    use std::convert::TryInto;
-
     use ergo_lib::chain::ergo_state_context::ErgoStateContext;
-    use ergo_lib::chain::transaction::TxId;
     use ergo_lib::ergo_chain_types::EcPoint;
     use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
     use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
-    use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
     use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
-    use ergo_lib::ergotree_ir::chain::ergo_box::NonMandatoryRegisters;
-    use ergo_lib::ergotree_ir::chain::token::Token;
     use ergo_lib::wallet::signing::TransactionContext;
     use ergo_lib::wallet::Wallet;
     use sigma_test_util::force_any_val;
 
-    use crate::box_kind::OracleBoxWrapperInputs;
-    use crate::box_kind::PostedOracleBox;
-    use crate::box_kind::RefreshBoxWrapper;
     use crate::box_kind::RefreshBoxWrapperInputs;
     use crate::contracts::oracle::OracleContractParameters;
     use crate::contracts::pool::PoolContractParameters;
-    use crate::contracts::refresh::RefreshContract;
     use crate::contracts::refresh::RefreshContractInputs;
     use crate::contracts::refresh::RefreshContractParameters;
-    use crate::oracle_config::TokenIds;
     use crate::oracle_config::BASE_FEE;
-    use crate::oracle_state::StageError;
+    use crate::pool_commands::aggregation::Mean;
     use crate::pool_commands::test_utils::generate_token_ids;
     use crate::pool_commands::test_utils::{
-        find_input_boxes, make_datapoint_box, make_pool_box, make_wallet_unspent_box, PoolBoxMock,
-        WalletDataMock,
+        find_input_boxes, make_datapoint_boxes, make_pool_box, make_refresh_box,
+        make_wallet_unspent_box, DatapointStageMock, PoolBoxMock, RefreshBoxMock, WalletDataMock,
     };
-    use crate::spec_token::TokenIdKind;
 
     use super::*;
 
-    #[derive(Clone)]
-    struct RefreshBoxMock {
-        refresh_box: RefreshBoxWrapper,
-    }
-
-    impl RefreshBoxSource for RefreshBoxMock {
-        fn get_refresh_box(&self) -> std::result::Result<RefreshBoxWrapper, StageError> {
-            Ok(self.refresh_box.clone())
-        }
-    }
-
-    #[derive(Clone)]
-    struct DatapointStageMock {
-        datapoints: Vec<PostedOracleBox>,
-    }
-
-    impl DatapointBoxesSource for DatapointStageMock {
-        fn get_oracle_datapoint_boxes(
-            &self,
-        ) -> std::result::Result<Vec<PostedOracleBox>, StageError> {
-            Ok(self.datapoints.clone())
-        }
-    }
-
-    fn make_refresh_box(
-        value: BoxValue,
-        inputs: &RefreshBoxWrapperInputs,
-        creation_height: u32,
-    ) -> RefreshBoxWrapper {
-        let tokens = vec![Token::from((
-            inputs.refresh_nft_token_id.token_id(),
-            1u64.try_into().unwrap(),
-        ))]
-        .try_into()
-        .unwrap();
-        RefreshBoxWrapper::new(
-            ErgoBox::new(
-                value,
-                RefreshContract::checked_load(&inputs.contract_inputs)
-                    .unwrap()
-                    .ergo_tree(),
-                Some(tokens),
-                NonMandatoryRegisters::empty(),
-                creation_height,
-                force_any_val::<TxId>(),
-                0,
-            )
-            .unwrap(),
-            inputs,
-        )
-        .unwrap()
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    fn make_datapoint_boxes(
-        pub_keys: Vec<EcPoint>,
-        datapoints: Vec<i64>,
-        epoch_counter: i32,
-        value: BoxValue,
-        creation_height: u32,
-        oracle_contract_parameters: &OracleContractParameters,
-        token_ids: &TokenIds,
-    ) -> Vec<PostedOracleBox> {
-        let oracle_box_wrapper_inputs =
-            OracleBoxWrapperInputs::try_from((oracle_contract_parameters.clone(), token_ids))
-                .unwrap();
-        datapoints
-            .into_iter()
-            .zip(pub_keys)
-            .map(|(datapoint, pub_key)| {
-                PostedOracleBox::new(
-                    make_datapoint_box(
-                        pub_key.clone(),
-                        datapoint,
-                        epoch_counter,
-                        token_ids,
-                        value,
-                        creation_height,
-                    ),
-                    &oracle_box_wrapper_inputs,
-                )
-                .unwrap()
-            })
-            .collect()
-    }
-
     #[test]
     fn test_refresh_pool() {
         let ctx = force_any_val::<ErgoStateContext>();
@@ -517,6 +490,7 @@ mod tests {
             }),
             5,
             4,
+            &Mean,
             &wallet_mock,
             height,
             change_address.clone(),
@@ -561,6 +535,7 @@ mod tests {
                 }),
                 5,
                 4,
+                &Mean,
                 &wallet_mock,
                 height,
                 change_address,
@@ -572,26 +547,23 @@ mod tests {
     }
 
     #[test]
-    fn test_oracle_deviation_check() {
-        assert_eq!(
-            filtered_oracle_boxes_by_rate(vec![95, 96, 97, 98, 99, 200], 5).unwrap(),
-            vec![95, 96, 97, 98, 99]
-        );
-        assert_eq!(
-            filtered_oracle_boxes_by_rate(vec![70, 95, 96, 97, 98, 99, 200], 5).unwrap(),
-            vec![95, 96, 97, 98, 99]
-        );
-        assert_eq!(
-            filtered_oracle_boxes_by_rate(vec![70, 95, 96, 97, 98, 99], 5).unwrap(),
-            vec![95, 96, 97, 98, 99]
-        );
-        assert_eq!(
-            filtered_oracle_boxes_by_rate(vec![70, 70, 95, 96, 97, 98, 99], 5).unwrap(),
-            vec![95, 96, 97, 98, 99]
-        );
-        assert_eq!(
-            filtered_oracle_boxes_by_rate(vec![95, 96, 97, 98, 99, 200, 200], 5).unwrap(),
-            vec![95, 96, 97, 98, 99]
+    fn test_insufficient_pool_rewards() {
+        // the pool box holds 100 reward tokens
+        let in_pool_box = make_pool_box(
+            200,
+            1,
+            *BASE_FEE,
+            100,
+            &PoolContractParameters::default(),
+            &generate_token_ids(),
         );
+        assert!(build_out_pool_box(&in_pool_box, 130, 200, 98).is_ok());
+        assert!(matches!(
+            build_out_pool_box(&in_pool_box, 130, 200, 100),
+            Err(RefreshActionError::InsufficientPoolRewards {
+                available: 100,
+                required: 101
+            })
+        ));
     }
 }
@@ -20,7 +20,6 @@ use ergo_lib::ergotree_ir::mir::expr::Expr;
 use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
 use ergo_lib::wallet::signing::TransactionContext;
 use ergo_lib::wallet::Wallet;
-use ergo_node_interface::node_interface::NodeError;
 use sigma_test_util::force_any_val;
 
 use crate::box_kind::BallotBoxWrapper;
@@ -28,6 +27,9 @@ use crate::box_kind::OracleBoxWrapper;
 use crate::box_kind::OracleBoxWrapperInputs;
 use crate::box_kind::PoolBoxWrapper;
 use crate::box_kind::PoolBoxWrapperInputs;
+use crate::box_kind::PostedOracleBox;
+use crate::box_kind::RefreshBoxWrapper;
+use crate::box_kind::RefreshBoxWrapperInputs;
 use crate::box_kind::UpdateBoxWrapper;
 use crate::box_kind::VoteBallotBoxWrapper;
 use crate::contracts::oracle::OracleContract;
@@ -37,12 +39,16 @@ use crate::contracts::oracle::OracleContractParameters;
 use crate::contracts::pool::PoolContract;
 use crate::contracts::pool::PoolContractInputs;
 use crate::contracts::pool::PoolContractParameters;
+use crate::contracts::refresh::RefreshContract;
 use crate::node_interface::SignTransaction;
 use crate::oracle_config::TokenIds;
+use crate::oracle_state::DatapointBoxesSource;
 use crate::oracle_state::LocalBallotBoxSource;
+use crate::oracle_state::RefreshBoxSource;
 use crate::oracle_state::UpdateBoxSource;
 use crate::oracle_state::VoteBallotBoxesSource;
 use crate::oracle_state::{LocalDatapointBoxSource, PoolBoxSource, StageError};
+use crate::signer::SignerError;
 use crate::spec_token::BallotTokenId;
 use crate::spec_token::OracleTokenId;
 use crate::spec_token::PoolTokenId;
@@ -120,6 +126,28 @@ impl UpdateBoxSource for UpdateBoxMock {
     }
 }
 
+#[derive(Clone)]
+pub(crate) struct RefreshBoxMock {
+    pub refresh_box: RefreshBoxWrapper,
+}
+
+impl RefreshBoxSource for RefreshBoxMock {
+    fn get_refresh_box(&self) -> std::result::Result<RefreshBoxWrapper, StageError> {
+        Ok(self.refresh_box.clone())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct DatapointStageMock {
+    pub datapoints: Vec<PostedOracleBox>,
+}
+
+impl DatapointBoxesSource for DatapointStageMock {
+    fn get_oracle_datapoint_boxes(&self) -> std::result::Result<Vec<PostedOracleBox>, StageError> {
+        Ok(self.datapoints.clone())
+    }
+}
+
 pub(crate) fn make_pool_box(
     datapoint: i64,
     epoch_counter: i32,
@@ -224,6 +252,67 @@ pub(crate) fn make_datapoint_box(
     .unwrap()
 }
 
+pub(crate) fn make_refresh_box(
+    value: BoxValue,
+    inputs: &RefreshBoxWrapperInputs,
+    creation_height: u32,
+) -> RefreshBoxWrapper {
+    let tokens = vec![Token::from((
+        inputs.refresh_nft_token_id.token_id(),
+        1u64.try_into().unwrap(),
+    ))]
+    .try_into()
+    .unwrap();
+    RefreshBoxWrapper::new(
+        ErgoBox::new(
+            value,
+            RefreshContract::checked_load(&inputs.contract_inputs)
+                .unwrap()
+                .ergo_tree(),
+            Some(tokens),
+            NonMandatoryRegisters::empty(),
+            creation_height,
+            force_any_val::<TxId>(),
+            0,
+        )
+        .unwrap(),
+        inputs,
+    )
+    .unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn make_datapoint_boxes(
+    pub_keys: Vec<EcPoint>,
+    datapoints: Vec<i64>,
+    epoch_counter: i32,
+    value: BoxValue,
+    creation_height: u32,
+    oracle_contract_parameters: &OracleContractParameters,
+    token_ids: &TokenIds,
+) -> Vec<PostedOracleBox> {
+    let oracle_box_wrapper_inputs =
+        OracleBoxWrapperInputs::try_from((oracle_contract_parameters.clone(), token_ids)).unwrap();
+    datapoints
+        .into_iter()
+        .zip(pub_keys)
+        .map(|(datapoint, pub_key)| {
+            PostedOracleBox::new(
+                make_datapoint_box(
+                    pub_key.clone(),
+                    datapoint,
+                    epoch_counter,
+                    token_ids,
+                    value,
+                    creation_height,
+                ),
+                &oracle_box_wrapper_inputs,
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
 pub(crate) fn make_wallet_unspent_box(
     pub_key: ProveDlog,
     value: BoxValue,
@@ -270,7 +359,7 @@ impl<'a> SignTransaction for LocalTxSigner<'a> {
         unsigned_tx: &UnsignedTransaction,
         inputs: TxIoVec<ErgoBox>,
         data_boxes: Option<TxIoVec<ErgoBox>>,
-    ) -> Result<ergo_lib::chain::transaction::Transaction, NodeError> {
+    ) -> Result<ergo_lib::chain::transaction::Transaction, SignerError> {
         let tx = self
             .wallet
             .sign_transaction(
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::address_util::{address_to_raw_for_register, AddressUtilError};
 use crate::box_kind::{PoolBoxWrapperInputs, RefreshBoxWrapperInputs};
@@ -6,11 +7,12 @@ use crate::contracts::pool::{PoolContract, PoolContractError};
 use crate::contracts::refresh::{RefreshContract, RefreshContractError};
 /// This file holds logic related to UTXO-set scans
 use crate::node_interface::{get_scan_boxes, register_scan};
-use crate::spec_token::{BallotTokenId, OracleTokenId, UpdateTokenId};
+use crate::spec_token::{BallotTokenId, OracleTokenId, TokenIdKind, UpdateTokenId};
 
 use derive_more::From;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
-use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::ergo_box::{ErgoBox, NonMandatoryRegisterId};
+use ergo_lib::ergotree_ir::chain::token::TokenId;
 use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
 use ergo_lib::ergotree_ir::mir::constant::Constant;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
@@ -42,30 +44,122 @@ pub enum ScanError {
     AddressUtilError(AddressUtilError),
 }
 
-/// A `Scan` is a name + scan_id for a given scan with extra methods for acquiring boxes.
+pub const DATAPOINT_SCAN: &str = "All Datapoints Scan";
+pub const LOCAL_DATAPOINT_SCAN: &str = "Local Oracle Datapoint Scan";
+pub const LOCAL_BALLOT_BOX_SCAN: &str = "Local Ballot Box Scan";
+pub const BALLOT_BOX_SCAN: &str = "Ballot Box Scan";
+pub const POOL_BOX_SCAN: &str = "Pool Box Scan";
+pub const REFRESH_BOX_SCAN: &str = "Refresh Box Scan";
+pub const UPDATE_BOX_SCAN: &str = "Update Box Scan";
+
+pub trait TokenBoxSource {
+    /// Unspent boxes holding the token in the UTXO set
+    fn get_unspent_token_boxes(&self, token_id: &TokenId) -> Result<Vec<ErgoBox>, NodeError>;
+}
+
+/// Where the boxes of the scans are found
+#[derive(Clone)]
+pub enum BoxLookup {
+    /// Scans registered with the wallet of the configured node, with the ids in `scanIDs.json`
+    NodeScans(JsonValue),
+    /// Boxes holding the scan's token in the blockchain index of a node, filtered by the rest of
+    /// the tracking rule
+    BlockchainIndex(Arc<dyn TokenBoxSource + Send + Sync>),
+}
+
+/// Unspent boxes holding a token, with the given script and R4 if set
+#[derive(Debug, Clone)]
+pub struct TrackingRule {
+    token_id: TokenId,
+    ergo_tree: Option<ErgoTree>,
+    /// Serialized R4 constant, base16
+    r4: Option<String>,
+}
+
+impl TrackingRule {
+    fn to_json(&self) -> serde_json::Value {
+        let mut args = vec![json!({
+            "predicate": "containsAsset",
+            "assetId": self.token_id,
+        })];
+        if let Some(ergo_tree) = &self.ergo_tree {
+            args.push(json!({
+                "predicate": "equals",
+                "value": ergo_tree.to_scan_bytes(),
+            }));
+        }
+        if let Some(r4) = &self.r4 {
+            args.push(json!({
+                "predicate": "equals",
+                "register": "R4",
+                "value": r4,
+            }));
+        }
+        json!({
+            "predicate": "and",
+            "args": args,
+        })
+    }
+
+    fn matches(&self, b: &ErgoBox) -> bool {
+        let has_token = b.tokens.as_ref().map_or(false, |tokens| {
+            tokens.iter().any(|t| t.token_id == self.token_id)
+        });
+        let r4 = || {
+            b.get_register(NonMandatoryRegisterId::R4.into())
+                .and_then(|c| c.sigma_serialize_bytes().ok())
+                .map(|bytes| base16::encode_lower(&bytes))
+        };
+        has_token
+            && self.ergo_tree.as_ref().map_or(true, |t| *t == b.ergo_tree)
+            && self
+                .r4
+                .as_ref()
+                .map_or(true, |r4_bytes| r4().as_ref() == Some(r4_bytes))
+    }
+}
+
+#[derive(Clone)]
+enum ScanSource {
+    Node(ScanID),
+    Index(Arc<dyn TokenBoxSource + Send + Sync>),
+}
+
+impl std::fmt::Debug for ScanSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanSource::Node(id) => write!(f, "Node({})", id),
+            ScanSource::Index(_) => write!(f, "Index"),
+        }
+    }
+}
+
+/// A `Scan` is a name + tracking rule with extra methods for acquiring boxes.
 #[derive(Debug, Clone)]
 pub struct Scan {
     name: &'static str,
-    id: ScanID,
+    rule: TrackingRule,
+    source: ScanSource,
 }
 
 impl Scan {
-    /// Create a new `Scan` with provided name & scan_id
-    pub fn new(name: &'static str, scan_id: &String) -> Scan {
-        Scan {
-            name,
-            id: scan_id.clone(),
-        }
+    /// Create a new `Scan` with the scan id saved under `name`, or the blockchain index
+    pub fn new(name: &'static str, rule: TrackingRule, lookup: &BoxLookup) -> Scan {
+        let source = match lookup {
+            BoxLookup::NodeScans(scan_ids) => ScanSource::Node(scan_ids[name].to_string()),
+            BoxLookup::BlockchainIndex(index) => ScanSource::Index(index.clone()),
+        };
+        Scan { name, rule, source }
     }
 
     /// Registers a scan in the node and returns a `Scan` as a result
     pub fn register(
         name: &'static str,
-        tracking_rule: serde_json::Value,
+        rule: TrackingRule,
     ) -> std::result::Result<Scan, ScanError> {
         let scan_json = json!({
             "scanName": name,
-            "trackingRule": tracking_rule,
+            "trackingRule": rule.to_json(),
         });
 
         info!(
@@ -76,12 +170,23 @@ impl Scan {
         let scan_id = register_scan(&scan_json)?;
         info!("Scan Successfully Set.\nID: {}", scan_id);
 
-        Ok(Scan::new(name, &scan_id))
+        Ok(Scan {
+            name,
+            rule,
+            source: ScanSource::Node(scan_id),
+        })
     }
 
     /// Returns all boxes found by the scan
     pub fn get_boxes(&self) -> std::result::Result<Vec<ErgoBox>, ScanError> {
-        let boxes = get_scan_boxes(&self.id)?;
+        let boxes = match &self.source {
+            ScanSource::Node(id) => get_scan_boxes(id)?,
+            ScanSource::Index(index) => index
+                .get_unspent_token_boxes(&self.rule.token_id)?
+                .into_iter()
+                .filter(|b| self.rule.matches(b))
+                .collect(),
+        };
         Ok(boxes)
     }
 
@@ -101,10 +206,11 @@ pub fn get_scans_file_path() -> PathBuf {
 pub fn save_scan_ids(scans: Vec<Scan>) -> std::result::Result<(), ScanError> {
     let mut id_json = json!({});
     for scan in scans {
-        if &scan.id == "null" {
-            return Err(ScanError::FailedToRegister);
-        }
-        id_json[scan.name] = scan.id.into();
+        let id = match scan.source {
+            ScanSource::Node(id) if id != "null" => id,
+            ScanSource::Node(_) | ScanSource::Index(_) => return Err(ScanError::FailedToRegister),
+        };
+        id_json[scan.name] = id.into();
     }
     let path = get_scans_file_path();
     log::debug!("Saving scan IDs to {}", path.display());
@@ -118,182 +224,89 @@ pub fn load_scan_ids() -> Result<JsonValue, anyhow::Error> {
     Ok(json::parse(&std::fs::read_to_string(path)?)?)
 }
 
-/// This function registers scanning for the pool box
-pub fn register_pool_box_scan(
-    inputs: PoolBoxWrapperInputs,
-) -> std::result::Result<Scan, ScanError> {
-    // ErgoTree bytes of the P2S address/script
-    let pool_box_tree_bytes = PoolContract::checked_load(&inputs.contract_inputs)?
-        .ergo_tree()
-        .to_scan_bytes();
-
-    // Scan for NFT id + Oracle Pool Epoch address
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": inputs.pool_nft_token_id.clone(),
-        },
-        {
-            "predicate": "equals",
-            "value": &pool_box_tree_bytes
-        }
-    ]
-    } );
-
-    Scan::register("Pool Box Scan", scan_json)
+/// Rule for the pool box: the pool NFT and the pool contract
+pub fn pool_box_rule(
+    inputs: &PoolBoxWrapperInputs,
+) -> std::result::Result<TrackingRule, ScanError> {
+    Ok(TrackingRule {
+        token_id: inputs.pool_nft_token_id.token_id(),
+        ergo_tree: Some(PoolContract::checked_load(&inputs.contract_inputs)?.ergo_tree()),
+        r4: None,
+    })
 }
 
-/// This function registers scanning for the refresh box
-pub fn register_refresh_box_scan(
-    scan_name: &'static str,
-    inputs: RefreshBoxWrapperInputs,
-) -> std::result::Result<Scan, ScanError> {
-    // ErgoTree bytes of the P2S address/script
-    let tree_bytes = RefreshContract::checked_load(&inputs.contract_inputs)?
-        .ergo_tree()
-        .to_scan_bytes();
-
-    // Scan for NFT id + Oracle Pool Epoch address
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": inputs.refresh_nft_token_id.clone(),
-        },
-        {
-            "predicate": "equals",
-            "value": tree_bytes,
-        }
-    ]
-    } );
-
-    Scan::register(scan_name, scan_json)
+/// Rule for the refresh box: the refresh NFT and the refresh contract
+pub fn refresh_box_rule(
+    inputs: &RefreshBoxWrapperInputs,
+) -> std::result::Result<TrackingRule, ScanError> {
+    Ok(TrackingRule {
+        token_id: inputs.refresh_nft_token_id.token_id(),
+        ergo_tree: Some(RefreshContract::checked_load(&inputs.contract_inputs)?.ergo_tree()),
+        r4: None,
+    })
 }
 
-/// This function registers scanning for the oracle's personal Datapoint box
-pub fn register_local_oracle_datapoint_scan(
+/// Rule for the oracle's personal Datapoint box: the oracle token, the datapoint contract and the
+/// oracle address in R4
+pub fn local_oracle_datapoint_rule(
     oracle_pool_participant_token: &OracleTokenId,
     datapoint_address: &ErgoTree,
     oracle_address: &NetworkAddress,
-) -> std::result::Result<Scan, ScanError> {
-    // Raw EC bytes + type identifier
-    let oracle_add_bytes = address_to_raw_for_register(&oracle_address.to_base58())?;
-    let datapoint_bytes = datapoint_address.to_scan_bytes();
-
-    // Scan for pool participant token id + datapoint contract address + oracle_address in R4
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": oracle_pool_participant_token.clone(),
-        },
-        {
-            "predicate": "equals",
-            "value": datapoint_bytes,
-        },
-        {
-            "predicate": "equals",
-            "register": "R4",
-            "value": oracle_add_bytes.clone(),
-        }
-    ]
-    } );
-
-    Scan::register("Local Oracle Datapoint Scan", scan_json)
+) -> std::result::Result<TrackingRule, ScanError> {
+    Ok(TrackingRule {
+        token_id: oracle_pool_participant_token.token_id(),
+        ergo_tree: Some(datapoint_address.clone()),
+        // Raw EC bytes + type identifier
+        r4: Some(address_to_raw_for_register(&oracle_address.to_base58())?),
+    })
 }
 
-/// This function registers scanning for all of the pools oracles' Datapoint boxes for datapoint collection
-pub fn register_datapoint_scan(
+/// Rule for all of the pools oracles' Datapoint boxes for datapoint collection
+pub fn datapoint_rule(
     oracle_pool_participant_token: &OracleTokenId,
     datapoint_address: &ErgoTree,
-) -> std::result::Result<Scan, ScanError> {
-    let datapoint_bytes = datapoint_address.to_scan_bytes();
-    // Scan for pool participant token id + datapoint contract address + oracle_address in R4
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": oracle_pool_participant_token.clone(),
-        },
-        {
-            "predicate": "equals",
-            "value": datapoint_bytes,
-        }
-    ]
-    } );
-
-    Scan::register("All Datapoints Scan", scan_json)
+) -> TrackingRule {
+    TrackingRule {
+        token_id: oracle_pool_participant_token.token_id(),
+        ergo_tree: Some(datapoint_address.clone()),
+        r4: None,
+    }
 }
 
-/// This function registers scanning for the local ballot box
-pub fn register_local_ballot_box_scan(
+/// Rule for the local ballot box: the ballot token, the ballot contract and the owner address in
+/// R4
+pub fn local_ballot_box_rule(
     ballot_contract_address: &ErgoTree,
     ballot_token_id: &BallotTokenId,
     ballot_token_owner_address: &NetworkAddress,
-) -> std::result::Result<Scan, ScanError> {
-    // Raw EC bytes + type identifier
-    let ballot_add_bytes = address_to_raw_for_register(&ballot_token_owner_address.to_base58())?;
-    let ballot_contract_bytes = ballot_contract_address.to_scan_bytes();
-    // Scan for pool participant token id + datapoint contract address + oracle_address in R4
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": ballot_token_id.clone(),
-        },
-        {
-            "predicate": "equals",
-            "value": ballot_contract_bytes,
-        },
-        {
-            "predicate": "equals",
-            "register": "R4",
-            "value": ballot_add_bytes.clone(),
-        }
-    ]
-    } );
-
-    Scan::register("Local Ballot Box Scan", scan_json)
+) -> std::result::Result<TrackingRule, ScanError> {
+    Ok(TrackingRule {
+        token_id: ballot_token_id.token_id(),
+        ergo_tree: Some(ballot_contract_address.clone()),
+        r4: Some(address_to_raw_for_register(
+            &ballot_token_owner_address.to_base58(),
+        )?),
+    })
 }
 
-/// Scan for all ballot boxes matching token id of oracle pool. When updating the pool box only ballot boxes voting for the new pool will be spent
-pub fn register_ballot_box_scan(
+/// Rule for all ballot boxes matching token id of oracle pool. When updating the pool box only ballot boxes voting for the new pool will be spent
+pub fn ballot_box_rule(
     ballot_contract_address: &ErgoTree,
     ballot_token_id: &BallotTokenId,
-) -> std::result::Result<Scan, ScanError> {
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": ballot_token_id.clone(),
-        },
-        {
-            "predicate": "equals",
-            "value": ballot_contract_address.to_scan_bytes(),
-        }
-        ] });
-    Scan::register("Ballot Box Scan", scan_json)
+) -> TrackingRule {
+    TrackingRule {
+        token_id: ballot_token_id.token_id(),
+        ergo_tree: Some(ballot_contract_address.clone()),
+        r4: None,
+    }
 }
 
-pub fn register_update_box_scan(
-    update_nft_token_id: &UpdateTokenId,
-) -> std::result::Result<Scan, ScanError> {
-    let scan_json = json! ( {
-        "predicate": "and",
-        "args": [
-        {
-            "predicate": "containsAsset",
-            "assetId": update_nft_token_id.clone(),
-        },
-        ] });
-    Scan::register("Update Box Scan", scan_json)
+pub fn update_box_rule(update_nft_token_id: &UpdateTokenId) -> TrackingRule {
+    TrackingRule {
+        token_id: update_nft_token_id.token_id(),
+        ergo_tree: None,
+        r4: None,
+    }
 }
 
 /// Convert a chain type to Coll[Byte] for scans
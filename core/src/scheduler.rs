@@ -0,0 +1,232 @@
+//! Scheduling of the main loop iterations. In `new_block` mode the node height is polled and an
+//! iteration runs as soon as a new block shows up, or after `interval_secs` if no block arrived by
+//! then. In `interval` mode an iteration runs every `interval_secs`. SIGINT/SIGTERM stop the loop
+//! once the current iteration is done and stop the REST server.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use ergo_node_interface::node_interface::NodeError;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::datapoint_source::cancel_datapoint_fetches;
+
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MainLoopMode {
+    #[default]
+    NewBlock,
+    Interval,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MainLoopConfig {
+    #[serde(default)]
+    pub mode: MainLoopMode,
+    /// Delay between iterations in `interval` mode, longest wait for a new block in `new_block`
+    /// mode
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// How often the node height is checked in `new_block` mode
+    #[serde(default = "default_height_poll_secs")]
+    pub height_poll_secs: u64,
+}
+
+fn default_interval_secs() -> u64 {
+    30
+}
+
+fn default_height_poll_secs() -> u64 {
+    2
+}
+
+impl Default for MainLoopConfig {
+    fn default() -> Self {
+        MainLoopConfig {
+            mode: MainLoopMode::default(),
+            interval_secs: default_interval_secs(),
+            height_poll_secs: default_height_poll_secs(),
+        }
+    }
+}
+
+/// Shutdown request shared between the signal handler, the main loop and the REST server
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<(Mutex<bool>, Condvar)>,
+    token: CancellationToken,
+}
+
+impl Shutdown {
+    pub fn request(&self) {
+        let (requested, condvar) = &*self.requested;
+        *requested.lock().unwrap() = true;
+        condvar.notify_all();
+        self.token.cancel();
+    }
+
+    /// Resolves once shutdown is requested
+    pub async fn requested(&self) {
+        self.token.cancelled().await
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.0.lock().unwrap()
+    }
+
+    /// Sleeps for `timeout` or until shutdown is requested. Returns true if it was requested.
+    pub fn sleep(&self, timeout: Duration) -> bool {
+        let (requested, condvar) = &*self.requested;
+        let (requested, _) = condvar
+            .wait_timeout_while(requested.lock().unwrap(), timeout, |requested| !*requested)
+            .unwrap();
+        *requested
+    }
+}
+
+/// Requests shutdown (and cancels pending datapoint fetches) on SIGINT/SIGTERM
+pub fn listen_for_shutdown(tokio_runtime: &tokio::runtime::Runtime) -> Shutdown {
+    let shutdown = Shutdown::default();
+    let requester = shutdown.clone();
+    tokio_runtime.spawn(async move {
+        wait_for_signal().await;
+        log::info!("Shutdown requested, stopping after the current iteration");
+        cancel_datapoint_fetches();
+        requester.request();
+    });
+    shutdown
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = sigterm.recv() => {},
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+pub struct Scheduler {
+    mode: MainLoopMode,
+    interval: Duration,
+    height_poll: Duration,
+    shutdown: Shutdown,
+    last_height: Option<u64>,
+}
+
+impl Scheduler {
+    pub fn new(config: MainLoopConfig, shutdown: Shutdown) -> Self {
+        Scheduler {
+            mode: config.mode,
+            interval: Duration::from_secs(config.interval_secs),
+            height_poll: Duration::from_secs(config.height_poll_secs.max(1)),
+            shutdown,
+            last_height: None,
+        }
+    }
+
+    /// Blocks until the next iteration is due. Returns false if shutdown was requested.
+    pub fn wait_for_next_iteration<F>(&mut self, current_height: F) -> bool
+    where
+        F: Fn() -> Result<u64, NodeError>,
+    {
+        if self.shutdown.is_requested() {
+            return false;
+        }
+        match self.mode {
+            MainLoopMode::Interval => !self.shutdown.sleep(self.interval),
+            MainLoopMode::NewBlock => self.wait_for_new_block(current_height),
+        }
+    }
+
+    fn wait_for_new_block<F>(&mut self, current_height: F) -> bool
+    where
+        F: Fn() -> Result<u64, NodeError>,
+    {
+        let deadline = Instant::now() + self.interval;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                log::debug!("No new block within {:?}, running anyway", self.interval);
+                return true;
+            }
+            if self.shutdown.sleep(self.height_poll.min(deadline - now)) {
+                return false;
+            }
+            match current_height() {
+                Ok(height) => match self.last_height.replace(height) {
+                    Some(last_height) if last_height != height => return true,
+                    Some(_) | None => (),
+                },
+                Err(e) => log::warn!("Failed to poll the node height: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn make_scheduler(mode: MainLoopMode, shutdown: Shutdown) -> Scheduler {
+        Scheduler {
+            mode,
+            interval: Duration::from_millis(200),
+            height_poll: Duration::from_millis(1),
+            shutdown,
+            last_height: None,
+        }
+    }
+
+    #[test]
+    fn test_new_block() {
+        let mut scheduler = make_scheduler(MainLoopMode::NewBlock, Shutdown::default());
+        let polls = Cell::new(0);
+        // a new block on the fourth poll, the first one only sets the last seen height
+        let height = || {
+            polls.set(polls.get() + 1);
+            Ok(if polls.get() < 4 { 100 } else { 101 })
+        };
+        let started = Instant::now();
+        assert!(scheduler.wait_for_next_iteration(height));
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert_eq!(polls.get(), 4);
+        assert_eq!(scheduler.last_height, Some(101));
+        // falls back to the interval without new blocks
+        let started = Instant::now();
+        assert!(scheduler.wait_for_next_iteration(|| Ok(101)));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let shutdown = Shutdown::default();
+        let mut scheduler = make_scheduler(MainLoopMode::Interval, shutdown.clone());
+        let requester = shutdown.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            requester.request();
+        });
+        let started = Instant::now();
+        assert!(!scheduler.wait_for_next_iteration(|| Ok(100)));
+        assert!(started.elapsed() < Duration::from_millis(200));
+        let mut scheduler = make_scheduler(MainLoopMode::NewBlock, shutdown);
+        assert!(!scheduler.wait_for_next_iteration(|| Ok(100)));
+    }
+}
@@ -23,6 +23,7 @@ use crate::{
         bootstrap::{BootstrapConfig, TokensToMint},
         prepare_update::{UpdateBootstrapConfig, UpdateTokensToMint},
     },
+    collector_election::CollectorElectionConfig,
     contracts::{
         ballot::{BallotContractParameters, BallotContractParametersError},
         oracle::{OracleContractParameters, OracleContractParametersError},
@@ -33,9 +34,19 @@ use crate::{
         },
         update::{UpdateContractParameters, UpdateContractParametersError},
     },
-    datapoint_source::PredefinedDataPointSource,
+    datapoint_source::{
+        AggregateDataPointSourceConfig, ExternalScriptSpec, FetchPolicy, HttpJsonSourceConfig,
+        PredefinedDataPointSource,
+    },
+    node_pool::NodePoolConfig,
     oracle_config::{OracleConfig, OracleConfigError, TokenIds},
+    pool_commands::aggregation::AggregationStrategyConfig,
+    pool_commands::deviation_guard::DeviationGuardConfig,
+    scheduler::MainLoopConfig,
+    signer::TxSignerConfig,
     spec_token::TokenIdKind,
+    tx_tracker::TxTrackerConfig,
+    wallet::WalletSourceConfig,
 };
 
 /// Used to (de)serialize `OracleConfig` instance.
@@ -49,7 +60,33 @@ pub(crate) struct OracleConfigSerde {
     core_api_port: u16,
     oracle_address: String,
     data_point_source: Option<PredefinedDataPointSource>,
-    data_point_source_custom_script: Option<String>,
+    data_point_source_custom_script: Option<ExternalScriptSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_point_source_aggregate: Option<AggregateDataPointSourceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_point_source_http_json: Option<HttpJsonSourceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_point_fetch_policy: Option<FetchPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_point_max_age_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_point_deviation_guard: Option<DeviationGuardConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_aggregation_strategy: Option<AggregationStrategyConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_collector_election: Option<CollectorElectionConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pool_reward_warning_threshold: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_tracker: Option<TxTrackerConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    main_loop: Option<MainLoopConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_signer: Option<TxSignerConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wallet_source: Option<WalletSourceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    node_pool: Option<NodePoolConfig>,
     oracle_contract_parameters: OracleContractParametersSerde,
     pool_contract_parameters: PoolContractParametersSerde,
     refresh_contract_parameters: RefreshContractParametersSerde,
@@ -123,6 +160,19 @@ impl From<OracleConfig> for OracleConfigSerde {
             oracle_address: c.oracle_address.to_base58(),
             data_point_source: c.data_point_source,
             data_point_source_custom_script: c.data_point_source_custom_script,
+            data_point_source_aggregate: c.data_point_source_aggregate,
+            data_point_source_http_json: c.data_point_source_http_json,
+            data_point_fetch_policy: c.data_point_fetch_policy,
+            data_point_max_age_secs: c.data_point_max_age_secs,
+            data_point_deviation_guard: c.data_point_deviation_guard,
+            refresh_aggregation_strategy: c.refresh_aggregation_strategy,
+            refresh_collector_election: c.refresh_collector_election,
+            pool_reward_warning_threshold: c.pool_reward_warning_threshold,
+            tx_tracker: c.tx_tracker,
+            main_loop: c.main_loop,
+            tx_signer: c.tx_signer,
+            wallet_source: c.wallet_source,
+            node_pool: c.node_pool,
             oracle_contract_parameters,
             pool_contract_parameters,
             refresh_contract_parameters,
@@ -236,6 +286,19 @@ impl TryFrom<OracleConfigSerde> for OracleConfig {
             oracle_address,
             data_point_source: c.data_point_source,
             data_point_source_custom_script: c.data_point_source_custom_script,
+            data_point_source_aggregate: c.data_point_source_aggregate,
+            data_point_source_http_json: c.data_point_source_http_json,
+            data_point_fetch_policy: c.data_point_fetch_policy,
+            data_point_max_age_secs: c.data_point_max_age_secs,
+            data_point_deviation_guard: c.data_point_deviation_guard,
+            refresh_aggregation_strategy: c.refresh_aggregation_strategy,
+            refresh_collector_election: c.refresh_collector_election,
+            pool_reward_warning_threshold: c.pool_reward_warning_threshold,
+            tx_tracker: c.tx_tracker,
+            main_loop: c.main_loop,
+            tx_signer: c.tx_signer,
+            wallet_source: c.wallet_source,
+            node_pool: c.node_pool,
             oracle_box_wrapper_inputs,
             pool_box_wrapper_inputs,
             refresh_box_wrapper_inputs,
@@ -260,9 +323,13 @@ pub struct BootstrapConfigSerde {
     node_api_key: String,
     core_api_port: u16,
     data_point_source: Option<PredefinedDataPointSource>,
-    data_point_source_custom_script: Option<String>,
+    data_point_source_custom_script: Option<ExternalScriptSpec>,
     oracle_address: String,
     base_fee: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_signer: Option<TxSignerConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wallet_source: Option<WalletSourceConfig>,
 }
 
 impl From<BootstrapConfig> for BootstrapConfigSerde {
@@ -288,6 +355,8 @@ impl From<BootstrapConfig> for BootstrapConfigSerde {
             data_point_source: c.data_point_source,
             data_point_source_custom_script: c.data_point_source_custom_script,
             base_fee: c.base_fee,
+            tx_signer: c.tx_signer,
+            wallet_source: c.wallet_source,
         }
     }
 }
@@ -356,6 +425,8 @@ impl TryFrom<BootstrapConfigSerde> for BootstrapConfig {
             data_point_source: c.data_point_source,
             data_point_source_custom_script: c.data_point_source_custom_script,
             base_fee: c.base_fee,
+            tx_signer: c.tx_signer,
+            wallet_source: c.wallet_source,
         })
     }
 }
@@ -0,0 +1,347 @@
+//! Signing of the oracle transactions. The `node` signer uses the node wallet, which has to be
+//! unlocked. The `local` signer decrypts a secret key from a key file and signs with it, the node
+//! is only asked for the spent boxes and the last block headers, and submits the signed tx. The
+//! `remote` signer sends the tx to an external signing service.
+
+mod key_file;
+mod remote;
+pub mod remote_protocol;
+
+pub use key_file::SecretKind;
+pub use remote::RemoteSignerConfig;
+pub use remote_protocol::SignAction;
+
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::{Transaction, TxIoVec};
+use ergo_lib::ergo_chain_types::Header;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::wallet::secret_key::SecretKey;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::{Wallet, WalletError};
+use ergo_node_interface::node_interface::{NodeError, NodeInterface};
+use once_cell::sync;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::node_interface::SignTransaction;
+use crate::tx_tracker::ActionKind;
+
+use key_file::{EncryptedKeyFile, DEFAULT_ITERATIONS};
+use remote::RemoteSigner;
+
+/// Env var with the key file password, used if no `password_file` is configured
+pub const PASSWORD_ENV_VAR: &str = "ORACLE_CORE_SIGNER_PASSWORD";
+
+/// Decrypted once, on first use
+static LOCAL_SECRET: sync::OnceCell<SecretKey> = sync::OnceCell::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TxSignerConfig {
+    /// Node wallet
+    #[default]
+    Node,
+    /// Secret key from an encrypted key file
+    Local(LocalSignerConfig),
+    /// External signing service
+    Remote(RemoteSignerConfig),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LocalSignerConfig {
+    pub key_file: String,
+    /// File holding the key file password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum SignerError {
+    #[error("node error: {0}")]
+    Node(#[from] NodeError),
+    #[error("key file IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("key file JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid key file: {0}")]
+    InvalidKeyFile(String),
+    #[error("invalid secret: {0}")]
+    InvalidSecret(String),
+    #[error("failed to encrypt the secret")]
+    Encryption,
+    #[error("failed to decrypt the key file, wrong password?")]
+    Decryption,
+    #[error(
+        "no key file password, set `password_file` or the {} env var",
+        PASSWORD_ENV_VAR
+    )]
+    MissingPassword,
+    #[error("key file holds the key of {actual}, expected the oracle address {expected}")]
+    WrongKey { expected: String, actual: String },
+    #[error("tx context error: {0}")]
+    TxContext(String),
+    #[error("wallet error: {0}")]
+    Wallet(#[from] WalletError),
+    #[error(
+        "no remote signer auth token, set `auth_token_file` or the {} env var",
+        remote::AUTH_TOKEN_ENV_VAR
+    )]
+    MissingAuthToken,
+    #[error("remote signer HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("remote signer refused to sign (status {status}): {message}")]
+    Remote { status: u16, message: String },
+    #[error("remote signer returned a different tx")]
+    SignedTxMismatch,
+}
+
+/// What the local and remote signers need from the node
+pub trait SigningContextSource {
+    /// Unspent box, including the ones created by unconfirmed txs
+    fn get_box(&self, box_id: &str) -> Result<ErgoBox, NodeError>;
+    /// Last 10 block headers, the last block first
+    fn get_last_headers(&self) -> Result<[Header; 10], NodeError>;
+}
+
+/// Input and data boxes of the tx
+fn fetch_boxes(
+    context_source: &dyn SigningContextSource,
+    unsigned_tx: &UnsignedTransaction,
+) -> Result<(TxIoVec<ErgoBox>, Option<TxIoVec<ErgoBox>>), NodeError> {
+    let inputs = unsigned_tx
+        .inputs
+        .iter()
+        .map(|input| context_source.get_box(&String::from(input.box_id)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data_boxes = unsigned_tx
+        .data_inputs
+        .as_ref()
+        .map(|data_inputs| {
+            data_inputs
+                .iter()
+                .map(|input| context_source.get_box(&String::from(input.box_id)))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    Ok((
+        inputs.try_into().unwrap(),
+        data_boxes.map(|boxes| boxes.try_into().unwrap()),
+    ))
+}
+
+pub struct LocalSigner<C: SigningContextSource> {
+    secret: SecretKey,
+    context_source: C,
+}
+
+impl<C: SigningContextSource> LocalSigner<C> {
+    pub fn new(secret: SecretKey, context_source: C) -> Self {
+        LocalSigner {
+            secret,
+            context_source,
+        }
+    }
+
+    /// Signs the tx, the input and data boxes are looked up on the node
+    pub fn sign_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> Result<Transaction, SignerError> {
+        let (inputs, data_boxes) = fetch_boxes(&self.context_source, unsigned_tx)?;
+        self.sign_transaction_with_inputs(unsigned_tx, inputs, data_boxes)
+    }
+}
+
+impl<C: SigningContextSource> SignTransaction for LocalSigner<C> {
+    fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: TxIoVec<ErgoBox>,
+        data_boxes: Option<TxIoVec<ErgoBox>>,
+    ) -> Result<Transaction, SignerError> {
+        let tx_context = TransactionContext::new(
+            unsigned_tx.clone(),
+            inputs.as_vec().clone(),
+            data_boxes.map(|bs| bs.as_vec().clone()).unwrap_or_default(),
+        )
+        .map_err(|e| SignerError::TxContext(e.to_string()))?;
+        let state_context = remote_protocol::state_context(self.context_source.get_last_headers()?);
+        let wallet = Wallet::from_secrets(vec![self.secret.clone()]);
+        Ok(wallet.sign_transaction(tx_context, &state_context, None)?)
+    }
+}
+
+pub enum TxSigner {
+    Node(NodeInterface),
+    Local(LocalSigner<NodeInterface>),
+    Remote(RemoteSigner<NodeInterface>),
+}
+
+impl TxSigner {
+    /// The local signer only accepts a key file with the key of `oracle_address`
+    pub fn from_config(
+        config: &TxSignerConfig,
+        node: NodeInterface,
+        oracle_address: &NetworkAddress,
+    ) -> Result<Self, SignerError> {
+        match config {
+            TxSignerConfig::Node => Ok(TxSigner::Node(node)),
+            TxSignerConfig::Local(local_config) => {
+                let secret = LOCAL_SECRET
+                    .get_or_try_init(|| load_secret(local_config, oracle_address))?
+                    .clone();
+                Ok(TxSigner::Local(LocalSigner::new(secret, node)))
+            }
+            TxSignerConfig::Remote(remote_config) => {
+                Ok(TxSigner::Remote(RemoteSigner::new(remote_config, node)?))
+            }
+        }
+    }
+
+    /// Signs the tx, the input and data boxes are looked up on the node
+    pub fn sign_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        action: SignAction,
+    ) -> Result<Transaction, SignerError> {
+        match self {
+            TxSigner::Node(node) => Ok(node.sign_transaction(unsigned_tx, None, None)?),
+            TxSigner::Local(signer) => signer.sign_transaction(unsigned_tx),
+            TxSigner::Remote(signer) => signer.sign_transaction(unsigned_tx, action),
+        }
+    }
+
+    /// `SignTransaction` for txs doing `action`
+    pub fn for_action(&self, action: SignAction) -> ActionSigner {
+        ActionSigner {
+            signer: self,
+            action,
+        }
+    }
+}
+
+pub struct ActionSigner<'a> {
+    signer: &'a TxSigner,
+    action: SignAction,
+}
+
+impl SignTransaction for ActionSigner<'_> {
+    fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: TxIoVec<ErgoBox>,
+        data_boxes: Option<TxIoVec<ErgoBox>>,
+    ) -> Result<Transaction, SignerError> {
+        match self.signer {
+            TxSigner::Node(node) => {
+                node.sign_transaction_with_inputs(unsigned_tx, inputs, data_boxes)
+            }
+            TxSigner::Local(signer) => {
+                signer.sign_transaction_with_inputs(unsigned_tx, inputs, data_boxes)
+            }
+            TxSigner::Remote(signer) => {
+                signer.sign_transaction_with_inputs(unsigned_tx, inputs, data_boxes, self.action)
+            }
+        }
+    }
+}
+
+impl From<ActionKind> for SignAction {
+    fn from(kind: ActionKind) -> Self {
+        match kind {
+            ActionKind::Refresh => SignAction::Refresh,
+            ActionKind::PublishDatapoint => SignAction::PublishDatapoint,
+        }
+    }
+}
+
+fn load_secret(
+    config: &LocalSignerConfig,
+    oracle_address: &NetworkAddress,
+) -> Result<SecretKey, SignerError> {
+    let password = match &config.password_file {
+        Some(password_file) => std::fs::read_to_string(password_file)?
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_string(),
+        None => take_env_var(PASSWORD_ENV_VAR).ok_or(SignerError::MissingPassword)?,
+    };
+    let key_file: EncryptedKeyFile =
+        serde_json::from_str(&std::fs::read_to_string(&config.key_file)?)?;
+    let secret = key_file.decrypt(&password)?;
+    check_key(&secret, oracle_address)?;
+    log::info!("Loaded the signing key from {}", config.key_file);
+    Ok(secret)
+}
+
+/// Reads the env var and removes it from the environment, so that child processes (e.g. external
+/// datapoint scripts) do not inherit the secret
+fn take_env_var(name: &str) -> Option<String> {
+    let value = std::env::var(name).ok()?;
+    std::env::remove_var(name);
+    Some(value)
+}
+
+fn check_key(secret: &SecretKey, oracle_address: &NetworkAddress) -> Result<(), SignerError> {
+    let key_address = secret.get_address_from_public_image();
+    if key_address != oracle_address.address() {
+        return Err(SignerError::WrongKey {
+            expected: oracle_address.to_base58(),
+            actual: NetworkAddress::new(oracle_address.network(), &key_address).to_base58(),
+        });
+    }
+    Ok(())
+}
+
+/// Encrypts the secret into a new key file
+pub fn write_key_file(
+    path: &str,
+    kind: SecretKind,
+    secret: &str,
+    password: &str,
+) -> Result<(), SignerError> {
+    let key_file = EncryptedKeyFile::encrypt(kind, secret, password, DEFAULT_ITERATIONS)?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    serde_json::to_writer_pretty(&mut file, &key_file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::chain::address::{Address, NetworkPrefix};
+    use ergo_lib::ergotree_ir::sigma_protocol::private_input::DlogProverInput;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+
+    #[test]
+    fn test_check_key() {
+        let secret = SecretKey::from(force_any_val::<DlogProverInput>());
+        let own_address = NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &secret.get_address_from_public_image(),
+        );
+        assert!(check_key(&secret, &own_address).is_ok());
+        let other_address = NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &Address::P2Pk(force_any_val::<DlogProverInput>().public_image()),
+        );
+        assert!(matches!(
+            check_key(&secret, &other_address),
+            Err(SignerError::WrongKey { .. })
+        ));
+    }
+
+    #[test]
+    fn test_env_var_taken_out() {
+        let name = "ORACLE_CORE_TEST_TAKEN_SECRET";
+        std::env::set_var(name, "secret");
+        assert_eq!(take_env_var(name), Some("secret".to_string()));
+        assert!(std::env::var(name).is_err());
+        assert_eq!(take_env_var(name), None);
+    }
+}
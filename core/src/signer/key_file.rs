@@ -0,0 +1,179 @@
+//! Key file of the local signer. It holds a mnemonic or a hex encoded DLog secret, encrypted with
+//! AES-256-GCM under a key derived from the password with PBKDF2-HMAC-SHA256.
+
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use ergo_lib::wallet::derivation_path::{ChildIndexHardened, ChildIndexNormal, DerivationPath};
+use ergo_lib::wallet::ext_secret_key::ExtSecretKey;
+use ergo_lib::wallet::mnemonic::Mnemonic;
+use ergo_lib::wallet::secret_key::SecretKey;
+use hmac::Hmac;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::SignerError;
+
+pub const DEFAULT_ITERATIONS: u32 = 200_000;
+
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretKind {
+    /// Mnemonic phrase (no mnemonic password), the first EIP-3 key (m/44'/429'/0'/0/0) is used
+    Mnemonic,
+    /// Hex encoded 32 bytes DLog secret
+    Dlog,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EncryptedKeyFile {
+    pub kind: SecretKind,
+    /// PBKDF2 iterations
+    pub iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub cipher_text: String,
+}
+
+impl EncryptedKeyFile {
+    pub fn encrypt(
+        kind: SecretKind,
+        secret: &str,
+        password: &str,
+        iterations: u32,
+    ) -> Result<Self, SignerError> {
+        // fail early instead of when the oracle starts
+        secret_key(kind, secret)?;
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher_text = cipher(password, &salt, iterations)
+            .encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+            .map_err(|_| SignerError::Encryption)?;
+        Ok(EncryptedKeyFile {
+            kind,
+            iterations,
+            salt: base16::encode_lower(&salt),
+            nonce: base16::encode_lower(&nonce),
+            cipher_text: base16::encode_lower(&cipher_text),
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<SecretKey, SignerError> {
+        let salt = decode_hex(&self.salt)?;
+        let nonce = decode_hex(&self.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(SignerError::InvalidKeyFile(format!(
+                "nonce must be {} bytes",
+                NONCE_LEN
+            )));
+        }
+        let plain_text = cipher(password, &salt, self.iterations)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                decode_hex(&self.cipher_text)?.as_slice(),
+            )
+            .map_err(|_| SignerError::Decryption)?;
+        let secret = String::from_utf8(plain_text)
+            .map_err(|_| SignerError::InvalidKeyFile("secret is not UTF-8".to_string()))?;
+        secret_key(self.kind, &secret)
+    }
+}
+
+fn cipher(password: &str, salt: &[u8], iterations: u32) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, &mut key);
+    Aes256Gcm::new(GenericArray::from_slice(&key))
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, SignerError> {
+    base16::decode(s).map_err(|e| SignerError::InvalidKeyFile(e.to_string()))
+}
+
+fn secret_key(kind: SecretKind, secret: &str) -> Result<SecretKey, SignerError> {
+    match kind {
+        SecretKind::Mnemonic => {
+            let seed = Mnemonic::to_seed(secret.trim(), "");
+            let path = DerivationPath::new(
+                ChildIndexHardened::from_31_bit(0).unwrap(),
+                vec![ChildIndexNormal::normal(0).unwrap()],
+            );
+            ExtSecretKey::derive_master(seed)
+                .and_then(|master| master.derive(path))
+                .map(|key| key.secret_key())
+                .map_err(|e| SignerError::InvalidSecret(e.to_string()))
+        }
+        SecretKind::Dlog => {
+            let bytes: [u8; 32] = base16::decode(secret.trim())
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| {
+                    SignerError::InvalidSecret("expected 32 hex encoded bytes".to_string())
+                })?;
+            SecretKey::dlog_from_bytes(&bytes)
+                .ok_or_else(|| SignerError::InvalidSecret("not a valid DLog secret".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::sigma_protocol::private_input::DlogProverInput;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+
+    // keeps the tests fast
+    const TEST_ITERATIONS: u32 = 1000;
+
+    #[test]
+    fn test_dlog_roundtrip() {
+        let secret = force_any_val::<DlogProverInput>();
+        let encoded = base16::encode_lower(&secret.to_bytes());
+        let key_file =
+            EncryptedKeyFile::encrypt(SecretKind::Dlog, &encoded, "pass", TEST_ITERATIONS).unwrap();
+        assert!(!key_file.cipher_text.contains(&encoded));
+        let decrypted = key_file.decrypt("pass").unwrap();
+        assert_eq!(
+            decrypted.get_address_from_public_image(),
+            SecretKey::from(secret).get_address_from_public_image()
+        );
+        assert!(matches!(
+            key_file.decrypt("wrong"),
+            Err(SignerError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        let mnemonic = "chest run soon urge pipe vacant embark sight deal merry fit armor lift \
+            cart smooth";
+        let key_file =
+            EncryptedKeyFile::encrypt(SecretKind::Mnemonic, mnemonic, "pass", TEST_ITERATIONS)
+                .unwrap();
+        let json = serde_json::to_string(&key_file).unwrap();
+        let key_file: EncryptedKeyFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            key_file
+                .decrypt("pass")
+                .unwrap()
+                .get_address_from_public_image(),
+            secret_key(SecretKind::Mnemonic, mnemonic)
+                .unwrap()
+                .get_address_from_public_image()
+        );
+    }
+
+    #[test]
+    fn test_invalid_secret() {
+        assert!(matches!(
+            EncryptedKeyFile::encrypt(SecretKind::Dlog, "abcd", "pass", TEST_ITERATIONS),
+            Err(SignerError::InvalidSecret(_))
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//! Signer that hands the tx over to an external signing service (see `remote_protocol`), so that
+//! the key never is on the oracle host.
+
+use std::time::Duration;
+
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::{Transaction, TxIoVec};
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use once_cell::sync;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+
+use super::remote_protocol::{
+    SignAction, SignErrorResponse, SignRequest, SignResponse, PROTOCOL_VERSION, SIGN_PATH,
+};
+use super::{SignerError, SigningContextSource};
+
+/// Env var with the auth token, used if no `auth_token_file` is configured
+pub const AUTH_TOKEN_ENV_VAR: &str = "ORACLE_CORE_SIGNER_TOKEN";
+
+/// Taken out of the environment on first use, the signer is created for every tx
+static AUTH_TOKEN_FROM_ENV: sync::OnceCell<String> = sync::OnceCell::new();
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteSignerConfig {
+    /// Base URL of the signer
+    pub url: String,
+    /// File holding the bearer token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token_file: Option<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+pub struct RemoteSigner<C: SigningContextSource> {
+    sign_url: String,
+    auth_token: String,
+    timeout: Duration,
+    context_source: C,
+}
+
+impl<C: SigningContextSource> RemoteSigner<C> {
+    pub fn new(config: &RemoteSignerConfig, context_source: C) -> Result<Self, SignerError> {
+        let auth_token = match &config.auth_token_file {
+            Some(auth_token_file) => std::fs::read_to_string(auth_token_file)?.trim().to_string(),
+            None => AUTH_TOKEN_FROM_ENV
+                .get_or_try_init(|| {
+                    super::take_env_var(AUTH_TOKEN_ENV_VAR).ok_or(SignerError::MissingAuthToken)
+                })?
+                .clone(),
+        };
+        Ok(RemoteSigner {
+            sign_url: format!("{}{}", config.url.trim_end_matches('/'), SIGN_PATH),
+            auth_token,
+            timeout: Duration::from_secs(config.timeout_secs),
+            context_source,
+        })
+    }
+
+    /// Signs the tx, the input and data boxes are looked up on the node
+    pub fn sign_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        action: SignAction,
+    ) -> Result<Transaction, SignerError> {
+        let (inputs, data_boxes) = super::fetch_boxes(&self.context_source, unsigned_tx)?;
+        self.sign_transaction_with_inputs(unsigned_tx, inputs, data_boxes, action)
+    }
+
+    pub fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: TxIoVec<ErgoBox>,
+        data_boxes: Option<TxIoVec<ErgoBox>>,
+        action: SignAction,
+    ) -> Result<Transaction, SignerError> {
+        let request = SignRequest {
+            version: PROTOCOL_VERSION,
+            action,
+            unsigned_tx: unsigned_tx.clone(),
+            inputs: inputs.as_vec().clone(),
+            data_inputs: data_boxes
+                .map(|boxes| boxes.as_vec().clone())
+                .unwrap_or_default(),
+            last_headers: self.context_source.get_last_headers()?.to_vec(),
+        };
+        let resp = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()?
+            .post(&self.sign_url)
+            .bearer_auth(&self.auth_token)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&request)?)
+            .send()?;
+        let status = resp.status();
+        let text = resp.text()?;
+        if !status.is_success() {
+            let message = serde_json::from_str::<SignErrorResponse>(&text)
+                .map(|resp| resp.error)
+                .unwrap_or(text);
+            return Err(SignerError::Remote {
+                status: status.as_u16(),
+                message,
+            });
+        }
+        check_signed_tx(
+            unsigned_tx,
+            serde_json::from_str::<SignResponse>(&text)?.signed_tx,
+        )
+    }
+}
+
+/// The signer must not change the tx, the id does not depend on the proofs
+fn check_signed_tx(
+    unsigned_tx: &UnsignedTransaction,
+    signed_tx: Transaction,
+) -> Result<Transaction, SignerError> {
+    if signed_tx.id() != unsigned_tx.id() {
+        return Err(SignerError::SignedTxMismatch);
+    }
+    Ok(signed_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ProofBytes;
+    use ergo_lib::ergotree_ir::chain::address::{Address, NetworkAddress, NetworkPrefix};
+    use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+    use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+    use ergo_lib::wallet::miner_fee::MINERS_FEE_ADDRESS;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::signer::remote_protocol::SignPolicy;
+
+    #[test]
+    fn test_check_signed_tx() {
+        let unsigned_tx = force_any_val::<UnsignedTransaction>();
+        let proofs = vec![ProofBytes::Empty; unsigned_tx.inputs.len()];
+        let signed_tx = Transaction::from_unsigned_tx(unsigned_tx.clone(), proofs).unwrap();
+        assert!(check_signed_tx(&unsigned_tx, signed_tx).is_ok());
+        let other_tx = force_any_val::<Transaction>();
+        assert!(matches!(
+            check_signed_tx(&unsigned_tx, other_tx),
+            Err(SignerError::SignedTxMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_sign_policy() {
+        let network = NetworkPrefix::Mainnet;
+        let p2pk_address = || Address::P2Pk(force_any_val::<DlogProverInput>().public_image());
+        let contract_address = p2pk_address();
+        let contract_tree = contract_address.script().unwrap();
+        let change_tree = p2pk_address().script().unwrap();
+        let output = |tree: ErgoTree, value: u64| {
+            ErgoBoxCandidateBuilder::new(BoxValue::try_from(value).unwrap(), tree, 1)
+                .build()
+                .unwrap()
+        };
+        let fee_tree = MINERS_FEE_ADDRESS.script().unwrap();
+        let outputs = vec![
+            output(contract_tree, 1_000_000),
+            output(change_tree.clone(), 5_000_000),
+            output(fee_tree.clone(), 1_100_000),
+        ];
+        let policy = SignPolicy {
+            allowed_outputs: vec![(
+                SignAction::Refresh,
+                vec![NetworkAddress::new(network, &contract_address).to_base58()],
+            )]
+            .into_iter()
+            .collect(),
+            max_fee: 1_100_000,
+        };
+        assert!(policy
+            .check(SignAction::Refresh, &outputs, &change_tree, network)
+            .is_ok());
+        // action not in the policy
+        assert!(policy
+            .check(
+                SignAction::ExtractRewardTokens,
+                &outputs,
+                &change_tree,
+                network
+            )
+            .is_err());
+        // output to an address not in the policy
+        let other_tree = p2pk_address().script().unwrap();
+        assert!(policy
+            .check(SignAction::Refresh, &outputs, &other_tree, network)
+            .is_err());
+        // fee above the max
+        let mut high_fee_outputs = outputs;
+        high_fee_outputs.push(output(fee_tree, 1_000_000));
+        assert!(policy
+            .check(
+                SignAction::Refresh,
+                &high_fee_outputs,
+                &change_tree,
+                network
+            )
+            .is_err());
+    }
+}
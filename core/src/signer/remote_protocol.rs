@@ -0,0 +1,146 @@
+//! Request and response of the remote signer protocol. The oracle POSTs a `SignRequest` as JSON
+//! to `<url>/sign` with an `Authorization: Bearer <token>` header. The signer answers with a
+//! `SignResponse`, or with a non-2xx status and a `SignErrorResponse` if it refuses to sign.
+//!
+//! Only depends on ergo-lib and serde, the stand-in signer binary includes this file as well.
+
+use std::collections::HashMap;
+
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::ergo_chain_types::{Header, PreHeader};
+use ergo_lib::ergotree_ir::chain::address::{AddressEncoder, NetworkPrefix};
+use ergo_lib::ergotree_ir::chain::ergo_box::{ErgoBox, ErgoBoxCandidate};
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::wallet::miner_fee::MINERS_FEE_ADDRESS;
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const SIGN_PATH: &str = "/sign";
+
+/// What the tx does, for the signer's policy
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SignAction {
+    PublishDatapoint,
+    Refresh,
+    ExtractRewardTokens,
+    TransferOracleToken,
+    VoteUpdatePool,
+    UpdatePool,
+    PrepareUpdate,
+    /// Minting the pool tokens and creating the pool and refresh boxes of a new pool
+    Bootstrap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignRequest {
+    pub version: u32,
+    pub action: SignAction,
+    pub unsigned_tx: UnsignedTransaction,
+    /// Boxes spent by the tx, in the order of its inputs
+    pub inputs: Vec<ErgoBox>,
+    /// Boxes of the tx data inputs, in their order
+    #[serde(default)]
+    pub data_inputs: Vec<ErgoBox>,
+    /// Last 10 block headers, the last block first
+    pub last_headers: Vec<Header>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignResponse {
+    pub signed_tx: Transaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignErrorResponse {
+    pub error: String,
+}
+
+/// What a signer signs, e.g. loaded from a JSON file:
+///
+/// ```json
+/// {
+///   "allowed_outputs": {
+///     "publish_datapoint": ["<oracle contract address>"],
+///     "refresh": ["<pool contract address>", "<refresh contract address>", "<oracle contract address>"]
+///   },
+///   "max_fee": 2000000
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignPolicy {
+    /// Base58 addresses the outputs of each action may go to. Actions not listed are refused.
+    pub allowed_outputs: HashMap<SignAction, Vec<String>>,
+    /// Max total fee of a tx in nanoERGs
+    pub max_fee: u64,
+}
+
+impl SignPolicy {
+    /// Checks the outputs of a tx for the action. Besides the allowed addresses, outputs may go
+    /// to the miner fee contract and to `change_tree` (the signing key's own address).
+    pub fn check(
+        &self,
+        action: SignAction,
+        outputs: &[ErgoBoxCandidate],
+        change_tree: &ErgoTree,
+        network: NetworkPrefix,
+    ) -> Result<(), String> {
+        let allowed_addresses = self
+            .allowed_outputs
+            .get(&action)
+            .ok_or_else(|| format!("{:?} txs are not signed", action))?;
+        let encoder = AddressEncoder::new(network);
+        let allowed_trees = allowed_addresses
+            .iter()
+            .map(|address| {
+                encoder
+                    .parse_address_from_str(address)
+                    .map_err(|e| format!("invalid address {} in the policy: {}", address, e))?
+                    .script()
+                    .map_err(|e| e.to_string())
+            })
+            .collect::<Result<Vec<ErgoTree>, String>>()?;
+        let fee_tree = MINERS_FEE_ADDRESS.script().map_err(|e| e.to_string())?;
+        let mut fee: u64 = 0;
+        for (idx, output) in outputs.iter().enumerate() {
+            if output.ergo_tree == fee_tree {
+                fee = fee.saturating_add(output.value.into());
+            } else if output.ergo_tree != *change_tree && !allowed_trees.contains(&output.ergo_tree)
+            {
+                return Err(format!(
+                    "output {} goes to an address not allowed for {:?} txs",
+                    idx, action
+                ));
+            }
+        }
+        if fee > self.max_fee {
+            return Err(format!(
+                "fee of {} nanoERGs is above the max of {}",
+                fee, self.max_fee
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SignRequest {
+    pub fn state_context(&self) -> Result<ErgoStateContext, String> {
+        let last_headers: [Header; 10] = self
+            .last_headers
+            .clone()
+            .try_into()
+            .map_err(|_| "expected the last 10 block headers".to_string())?;
+        Ok(state_context(last_headers))
+    }
+}
+
+/// Context for signing a tx for the block after the last one
+pub fn state_context(last_headers: [Header; 10]) -> ErgoStateContext {
+    let last_header = &last_headers[0];
+    let mut pre_header = PreHeader::from(last_header.clone());
+    pre_header.parent_id = last_header.id;
+    pre_header.height = last_header.height + 1;
+    ErgoStateContext::new(pre_header, last_headers)
+}
@@ -1,10 +1,17 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::collector_election::CollectorElectionConfig;
+use crate::mempool::PendingTx;
 use crate::oracle_state::LiveEpochState;
 use crate::oracle_state::LocalDatapointState::Collected;
 use crate::oracle_state::LocalDatapointState::Posted;
 use crate::pool_commands::PoolCommand;
 
-pub struct EpochState {
-    epoch_start_height: u64,
+lazy_static! {
+    /// Latest decision of the main loop, for the REST API
+    pub static ref LAST_DECISION: Mutex<Option<Decision>> = Mutex::new(None);
 }
 
 /// Enum for the state that the oracle pool is currently in
@@ -12,53 +19,431 @@ pub struct EpochState {
 pub enum PoolState {
     NeedsBootstrap,
     LiveEpoch(LiveEpochState),
+    /// A tx spending the pool box or the local oracle box (or our own tx) is in the mempool
+    AwaitingConfirmation(Vec<PendingTx>),
 }
 
-pub fn process(
-    pool_state: PoolState,
-    epoch_length: u32,
-    current_height: u32,
-) -> Option<PoolCommand> {
-    let min_start_height = current_height - epoch_length;
-    match pool_state {
-        PoolState::NeedsBootstrap => {
-            log::warn!(
-                "No oracle pool found, needs bootstrap or wait for bootstrap txs to be on-chain"
-            );
-            None
+/// Where the local oracle stands in the current epoch
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EpochState {
+    NeedsBootstrap,
+    /// Waiting for unconfirmed txs spending the pool box or the local oracle box
+    AwaitingConfirmation,
+    /// No local oracle box with a datapoint yet
+    AwaitingFirstPost,
+    /// Datapoint posted in the current epoch, the epoch is still running
+    Posted,
+    /// Datapoint collected by the last refresh
+    Collected,
+    /// The epoch is over and our datapoint is in it
+    RefreshDue,
+    /// Our datapoint is from an earlier epoch or too old to be collected
+    EpochExpired,
+    /// The pool box cannot pay the rewards of a refresh
+    PoolOutOfRewards,
+}
+
+/// Outcome of `process`
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub height: u32,
+    pub state: EpochState,
+    pub command: Option<PoolCommand>,
+    pub reason: String,
+    /// The pool box reward tokens are below the warning threshold
+    pub pool_rewards_low: bool,
+}
+
+/// Pool parameters the decisions depend on
+#[derive(Debug, Copy, Clone)]
+pub struct PoolParams {
+    pub epoch_length: u32,
+    pub min_data_points: u32,
+    /// With election set the refresh is delayed according to the collector rank of the oracle
+    pub collector_election: Option<CollectorElectionConfig>,
+    /// Pool box reward tokens below which the operators are warned to update the pool
+    pub reward_warning_threshold: u64,
+}
+
+impl PoolParams {
+    /// Reward tokens a refresh collecting the minimum number of datapoints needs. A refresh pays
+    /// 2 reward tokens per collected box and the pool box has to keep at least one.
+    pub fn min_pool_reward_tokens(&self) -> u64 {
+        2 * self.min_data_points as u64 + 1
+    }
+}
+
+/// Refreshes collecting the minimum number of datapoints the pool box should be able to pay for
+/// without a warning, unless `pool_reward_warning_threshold` is set
+const DEFAULT_REWARD_WARNING_REFRESHES: u64 = 100;
+
+/// Reward token warning threshold, `configured` or enough for `DEFAULT_REWARD_WARNING_REFRESHES`
+pub fn reward_warning_threshold(configured: Option<u64>, min_data_points: u32) -> u64 {
+    configured.unwrap_or(DEFAULT_REWARD_WARNING_REFRESHES * 2 * min_data_points as u64)
+}
+
+/// Decides the next action of the oracle
+pub fn process(pool_state: PoolState, params: &PoolParams, current_height: u32) -> Decision {
+    let pool_rewards_low = match &pool_state {
+        PoolState::LiveEpoch(live_epoch) => {
+            live_epoch.pool_reward_tokens < params.reward_warning_threshold
         }
+        PoolState::NeedsBootstrap | PoolState::AwaitingConfirmation(_) => false,
+    };
+    let decision = |state, command, reason| Decision {
+        height: current_height,
+        state,
+        command,
+        reason,
+        pool_rewards_low,
+    };
+    match pool_state {
+        PoolState::NeedsBootstrap => decision(
+            EpochState::NeedsBootstrap,
+            None,
+            "No oracle pool found, needs bootstrap or wait for bootstrap txs to be on-chain"
+                .to_string(),
+        ),
+        PoolState::AwaitingConfirmation(pending_txs) => decision(
+            EpochState::AwaitingConfirmation,
+            None,
+            format!(
+                "Waiting for unconfirmed txs {}",
+                pending_txs
+                    .iter()
+                    .map(|tx| tx.tx_id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
         PoolState::LiveEpoch(live_epoch) => {
-            log::debug!("Height {current_height}. Live epoch state: {live_epoch:?}");
-            if let Some(local_datapoint_box_state) = live_epoch.local_datapoint_box_state {
-                match local_datapoint_box_state {
-                    Collected { height: _ } => {
-                        // publish datapoint after some blocks have passed after the pool box published
-                        // to avoid some oracle box become stale on the next refresh
-                        // (datapoint posted on the first block of the epoch go out of the epoch window too fast)
-                        if current_height > live_epoch.latest_pool_box_height + epoch_length / 2 {
-                            Some(PoolCommand::PublishSubsequentDataPoint { republish: false })
-                        } else {
-                            None
-                        }
-                    }
-                    Posted { epoch_id, height } => {
-                        if height < min_start_height || epoch_id != live_epoch.pool_box_epoch_id {
-                            Some(PoolCommand::PublishSubsequentDataPoint { republish: true })
-                        } else if live_epoch.latest_pool_box_height < min_start_height
-                            && epoch_id == live_epoch.pool_box_epoch_id
-                        {
-                            Some(PoolCommand::Refresh)
-                        } else {
-                            None
-                        }
-                    }
-                }
+            let (state, command, reason) = process_live_epoch(&live_epoch, params, current_height);
+            decision(state, command, reason)
+        }
+    }
+}
+
+fn process_live_epoch(
+    live_epoch: &LiveEpochState,
+    params: &PoolParams,
+    current_height: u32,
+) -> (EpochState, Option<PoolCommand>, String) {
+    let min_pool_reward_tokens = params.min_pool_reward_tokens();
+    if live_epoch.pool_reward_tokens < min_pool_reward_tokens {
+        return (
+            EpochState::PoolOutOfRewards,
+            None,
+            format!(
+                "Pool box has {} reward tokens, a refresh needs at least {}",
+                live_epoch.pool_reward_tokens, min_pool_reward_tokens
+            ),
+        );
+    }
+    let pool_box_height = live_epoch.latest_pool_box_height;
+    let epoch_end_height = pool_box_height.saturating_add(params.epoch_length);
+    // oldest datapoint height still collected by a refresh at the current height
+    let min_start_height = current_height.saturating_sub(params.epoch_length);
+    match live_epoch.local_datapoint_box_state {
+        None => (
+            EpochState::AwaitingFirstPost,
+            Some(PoolCommand::PublishFirstDataPoint),
+            "No local datapoint yet".to_string(),
+        ),
+        Some(Collected { .. }) => {
+            // publish datapoint after some blocks have passed after the pool box published
+            // to avoid some oracle box become stale on the next refresh
+            // (datapoint posted on the first block of the epoch go out of the epoch window too fast)
+            let publish_height = pool_box_height.saturating_add(params.epoch_length / 2);
+            if current_height > publish_height {
+                (
+                    EpochState::Collected,
+                    Some(PoolCommand::PublishSubsequentDataPoint { republish: false }),
+                    format!("Datapoint collected, publishing after height {publish_height}"),
+                )
             } else {
-                // no local datapoint found
-                Some(PoolCommand::PublishFirstDataPoint)
+                (
+                    EpochState::Collected,
+                    None,
+                    format!("Datapoint collected, waiting until after height {publish_height}"),
+                )
             }
         }
+        Some(Posted { epoch_id, .. }) if epoch_id != live_epoch.pool_box_epoch_id => (
+            EpochState::EpochExpired,
+            Some(PoolCommand::PublishSubsequentDataPoint { republish: true }),
+            format!(
+                "Datapoint posted in epoch {epoch_id}, the pool is in epoch {}",
+                live_epoch.pool_box_epoch_id
+            ),
+        ),
+        Some(Posted { height, .. }) if height < min_start_height => (
+            EpochState::EpochExpired,
+            Some(PoolCommand::PublishSubsequentDataPoint { republish: true }),
+            format!("Datapoint posted at height {height} is too old to be collected"),
+        ),
+        Some(Posted { .. }) if pool_box_height < min_start_height => {
+            // give the oracles ranked before us the chance to refresh first
+            let delay = params
+                .collector_election
+                .map(|c| c.delay_blocks(live_epoch.local_collector_rank))
+                .unwrap_or(0);
+            let refresh_height = epoch_end_height.saturating_add(delay);
+            if current_height > refresh_height {
+                (
+                    EpochState::RefreshDue,
+                    Some(PoolCommand::Refresh),
+                    format!("Epoch ended after height {epoch_end_height}"),
+                )
+            } else {
+                (
+                    EpochState::RefreshDue,
+                    None,
+                    format!(
+                        "Waiting until after height {refresh_height} for the collectors ranked before us (rank {:?})",
+                        live_epoch.local_collector_rank
+                    ),
+                )
+            }
+        }
+        Some(Posted { .. }) => (
+            EpochState::Posted,
+            None,
+            format!("Datapoint posted, the epoch ends after height {epoch_end_height}"),
+        ),
     }
 }
 
-// TODO: add tests
+#[cfg(test)]
+mod tests {
+    use crate::oracle_state::LocalDatapointState;
+
+    use super::*;
+
+    const EPOCH_LENGTH: u32 = 30;
+    const POOL_BOX_HEIGHT: u32 = 1000;
+    const EPOCH_END: u32 = POOL_BOX_HEIGHT + EPOCH_LENGTH + 1;
+
+    fn params(collector_election: Option<CollectorElectionConfig>) -> PoolParams {
+        PoolParams {
+            epoch_length: EPOCH_LENGTH,
+            min_data_points: 4,
+            collector_election,
+            reward_warning_threshold: 50,
+        }
+    }
+
+    fn live_epoch_state(
+        local_datapoint_box_state: Option<LocalDatapointState>,
+        local_collector_rank: Option<u32>,
+    ) -> LiveEpochState {
+        LiveEpochState {
+            pool_box_epoch_id: 5,
+            local_datapoint_box_state,
+            latest_pool_datapoint: 100,
+            latest_pool_box_height: POOL_BOX_HEIGHT,
+            pool_reward_tokens: 100,
+            local_reward_tokens: Some(10),
+            local_collector_rank,
+        }
+    }
+
+    fn live_epoch(
+        local_datapoint_box_state: Option<LocalDatapointState>,
+        local_collector_rank: Option<u32>,
+    ) -> PoolState {
+        PoolState::LiveEpoch(live_epoch_state(
+            local_datapoint_box_state,
+            local_collector_rank,
+        ))
+    }
+
+    fn posted(epoch_id: u32, height: u32) -> Option<LocalDatapointState> {
+        Some(Posted { epoch_id, height })
+    }
+
+    fn collected() -> Option<LocalDatapointState> {
+        Some(Collected {
+            height: POOL_BOX_HEIGHT,
+        })
+    }
+
+    fn decide(pool_state: PoolState, height: u32) -> (EpochState, Option<PoolCommand>) {
+        let decision = process(pool_state, &params(None), height);
+        assert!(!decision.reason.is_empty());
+        (decision.state, decision.command)
+    }
+
+    #[test]
+    fn test_needs_bootstrap() {
+        assert!(matches!(
+            decide(PoolState::NeedsBootstrap, 10),
+            (EpochState::NeedsBootstrap, None)
+        ));
+    }
+
+    #[test]
+    fn test_awaiting_confirmation() {
+        let pending = PoolState::AwaitingConfirmation(vec![PendingTx {
+            tx_id: "tx".to_string(),
+            spent_box_id: Some("pool box".to_string()),
+            own_action: None,
+        }]);
+        let decision = process(pending, &params(None), EPOCH_END);
+        assert_eq!(decision.state, EpochState::AwaitingConfirmation);
+        assert!(decision.command.is_none());
+        assert!(decision.reason.contains("tx"));
+    }
+
+    #[test]
+    fn test_first_post() {
+        assert!(matches!(
+            decide(live_epoch(None, None), 1010),
+            (
+                EpochState::AwaitingFirstPost,
+                Some(PoolCommand::PublishFirstDataPoint)
+            )
+        ));
+    }
+
+    #[test]
+    fn test_collected() {
+        assert!(matches!(
+            decide(live_epoch(collected(), None), POOL_BOX_HEIGHT + 15),
+            (EpochState::Collected, None)
+        ));
+        assert!(matches!(
+            decide(live_epoch(collected(), None), POOL_BOX_HEIGHT + 16),
+            (
+                EpochState::Collected,
+                Some(PoolCommand::PublishSubsequentDataPoint { republish: false })
+            )
+        ));
+    }
+
+    #[test]
+    fn test_posted() {
+        assert!(matches!(
+            decide(live_epoch(posted(5, 1016), None), EPOCH_END - 1),
+            (EpochState::Posted, None)
+        ));
+    }
+
+    #[test]
+    fn test_epoch_expired() {
+        // posted in the previous epoch
+        assert!(matches!(
+            decide(live_epoch(posted(4, 990), None), 1010),
+            (
+                EpochState::EpochExpired,
+                Some(PoolCommand::PublishSubsequentDataPoint { republish: true })
+            )
+        ));
+        // posted in this epoch but too long ago to be collected
+        assert!(matches!(
+            decide(live_epoch(posted(5, 1001), None), 1032),
+            (
+                EpochState::EpochExpired,
+                Some(PoolCommand::PublishSubsequentDataPoint { republish: true })
+            )
+        ));
+    }
+
+    #[test]
+    fn test_refresh_without_election() {
+        let state = || live_epoch(posted(5, 1016), Some(3));
+        assert!(matches!(
+            decide(state(), EPOCH_END),
+            (EpochState::RefreshDue, Some(PoolCommand::Refresh))
+        ));
+    }
+
+    #[test]
+    fn test_refresh_collector_election() {
+        let params = params(Some(CollectorElectionConfig {
+            fallback_delay_blocks: 4,
+        }));
+        let decide = |pool_state, height| {
+            let decision = process(pool_state, &params, height);
+            (decision.state, decision.command)
+        };
+        // the elected collector refreshes right away
+        assert!(matches!(
+            decide(live_epoch(posted(5, 1016), Some(0)), EPOCH_END),
+            (EpochState::RefreshDue, Some(PoolCommand::Refresh))
+        ));
+        // the others wait for the ranks before them
+        let second = || live_epoch(posted(5, 1016), Some(2));
+        assert!(matches!(
+            decide(second(), EPOCH_END),
+            (EpochState::RefreshDue, None)
+        ));
+        assert!(matches!(
+            decide(second(), EPOCH_END + 7),
+            (EpochState::RefreshDue, None)
+        ));
+        assert!(matches!(
+            decide(second(), EPOCH_END + 8),
+            (EpochState::RefreshDue, Some(PoolCommand::Refresh))
+        ));
+        // without a known rank the oracle does not wait
+        assert!(matches!(
+            decide(live_epoch(posted(5, 1016), None), EPOCH_END),
+            (EpochState::RefreshDue, Some(PoolCommand::Refresh))
+        ));
+    }
+
+    #[test]
+    fn test_pool_out_of_rewards() {
+        let state = |pool_reward_tokens| {
+            PoolState::LiveEpoch(LiveEpochState {
+                pool_reward_tokens,
+                ..live_epoch_state(posted(5, 1016), None)
+            })
+        };
+        assert!(matches!(
+            decide(state(8), EPOCH_END),
+            (EpochState::PoolOutOfRewards, None)
+        ));
+        assert!(matches!(
+            decide(state(9), EPOCH_END),
+            (EpochState::RefreshDue, Some(PoolCommand::Refresh))
+        ));
+    }
+
+    #[test]
+    fn test_pool_rewards_low() {
+        let pool_state = |pool_reward_tokens| {
+            PoolState::LiveEpoch(LiveEpochState {
+                pool_reward_tokens,
+                ..live_epoch_state(posted(5, 1016), None)
+            })
+        };
+        let decision = process(pool_state(49), &params(None), EPOCH_END);
+        assert!(decision.pool_rewards_low);
+        assert!(matches!(decision.command, Some(PoolCommand::Refresh)));
+        assert!(!process(pool_state(50), &params(None), EPOCH_END).pool_rewards_low);
+        assert!(!process(PoolState::NeedsBootstrap, &params(None), EPOCH_END).pool_rewards_low);
+        assert_eq!(reward_warning_threshold(None, 4), 800);
+        assert_eq!(reward_warning_threshold(Some(10), 4), 10);
+    }
+
+    #[test]
+    fn test_young_chain() {
+        // heights below the epoch length must not underflow
+        let pool_state = || {
+            PoolState::LiveEpoch(LiveEpochState {
+                latest_pool_box_height: 2,
+                ..live_epoch_state(posted(5, 3), None)
+            })
+        };
+        assert!(matches!(
+            decide(pool_state(), 5),
+            (EpochState::Posted, None)
+        ));
+        assert!(matches!(
+            decide(pool_state(), 33),
+            (EpochState::RefreshDue, Some(PoolCommand::Refresh))
+        ));
+    }
+}
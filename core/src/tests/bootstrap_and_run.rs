@@ -1,5 +1,7 @@
 use std::cell::RefCell;
 use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::Duration;
 
 use ergo_chain_sim::Block;
 use ergo_chain_sim::ChainSim;
@@ -9,19 +11,47 @@ use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInp
 use ergo_lib::ergotree_ir::chain::address::Address;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
 use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
 use ergo_lib::wallet::Wallet;
 use sigma_test_util::force_any_val;
 
 use crate::cli_commands::bootstrap::perform_bootstrap_chained_transaction;
 use crate::cli_commands::bootstrap::BootstrapConfig;
 use crate::cli_commands::bootstrap::BootstrapInput;
+use crate::contracts::ballot::BallotContract;
+use crate::contracts::oracle::OracleContract;
+use crate::contracts::pool::PoolContract;
+use crate::contracts::refresh::RefreshContract;
+use crate::contracts::update::UpdateContract;
+use crate::datapoint_source::test_utils::StubServer;
+use crate::datapoint_source::DataPoint;
+use crate::datapoint_source::DataPointSource;
+use crate::datapoint_source::DataPointSourceError;
+use crate::history::DatapointHistory;
 use crate::node_interface;
+use crate::node_interface::SignTransaction;
 use crate::node_interface::SubmitTransaction;
+use crate::node_pool::NodeEndpointConfig;
+use crate::node_pool::NodePool;
+use crate::node_pool::NodePoolConfig;
 use crate::oracle_config::OracleConfig;
 use crate::oracle_config::BASE_FEE;
+use crate::oracle_state::OraclePool;
+use crate::pool_commands::publish_datapoint::build_publish_first_datapoint_action;
 use crate::pool_commands::test_utils::init_log_tests;
 use crate::pool_commands::test_utils::LocalTxSigner;
 use crate::pool_commands::test_utils::WalletDataMock;
+use crate::pool_commands::PoolCommand;
+use crate::scans::BoxLookup;
+use crate::spec_token::TokenIdKind;
+use crate::state::process;
+use crate::state::PoolParams;
+use crate::state::PoolState;
+use crate::wallet::tracked_address;
+use crate::wallet::AddressWallet;
+use crate::wallet::WalletSourceConfig;
 
 struct ChainSubmitTx<'a> {
     chain: RefCell<&'a mut ChainSim>,
@@ -82,3 +112,279 @@ fn test_bootstrap_and_run() {
     let _oracle_config = bootstrap(&wallet, &address, &mut chain);
     assert_eq!(chain.height, 8);
 }
+
+/// Unspent boxes of the pool contracts and of `address` holding `token_id`, as served by the
+/// blockchain index of a node
+fn token_boxes(
+    chain: &ChainSim,
+    config: &OracleConfig,
+    address: &Address,
+    token_id: TokenId,
+) -> Vec<ErgoBox> {
+    let trees: Vec<ErgoTree> = vec![
+        PoolContract::checked_load(&config.pool_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree(),
+        RefreshContract::checked_load(&config.refresh_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree(),
+        OracleContract::checked_load(&config.oracle_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree(),
+        BallotContract::checked_load(&config.ballot_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree(),
+        UpdateContract::checked_load(&config.update_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree(),
+        address.script().unwrap(),
+    ];
+    trees
+        .iter()
+        .flat_map(|tree| chain.get_unspent_boxes(tree))
+        .filter(|b| {
+            b.tokens.as_ref().map_or(false, |tokens| {
+                tokens.iter().any(|t| t.token_id == token_id)
+            })
+        })
+        .collect()
+}
+
+/// Routes of a node with the extra index serving the chain, without any `/wallet` route
+fn node_routes(
+    chain: &ChainSim,
+    config: &OracleConfig,
+    address: &Address,
+    height: u32,
+) -> Vec<(String, String)> {
+    let token_ids = &config.token_ids;
+    let mut routes: Vec<(String, String)> = vec![
+        token_ids.pool_nft_token_id.token_id(),
+        token_ids.refresh_nft_token_id.token_id(),
+        token_ids.update_nft_token_id.token_id(),
+        token_ids.oracle_token_id.token_id(),
+        token_ids.ballot_token_id.token_id(),
+    ]
+    .into_iter()
+    .map(|token_id| {
+        let boxes = token_boxes(chain, config, address, token_id.clone());
+        (
+            format!(
+                "/blockchain/box/unspent/byTokenId/{}",
+                String::from(token_id)
+            ),
+            serde_json::to_string(&boxes).unwrap(),
+        )
+    })
+    .collect();
+    routes.push((
+        "/blockchain/box/unspent/byAddress".to_string(),
+        serde_json::to_string(&chain.get_unspent_boxes(&address.script().unwrap())).unwrap(),
+    ));
+    routes.push(("/transactions/unconfirmed".to_string(), "[]".to_string()));
+    routes.push(("/info".to_string(), node_info(height, height)));
+    routes
+}
+
+fn node_info(full_height: u32, max_peer_height: u32) -> String {
+    format!(
+        r#"{{"fullHeight": {full_height}, "headersHeight": {full_height}, "maxPeerHeight": {max_peer_height}, "peersCount": 5}}"#
+    )
+}
+
+fn start_node(routes: &[(String, String)]) -> StubServer {
+    StubServer::start(
+        routes
+            .iter()
+            .map(|(prefix, body)| (prefix.as_str(), body.as_str()))
+            .collect(),
+    )
+}
+
+fn endpoint(server: &StubServer) -> NodeEndpointConfig {
+    NodeEndpointConfig {
+        ip: server.addr().ip().to_string(),
+        port: server.addr().port(),
+        api_key: Some("key".to_string()),
+    }
+}
+
+fn history(name: &str) -> DatapointHistory {
+    let path = std::env::temp_dir().join(format!(
+        "oracle-core-history-{}-{}.json",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    DatapointHistory::new(path)
+}
+
+fn pool_params(config: &OracleConfig) -> PoolParams {
+    let contract_parameters = config
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    PoolParams {
+        epoch_length: contract_parameters.epoch_length() as u32,
+        min_data_points: contract_parameters.min_data_points() as u32,
+        collector_election: None,
+        reward_warning_threshold: 0,
+    }
+}
+
+#[test]
+fn test_iteration_reads_from_fallback_node() {
+    init_log_tests();
+    let mut chain = ChainSim::new();
+    let secret = force_any_val::<DlogProverInput>();
+    let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
+    let address = Address::P2Pk(secret.public_image());
+    chain.generate_unspent_box(
+        address.script().unwrap(),
+        100_000_000_u64.try_into().unwrap(),
+        None,
+    );
+    let config = bootstrap(&wallet, &address, &mut chain);
+
+    // the configured node fell behind, the fallback is at the tip and has the pool boxes
+    let primary = start_node(&[("/info".to_string(), node_info(100, 110))]);
+    let fallback = start_node(&node_routes(&chain, &config, &address, 110));
+    let node_pool = NodePool::new(
+        endpoint(&primary),
+        NodePoolConfig {
+            fallback_nodes: vec![endpoint(&fallback)],
+            ..NodePoolConfig::default()
+        },
+    );
+
+    let node = node_pool.select();
+    assert_eq!(crate::check_node(&node, &node_pool).unwrap(), Some(110));
+    let op = OraclePool::with_box_lookup(
+        &config,
+        BoxLookup::BlockchainIndex(Arc::new(node_pool.node())),
+    )
+    .unwrap();
+    let pool_state = crate::read_pool_state(&node, &op, &history("fallback"), &[]);
+    assert!(matches!(pool_state, PoolState::LiveEpoch(_)));
+    let decision = process(pool_state, &pool_params(&config), 110);
+    assert!(matches!(
+        decision.command,
+        Some(PoolCommand::PublishFirstDataPoint)
+    ));
+    // only the health check went to the configured node
+    assert!(primary.requests().iter().all(|target| target == "/info"));
+    assert!(fallback
+        .requests()
+        .iter()
+        .any(|target| target.starts_with("/blockchain/box/unspent/byTokenId/")));
+}
+
+struct FixedDatapoint(i64);
+
+impl DataPointSource for FixedDatapoint {
+    fn get_datapoint(&self) -> Result<DataPoint, DataPointSourceError> {
+        Ok(DataPoint::now(self.0))
+    }
+}
+
+#[test]
+fn test_iteration_without_node_wallet() {
+    init_log_tests();
+    let mut chain = ChainSim::new();
+    let secret = force_any_val::<DlogProverInput>();
+    let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
+    let address = Address::P2Pk(secret.public_image());
+    chain.generate_unspent_box(
+        address.script().unwrap(),
+        100_000_000_u64.try_into().unwrap(),
+        None,
+    );
+    let config = OracleConfig {
+        wallet_source: Some(WalletSourceConfig::Address { address: None }),
+        ..bootstrap(&wallet, &address, &mut chain)
+    };
+    assert!(config.uses_blockchain_index());
+
+    // the node answers 404 on all `/wallet` requests
+    let height = chain.height + 100;
+    let node = start_node(&node_routes(&chain, &config, &address, height));
+    let node_pool = NodePool::new(endpoint(&node), NodePoolConfig::default());
+    let node_interface = node_pool.select();
+    assert_eq!(
+        crate::check_node(&node_interface, &node_pool).unwrap(),
+        Some(height)
+    );
+    let op = OraclePool::with_box_lookup(
+        &config,
+        BoxLookup::BlockchainIndex(Arc::new(node_pool.node())),
+    )
+    .unwrap();
+    let pool_state = crate::read_pool_state(&node_interface, &op, &history("walletless"), &[]);
+    let pool_epoch_counter = match &pool_state {
+        PoolState::LiveEpoch(live_epoch) => live_epoch.pool_box_epoch_id,
+        PoolState::NeedsBootstrap | PoolState::AwaitingConfirmation(_) => {
+            panic!("expected a live epoch, got {:?}", pool_state)
+        }
+    };
+    let decision = process(pool_state, &pool_params(&config), height);
+    assert!(matches!(
+        decision.command,
+        Some(PoolCommand::PublishFirstDataPoint)
+    ));
+
+    let wallet_data = AddressWallet {
+        address: tracked_address(
+            config.wallet_source.as_ref().unwrap(),
+            &config.oracle_address,
+        )
+        .unwrap()
+        .unwrap(),
+        source: node_pool.node(),
+    };
+    let action = build_publish_first_datapoint_action(
+        &wallet_data,
+        height,
+        address.clone(),
+        secret.public_image(),
+        config.oracle_box_wrapper_inputs.clone(),
+        &FixedDatapoint(200),
+        Duration::from_secs(60),
+        pool_epoch_counter,
+    )
+    .unwrap();
+    let address_boxes = chain.get_unspent_boxes(&address.script().unwrap());
+    let inputs: Vec<ErgoBox> = action
+        .tx
+        .inputs
+        .iter()
+        .map(|input| {
+            address_boxes
+                .iter()
+                .find(|b| b.box_id() == input.box_id)
+                .unwrap()
+                .clone()
+        })
+        .collect();
+    let ctx = force_any_val::<ErgoStateContext>();
+    let signed_tx = LocalTxSigner {
+        ctx: &ctx,
+        wallet: &wallet,
+    }
+    .sign_transaction_with_inputs(&action.tx, inputs.try_into().unwrap(), None)
+    .unwrap();
+    ChainSubmitTx {
+        chain: (&mut chain).into(),
+    }
+    .submit_transaction(&signed_tx)
+    .unwrap();
+
+    let oracle_tree =
+        OracleContract::checked_load(&config.oracle_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree();
+    assert_eq!(chain.get_unspent_boxes(&oracle_tree).len(), 1);
+    assert!(!node
+        .requests()
+        .iter()
+        .any(|target| target.starts_with("/wallet")));
+}
@@ -0,0 +1,566 @@
+//! Tracks the txs submitted by this oracle until they reach the configured number of
+//! confirmations. A tx that dropped out of the mempool with its inputs still unspent is
+//! resubmitted while it can still be accepted, a tx whose inputs were spent by another tx is
+//! reported as invalidated. Once a tx is given up on the main loop builds the action again. Stored as JSON in the data dir (next to
+//! `scanIDs.json`) so that tracking survives restarts, see the `/trackedTxs` endpoint.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use derive_more::From;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_node_interface::node_interface::NodeError;
+use once_cell::sync;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::datapoint_source::unix_timestamp_now;
+
+pub static TX_TRACKER_DIR_PATH: sync::OnceCell<PathBuf> = sync::OnceCell::new();
+
+lazy_static! {
+    /// Transactions submitted by this oracle
+    pub static ref TX_TRACKER: TxTracker = TxTracker::load_or_empty(get_tx_tracker_file_path());
+}
+
+/// Finished txs kept around for the REST API
+const KEPT_FINISHED_TXS: usize = 20;
+
+pub fn get_tx_tracker_file_path() -> PathBuf {
+    TX_TRACKER_DIR_PATH.get().unwrap().join("trackedTxs.json")
+}
+
+#[derive(Debug, From, Error)]
+pub enum TxTrackerError {
+    #[error("tx tracker file IO error: {0}")]
+    Io(std::io::Error),
+    #[error("tx tracker file JSON error: {0}")]
+    Json(serde_json::Error),
+    #[error("node error: {0}")]
+    Node(NodeError),
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TxTrackerConfig {
+    /// Confirmations after which a tx is no longer tracked
+    pub confirmations: u32,
+    /// How often a tx dropped from the mempool is resubmitted before giving up on it
+    pub max_rebroadcasts: u32,
+}
+
+impl Default for TxTrackerConfig {
+    fn default() -> Self {
+        TxTrackerConfig {
+            confirmations: 3,
+            max_rebroadcasts: 3,
+        }
+    }
+}
+
+/// How long a dropped tx is still resubmitted, afterwards the main loop rebuilds the action
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RebroadcastWindow {
+    /// A datapoint tx is resubmitted only while its datapoint is younger than this (taken from
+    /// the first submission)
+    pub max_datapoint_age: Duration,
+    /// A refresh tx is resubmitted only while the height is at most this many blocks past the
+    /// creation height of its outputs, the `buffer_length` of the refresh contract
+    pub refresh_buffer_blocks: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    Refresh,
+    PublishDatapoint,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TxStatus {
+    /// In the mempool
+    Unconfirmed,
+    /// In a block but not yet settled
+    Confirmed { confirmations: u32 },
+    /// Reached the required confirmations
+    Settled,
+    /// Inputs were spent by another tx
+    Invalidated,
+    /// Left the mempool and could not be resubmitted
+    Dropped,
+}
+
+impl TxStatus {
+    pub fn is_finished(&self) -> bool {
+        match self {
+            TxStatus::Unconfirmed | TxStatus::Confirmed { .. } => false,
+            TxStatus::Settled | TxStatus::Invalidated | TxStatus::Dropped => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackedTx {
+    pub tx_id: String,
+    pub kind: ActionKind,
+    pub input_box_ids: Vec<String>,
+    /// Unix timestamp of the first submission
+    pub submitted_at: i64,
+    pub status: TxStatus,
+    pub rebroadcasts: u32,
+    /// Kept for rebroadcasting until the tx is finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_tx: Option<Transaction>,
+}
+
+/// Node queries needed to follow a submitted tx
+pub trait TxStatusSource {
+    /// Confirmations of a tx included in a block, `None` if it is not in a block
+    fn get_tx_confirmations(&self, tx_id: &str) -> Result<Option<u32>, NodeError>;
+    fn is_tx_unconfirmed(&self, tx_id: &str) -> Result<bool, NodeError>;
+    fn is_box_unspent(&self, box_id: &str) -> Result<bool, NodeError>;
+    fn resubmit_tx(&self, tx: &Transaction) -> Result<String, NodeError>;
+}
+
+#[derive(Debug)]
+pub struct TxTracker {
+    path: PathBuf,
+    txs: Mutex<Vec<TrackedTx>>,
+}
+
+impl TxTracker {
+    pub fn load(path: PathBuf) -> Result<Self, TxTrackerError> {
+        let txs = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+        Ok(TxTracker {
+            path,
+            txs: Mutex::new(txs),
+        })
+    }
+
+    /// Starts with no tracked txs if the file cannot be read
+    pub fn load_or_empty(path: PathBuf) -> Self {
+        Self::load(path.clone()).unwrap_or_else(|e| {
+            log::error!("Failed to load tracked txs, starting with none: {}", e);
+            TxTracker {
+                path,
+                txs: Mutex::new(Vec::new()),
+            }
+        })
+    }
+
+    fn save(&self, txs: &[TrackedTx]) -> Result<(), TxTrackerError> {
+        // replace the file in one go so that a crash cannot leave a truncated file behind
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(txs)?)?;
+        std::fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<TrackedTx> {
+        self.txs.lock().unwrap().clone()
+    }
+
+    pub fn record(
+        &self,
+        tx_id: String,
+        kind: ActionKind,
+        signed_tx: Transaction,
+    ) -> Result<(), TxTrackerError> {
+        let input_box_ids = signed_tx
+            .inputs
+            .iter()
+            .map(|input| String::from(input.box_id))
+            .collect();
+        let mut txs = self.txs.lock().unwrap();
+        txs.push(TrackedTx {
+            tx_id,
+            kind,
+            input_box_ids,
+            submitted_at: unix_timestamp_now(),
+            status: TxStatus::Unconfirmed,
+            rebroadcasts: 0,
+            signed_tx: Some(signed_tx),
+        });
+        self.save(&txs)
+    }
+
+    /// Refreshes the status of the unfinished txs, resubmitting the ones that dropped out of the
+    /// mempool and are still within `window` at `height`
+    pub fn update(
+        &self,
+        node: &dyn TxStatusSource,
+        config: &TxTrackerConfig,
+        window: &RebroadcastWindow,
+        height: u32,
+    ) -> Result<(), TxTrackerError> {
+        let mut txs = self.txs.lock().unwrap();
+        let mut res = Ok(());
+        for tx in txs.iter_mut().filter(|tx| !tx.status.is_finished()) {
+            if let Err(e) = update_tx(tx, node, config, window, height) {
+                // keep the statuses gathered so far
+                res = Err(e);
+                break;
+            }
+        }
+        prune_finished(&mut txs);
+        self.save(&txs)?;
+        res
+    }
+}
+
+fn update_tx(
+    tx: &mut TrackedTx,
+    node: &dyn TxStatusSource,
+    config: &TxTrackerConfig,
+    window: &RebroadcastWindow,
+    height: u32,
+) -> Result<(), TxTrackerError> {
+    match node.get_tx_confirmations(&tx.tx_id)? {
+        Some(confirmations) if confirmations >= config.confirmations => {
+            log::info!("{:?} tx {} settled", tx.kind, tx.tx_id);
+            finish(tx, TxStatus::Settled);
+        }
+        Some(confirmations) => tx.status = TxStatus::Confirmed { confirmations },
+        None if node.is_tx_unconfirmed(&tx.tx_id)? => tx.status = TxStatus::Unconfirmed,
+        None => {
+            let mut spent_box_id = None;
+            for box_id in &tx.input_box_ids {
+                if !node.is_box_unspent(box_id)? {
+                    spent_box_id = Some(box_id.clone());
+                    break;
+                }
+            }
+            match spent_box_id {
+                Some(box_id) => {
+                    log::error!(
+                        "{:?} tx {} was invalidated, input box {} was spent by another tx",
+                        tx.kind,
+                        tx.tx_id,
+                        box_id
+                    );
+                    finish(tx, TxStatus::Invalidated);
+                }
+                None => rebroadcast(tx, node, config, window, height),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rebroadcast(
+    tx: &mut TrackedTx,
+    node: &dyn TxStatusSource,
+    config: &TxTrackerConfig,
+    window: &RebroadcastWindow,
+    height: u32,
+) {
+    let signed_tx = match &tx.signed_tx {
+        Some(signed_tx) if tx.rebroadcasts < config.max_rebroadcasts => signed_tx,
+        Some(_) | None => {
+            log::error!(
+                "{:?} tx {} dropped from the mempool after {} rebroadcasts, giving up",
+                tx.kind,
+                tx.tx_id,
+                tx.rebroadcasts
+            );
+            finish(tx, TxStatus::Dropped);
+            return;
+        }
+    };
+    if let Some(reason) = expired(tx.kind, tx.submitted_at, signed_tx, window, height) {
+        log::warn!(
+            "{:?} tx {} dropped from the mempool, not resubmitting: {}",
+            tx.kind,
+            tx.tx_id,
+            reason
+        );
+        finish(tx, TxStatus::Dropped);
+        return;
+    }
+    match node.resubmit_tx(signed_tx) {
+        Ok(_) => {
+            tx.rebroadcasts += 1;
+            tx.status = TxStatus::Unconfirmed;
+            log::warn!(
+                "{:?} tx {} dropped from the mempool, resubmitted ({}/{})",
+                tx.kind,
+                tx.tx_id,
+                tx.rebroadcasts,
+                config.max_rebroadcasts
+            );
+        }
+        Err(e) => {
+            log::error!(
+                "{:?} tx {} dropped from the mempool and resubmitting failed: {}",
+                tx.kind,
+                tx.tx_id,
+                e
+            );
+            finish(tx, TxStatus::Dropped);
+        }
+    }
+}
+
+/// Why a dropped tx would no longer be accepted, `None` if it can still be resubmitted
+fn expired(
+    kind: ActionKind,
+    submitted_at: i64,
+    signed_tx: &Transaction,
+    window: &RebroadcastWindow,
+    height: u32,
+) -> Option<String> {
+    match kind {
+        ActionKind::PublishDatapoint => {
+            let age = unix_timestamp_now().saturating_sub(submitted_at);
+            let max_age = window.max_datapoint_age.as_secs() as i64;
+            (age > max_age).then(|| {
+                format!("its datapoint is {age}s old, older than the max age of {max_age}s")
+            })
+        }
+        ActionKind::Refresh => {
+            let built_height = signed_tx.outputs.first().creation_height;
+            let last_height = built_height.saturating_add(window.refresh_buffer_blocks);
+            (height > last_height).then(|| {
+                format!(
+                    "built at height {built_height}, the refresh contract accepts it until height {last_height}"
+                )
+            })
+        }
+    }
+}
+
+fn finish(tx: &mut TrackedTx, status: TxStatus) {
+    tx.status = status;
+    tx.signed_tx = None;
+}
+
+/// Keeps all unfinished txs and the latest `KEPT_FINISHED_TXS` finished ones
+fn prune_finished(txs: &mut Vec<TrackedTx>) {
+    let mut finished_to_drop = txs
+        .iter()
+        .filter(|tx| tx.status.is_finished())
+        .count()
+        .saturating_sub(KEPT_FINISHED_TXS);
+    txs.retain(|tx| {
+        if finished_to_drop > 0 && tx.status.is_finished() {
+            finished_to_drop -= 1;
+            false
+        } else {
+            true
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct NodeMock {
+        confirmations: HashMap<String, u32>,
+        mempool: Vec<String>,
+        spent_boxes: Vec<String>,
+        /// Accepts resubmitted txs and records their ids
+        resubmitted: Option<RefCell<Vec<String>>>,
+    }
+
+    impl TxStatusSource for NodeMock {
+        fn get_tx_confirmations(&self, tx_id: &str) -> Result<Option<u32>, NodeError> {
+            Ok(self.confirmations.get(tx_id).copied())
+        }
+
+        fn is_tx_unconfirmed(&self, tx_id: &str) -> Result<bool, NodeError> {
+            Ok(self.mempool.iter().any(|id| id == tx_id))
+        }
+
+        fn is_box_unspent(&self, box_id: &str) -> Result<bool, NodeError> {
+            Ok(!self.spent_boxes.iter().any(|id| id == box_id))
+        }
+
+        fn resubmit_tx(&self, tx: &Transaction) -> Result<String, NodeError> {
+            match &self.resubmitted {
+                Some(resubmitted) => {
+                    resubmitted.borrow_mut().push(tx.id().into());
+                    Ok(tx.id().into())
+                }
+                None => Err(NodeError::BadRequest("no signed tx in tests".to_string())),
+            }
+        }
+    }
+
+    const WINDOW: RebroadcastWindow = RebroadcastWindow {
+        max_datapoint_age: Duration::from_secs(60),
+        refresh_buffer_blocks: 4,
+    };
+
+    fn make_tracker(name: &str, txs: Vec<TrackedTx>) -> TxTracker {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-tracked-txs-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let tracker = TxTracker::load(path).unwrap();
+        *tracker.txs.lock().unwrap() = txs;
+        tracker
+    }
+
+    fn tracked(tx_id: &str, input_box_ids: &[&str]) -> TrackedTx {
+        TrackedTx {
+            tx_id: tx_id.to_string(),
+            kind: ActionKind::PublishDatapoint,
+            input_box_ids: input_box_ids.iter().map(|id| id.to_string()).collect(),
+            submitted_at: 1000,
+            status: TxStatus::Unconfirmed,
+            rebroadcasts: 0,
+            signed_tx: None,
+        }
+    }
+
+    fn statuses(tracker: &TxTracker) -> Vec<TxStatus> {
+        tracker.list().into_iter().map(|tx| tx.status).collect()
+    }
+
+    #[test]
+    fn test_update_statuses() {
+        let tracker = make_tracker(
+            "statuses",
+            vec![
+                tracked("settled", &["a"]),
+                tracked("confirmed", &["b"]),
+                tracked("unconfirmed", &["c"]),
+                tracked("invalidated", &["d", "e"]),
+                tracked("dropped", &["f"]),
+            ],
+        );
+        let node = NodeMock {
+            confirmations: vec![("settled".to_string(), 3), ("confirmed".to_string(), 1)]
+                .into_iter()
+                .collect(),
+            mempool: vec!["unconfirmed".to_string()],
+            spent_boxes: vec!["e".to_string()],
+        };
+        tracker
+            .update(&node, &TxTrackerConfig::default(), &WINDOW, 100)
+            .unwrap();
+        assert_eq!(
+            statuses(&tracker),
+            vec![
+                TxStatus::Settled,
+                TxStatus::Confirmed { confirmations: 1 },
+                TxStatus::Unconfirmed,
+                TxStatus::Invalidated,
+                // no signed tx to resubmit
+                TxStatus::Dropped,
+            ]
+        );
+        // finished txs are not looked at again
+        tracker
+            .update(
+                &NodeMock::default(),
+                &TxTrackerConfig::default(),
+                &WINDOW,
+                100,
+            )
+            .unwrap();
+        let statuses = statuses(&tracker);
+        assert_eq!(statuses[0], TxStatus::Settled);
+        assert_eq!(statuses[2], TxStatus::Dropped);
+        assert_eq!(statuses[3], TxStatus::Invalidated);
+    }
+
+    #[test]
+    fn test_persisted() {
+        let tracker = make_tracker("persisted", vec![tracked("tx", &["a"])]);
+        let node = NodeMock {
+            confirmations: vec![("tx".to_string(), 1)].into_iter().collect(),
+            ..Default::default()
+        };
+        tracker
+            .update(&node, &TxTrackerConfig::default(), &WINDOW, 100)
+            .unwrap();
+        let reloaded = TxTracker::load(tracker.path.clone()).unwrap();
+        assert_eq!(reloaded.list(), tracker.list());
+    }
+
+    #[test]
+    fn test_rebroadcast_only_within_window() {
+        let signed_tx = force_any_val::<Transaction>();
+        let dropped = |tx_id: &str, kind: ActionKind, submitted_at: i64| TrackedTx {
+            kind,
+            submitted_at,
+            signed_tx: Some(signed_tx.clone()),
+            ..tracked(tx_id, &["a"])
+        };
+        let now = unix_timestamp_now();
+        let tracker = make_tracker(
+            "rebroadcast-window",
+            vec![
+                dropped("fresh datapoint", ActionKind::PublishDatapoint, now - 30),
+                dropped("stale datapoint", ActionKind::PublishDatapoint, now - 90),
+                dropped("refresh", ActionKind::Refresh, now),
+            ],
+        );
+        let node = NodeMock {
+            resubmitted: Some(RefCell::new(Vec::new())),
+            ..Default::default()
+        };
+        let built_height = signed_tx.outputs.first().creation_height;
+        tracker
+            .update(
+                &node,
+                &TxTrackerConfig::default(),
+                &WINDOW,
+                built_height + WINDOW.refresh_buffer_blocks,
+            )
+            .unwrap();
+        assert_eq!(
+            statuses(&tracker),
+            vec![
+                TxStatus::Unconfirmed,
+                TxStatus::Dropped,
+                TxStatus::Unconfirmed
+            ]
+        );
+        assert_eq!(node.resubmitted.as_ref().unwrap().borrow().len(), 2);
+        // past the buffer of the refresh contract
+        tracker
+            .update(
+                &node,
+                &TxTrackerConfig::default(),
+                &WINDOW,
+                built_height + WINDOW.refresh_buffer_blocks + 1,
+            )
+            .unwrap();
+        assert_eq!(
+            statuses(&tracker),
+            vec![TxStatus::Unconfirmed, TxStatus::Dropped, TxStatus::Dropped]
+        );
+        assert_eq!(node.resubmitted.as_ref().unwrap().borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_prune_finished() {
+        let mut txs = (0..KEPT_FINISHED_TXS + 5)
+            .map(|i| TrackedTx {
+                status: TxStatus::Settled,
+                ..tracked(&i.to_string(), &[])
+            })
+            .collect::<Vec<_>>();
+        txs.insert(0, tracked("unconfirmed", &[]));
+        prune_finished(&mut txs);
+        assert_eq!(txs.len(), KEPT_FINISHED_TXS + 1);
+        assert_eq!(txs[0].tx_id, "unconfirmed");
+        assert_eq!(txs[1].tx_id, "5");
+    }
+}
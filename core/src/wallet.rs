@@ -1,20 +1,47 @@
+use std::collections::HashSet;
+
 use derive_more::From;
+use ergo_lib::ergotree_ir::chain::address::{
+    Address, AddressEncoder, AddressEncoderError, NetworkAddress,
+};
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_node_interface::node_interface::NodeError;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::node_interface;
+use crate::mempool::MempoolSource;
+use crate::node_interface::{self, new_node_interface};
+use crate::oracle_config::ORACLE_CONFIG;
 
 #[derive(Debug, Error, From)]
 pub enum WalletDataError {
     #[error("node error: {0}")]
     NodeError(NodeError),
+    #[error("address error: {0}")]
+    AddressEncoder(AddressEncoderError),
+    #[error("wallet address {0} is not a P2PK address")]
+    NotP2Pk(String),
 }
 
 pub trait WalletDataSource {
     fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError>;
 }
 
+/// Where the boxes paying for the tx fees come from
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WalletSourceConfig {
+    /// Node wallet
+    #[default]
+    Node,
+    /// Unspent boxes of a P2PK address (the oracle address if not set), looked up with the node's
+    /// blockchain API. Change goes back to the same address.
+    Address {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+    },
+}
+
 pub struct WalletData {}
 
 impl WalletData {
@@ -25,6 +52,129 @@ impl WalletData {
 
 impl WalletDataSource for WalletData {
     fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
-        node_interface::get_unspent_wallet_boxes().map_err(Into::into)
+        match wallet_address()? {
+            Some(address) => AddressWallet {
+                address,
+                source: new_node_interface(),
+            }
+            .get_unspent_wallet_boxes(),
+            None => node_interface::get_unspent_wallet_boxes().map_err(Into::into),
+        }
+    }
+}
+
+/// Base58 change address: the tracked address, or the change address of the node wallet
+pub fn change_address() -> Result<Option<String>, WalletDataError> {
+    match wallet_address()? {
+        Some(address) => Ok(Some(address.to_base58())),
+        None => Ok(node_interface::get_wallet_status()?.change_address),
+    }
+}
+
+/// Address tracked instead of the node wallet, if configured
+fn wallet_address() -> Result<Option<NetworkAddress>, WalletDataError> {
+    tracked_address(
+        &ORACLE_CONFIG.wallet_source.clone().unwrap_or_default(),
+        &ORACLE_CONFIG.oracle_address,
+    )
+}
+
+/// Address tracked with the `address` wallet source, `None` for the node wallet
+pub fn tracked_address(
+    wallet_source: &WalletSourceConfig,
+    oracle_address: &NetworkAddress,
+) -> Result<Option<NetworkAddress>, WalletDataError> {
+    match wallet_source {
+        WalletSourceConfig::Node => Ok(None),
+        WalletSourceConfig::Address { address } => {
+            let address = match address {
+                Some(address) => AddressEncoder::unchecked_parse_network_address_from_str(address)?,
+                None => oracle_address.clone(),
+            };
+            match address.address() {
+                Address::P2Pk(_) => Ok(Some(address)),
+                Address::P2SH(_) | Address::P2S(_) => {
+                    Err(WalletDataError::NotP2Pk(address.to_base58()))
+                }
+            }
+        }
+    }
+}
+
+pub trait AddressBoxSource {
+    /// Unspent boxes of the address in the UTXO set
+    fn get_unspent_address_boxes(&self, address: &str) -> Result<Vec<ErgoBox>, NodeError>;
+}
+
+/// Unspent boxes of an address, without the ones already spent by unconfirmed txs
+pub struct AddressWallet<S: AddressBoxSource + MempoolSource> {
+    pub address: NetworkAddress,
+    pub source: S,
+}
+
+impl<S: AddressBoxSource + MempoolSource> WalletDataSource for AddressWallet<S> {
+    fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
+        let boxes = self
+            .source
+            .get_unspent_address_boxes(&self.address.to_base58())?;
+        let spent_in_mempool = self
+            .source
+            .get_unconfirmed_txs()?
+            .into_iter()
+            .flat_map(|tx| tx.input_box_ids)
+            .collect::<HashSet<String>>();
+        Ok(boxes
+            .into_iter()
+            .filter(|b| !spent_in_mempool.contains(&String::from(b.box_id())))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+    use ergo_lib::ergotree_ir::sigma_protocol::private_input::DlogProverInput;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::mempool::UnconfirmedTx;
+
+    struct NodeMock {
+        boxes: Vec<ErgoBox>,
+        unconfirmed: Vec<UnconfirmedTx>,
+    }
+
+    impl AddressBoxSource for NodeMock {
+        fn get_unspent_address_boxes(&self, _address: &str) -> Result<Vec<ErgoBox>, NodeError> {
+            Ok(self.boxes.clone())
+        }
+    }
+
+    impl MempoolSource for NodeMock {
+        fn get_unconfirmed_txs(&self) -> Result<Vec<UnconfirmedTx>, NodeError> {
+            Ok(self.unconfirmed.clone())
+        }
+    }
+
+    #[test]
+    fn test_skips_boxes_spent_in_mempool() {
+        let boxes = force_any_val::<[ErgoBox; 3]>().to_vec();
+        let wallet = AddressWallet {
+            address: NetworkAddress::new(
+                NetworkPrefix::Mainnet,
+                &Address::P2Pk(force_any_val::<DlogProverInput>().public_image()),
+            ),
+            source: NodeMock {
+                boxes: boxes.clone(),
+                unconfirmed: vec![UnconfirmedTx {
+                    tx_id: "spending".to_string(),
+                    input_box_ids: vec![String::from(boxes[1].box_id())],
+                }],
+            },
+        };
+        assert_eq!(
+            wallet.get_unspent_wallet_boxes().unwrap(),
+            vec![boxes[0].clone(), boxes[2].clone()]
+        );
     }
 }